@@ -0,0 +1,42 @@
+//! Encoding Benchmarks
+//!
+//! Compares [`MdnsMessage::encode()`] with [`MdnsMessage::to_bytes()`] for 1000 announce messages
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use dns_sd2::{message::MdnsMessage, service::ServiceBuilder};
+
+fn announce_messages() -> Vec<MdnsMessage> {
+    (0..1000)
+        .map(|i| {
+            let service = ServiceBuilder::default()
+                .host(&format!("TestMachine{}", i))
+                .service("_test")
+                .protocol("_tcp")
+                .port(53000)
+                .txt("version", "1")
+                .build()
+                .unwrap();
+
+            MdnsMessage::announce(&service)
+        })
+        .collect()
+}
+
+fn encode(c: &mut Criterion) {
+    let messages = announce_messages();
+
+    let mut group = c.benchmark_group("announce");
+    group.throughput(Throughput::Elements(messages.len() as u64));
+
+    group.bench_function("to_bytes", |b| {
+        b.iter(|| messages.iter().map(|m| m.to_bytes().len()).sum::<usize>())
+    });
+    group.bench_function("encode", |b| {
+        b.iter(|| messages.iter().map(|m| m.encode().len()).sum::<usize>())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -1,4 +1,4 @@
-use dns_sd2::*;
+use dns_sd2::prelude::*;
 use futures::{pin_mut, StreamExt};
 use log::debug;
 
@@ -0,0 +1,16 @@
+#![no_main]
+
+use dns_sd2::{message::MdnsMessage, MdnsError};
+use libfuzzer_sys::fuzz_target;
+
+//Parsing arbitrary packets from the network may never panic or loop,
+//it either returns a message or an invalid message error
+fuzz_target!(|data: &[u8]| {
+    match MdnsMessage::parse(data) {
+        Ok(message) => {
+            //A parsed message can be serialized again
+            let _ = message.to_bytes();
+        }
+        Err(e) => assert!(matches!(e, MdnsError::InvalidMessage {})),
+    }
+});
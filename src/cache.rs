@@ -0,0 +1,174 @@
+use std::time::{Duration, SystemTime};
+
+use tokio::time::Instant;
+
+use crate::record::ResourceRecord;
+
+/// A cached [`ResourceRecord`] along with the time it was cached, see [`DnsSd2::export_cache()`](crate::DnsSd2::export_cache)
+///
+/// Allows persisting the cache over restarts, e.g. as JSON with the `serde` feature
+///
+/// The record holds its original TTL, the remaining TTL follows from `cached_at`
+///
+/// With the `serde` feature the record is serialized with its RDATA as a base64 string, see [`ResourceRecord`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CacheEntry {
+    pub record: ResourceRecord,
+    pub cached_at: SystemTime,
+}
+
+impl CacheEntry {
+    /// Entry for a record in the cache, dated back by the time its TTL has counted down
+    pub fn new(record: &ResourceRecord) -> CacheEntry {
+        let age = Duration::from_secs(record.original_ttl.saturating_sub(record.ttl) as u64);
+
+        let mut record = record.clone();
+        record.ttl = record.original_ttl;
+
+        CacheEntry {
+            record,
+            cached_at: SystemTime::now()
+                .checked_sub(age)
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+        }
+    }
+
+    /// The record with its remaining TTL, `None` if it has expired since it was cached
+    pub fn into_record(self) -> Option<ResourceRecord> {
+        let elapsed = SystemTime::now()
+            .duration_since(self.cached_at)
+            .unwrap_or_default();
+
+        if elapsed >= Duration::from_secs(self.record.original_ttl as u64) {
+            return None;
+        }
+
+        let mut record = self.record;
+        record.ttl = record.original_ttl - elapsed.as_secs() as u32;
+        record.received_at = Some(
+            Instant::now()
+                .checked_sub(elapsed)
+                .unwrap_or_else(Instant::now),
+        );
+
+        Some(record)
+    }
+}
+
+/// Whether `a` and `b` are the same cached record, with the same name (case insensitive), type and RDATA
+///
+/// The TTL and cache flush bit may differ, a record received again replaces the cached one
+pub fn same_record(a: &ResourceRecord, b: &ResourceRecord) -> bool {
+    a.record_type == b.record_type && a.name == b.name && a.rdata_bytes() == b.rdata_bytes()
+}
+
+/// Merge the records of `other` into `records`, e.g. the caches of the clients of multiple interfaces
+///
+/// Of the same record in both caches the one with the most TTL left is kept, see [`same_record()`]
+pub fn merge(records: &mut Vec<ResourceRecord>, other: Vec<ResourceRecord>) {
+    for record in other {
+        match records.iter_mut().find(|r| same_record(r, &record)) {
+            Some(cached) if cached.ttl < record.ttl => *cached = record,
+            Some(_) => {}
+            None => records.push(record),
+        }
+    }
+}
+
+/// Records of `records` which are not in `other`, e.g. the records only seen on one interface
+///
+/// See [`same_record()`]
+pub fn difference(records: &[ResourceRecord], other: &[ResourceRecord]) -> Vec<ResourceRecord> {
+    records
+        .iter()
+        .filter(|r| !other.iter().any(|o| same_record(r, o)))
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn test_cache_entry() {
+    use crate::name::Name;
+
+    let mut record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    record.original_ttl = 120;
+    record.ttl = 100;
+
+    let entry = CacheEntry::new(&record);
+
+    assert_eq!(entry.record.ttl, 120);
+    assert_eq!(entry.clone().into_record().unwrap().ttl, 100);
+
+    //Simulate a restart 80 seconds later, the record has 20 seconds left
+    let restarted = |secs| CacheEntry {
+        cached_at: entry.cached_at - Duration::from_secs(secs),
+        ..entry.clone()
+    };
+
+    let imported = restarted(80).into_record().unwrap();
+    assert_eq!(imported.ttl, 20);
+    assert!(imported.received_at.is_some());
+
+    //Expired while not running
+    assert!(restarted(100).into_record().is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_cache_entry_serde() {
+    use crate::name::Name;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let entry = CacheEntry::new(&ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    ));
+
+    //The RDATA of the record is a base64 string
+    let json = serde_json::to_value(&entry).unwrap();
+    let rdata = json["record"]["rdata"].as_str().unwrap();
+    assert_eq!(STANDARD.decode(rdata).unwrap(), vec![192, 168, 1, 2]);
+
+    let parsed: CacheEntry = serde_json::from_value(json).unwrap();
+
+    assert_eq!(parsed.cached_at, entry.cached_at);
+    assert_eq!(parsed.record, entry.record);
+}
+
+#[test]
+fn test_merge_difference() {
+    use crate::name::Name;
+
+    let a_record = |host: &str, ttl| {
+        ResourceRecord::create_a_record(
+            Name::new(format!("{}.local", host)).unwrap(),
+            [192, 168, 1, 2],
+        )
+        .with_ttl(ttl)
+    };
+
+    let mut first = vec![a_record("TestMachine", 30), a_record("FirstMachine", 60)];
+    let second = vec![a_record("testmachine", 50), a_record("SecondMachine", 60)];
+
+    assert_eq!(
+        difference(&first, &second),
+        vec![a_record("FirstMachine", 60)]
+    );
+    assert_eq!(
+        difference(&second, &first),
+        vec![a_record("SecondMachine", 60)]
+    );
+
+    //The record in both caches keeps the most TTL left
+    merge(&mut first, second.clone());
+    assert_eq!(first.len(), 3);
+    assert_eq!(first[0].ttl, 50);
+    assert!(difference(&second, &first).is_empty());
+
+    merge(&mut first, vec![a_record("TestMachine", 10)]);
+    assert_eq!(first[0].ttl, 50);
+}
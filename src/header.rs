@@ -24,7 +24,7 @@ use crate::MdnsError;
 ///```
 ///## RFC Reference
 ///- [1035 Section 4.1.1 - Header Format](https://www.rfc-editor.org/rfc/rfc1035#section-4.1.1)
-#[derive(PackedStruct, Default, Clone, Debug)]
+#[derive(PackedStruct, Default, Clone, Debug, PartialEq)]
 #[packed_struct(endian = "msb", bit_numbering = "msb0")]
 pub struct Header {
     /// ID
@@ -121,20 +121,20 @@ impl Header {
         self.pack().expect("Failed to pack Header").into()
     }
     
-    pub fn from_bytes(bytes: &Vec<u8>) -> Result<Self, MdnsError>{
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MdnsError>{
         //Header is 12 bytes in length
         if bytes.len() < 12 {
         error!("Message Header is not at least 12 bytes long");
         return Err(MdnsError::InvalidMessage{})     
         }
         
-        //We need to convert Vec into matching byte array to unpack
-        let header_bytes: &[u8;12] = &bytes[0..13].try_into().expect("Should be valid");
+        //We need to convert the slice into matching byte array to unpack
+        let header_bytes: &[u8;12] = &bytes[0..12].try_into().expect("Should be valid");
         if let Ok(header) = Header::unpack(header_bytes){
-            return Ok(header)  
+            Ok(header)
         }
         else{
-            return Err(MdnsError::InvalidMessage{})     
+            Err(MdnsError::InvalidMessage{})
         }
     }
 }
@@ -164,6 +164,20 @@ impl Default for OpCode {
     }
 }
 
+impl TryFrom<u8> for OpCode {
+    type Error = MdnsError;
+
+    /// Convert the four bit OPCODE of a header into an [`OpCode`]
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OpCode::StandardQuery),
+            1 => Ok(OpCode::InverseQuery),
+            2 => Ok(OpCode::ServerStatusRequest),
+            _ => Err(MdnsError::InvalidMessage {}),
+        }
+    }
+}
+
 ///This 4 bit field is set as part of responses. The values have the following interpretation:
 ///          - 0   No error condition
 ///          - 1   Format error - The name server was unable to interpret the query.
@@ -197,6 +211,23 @@ impl Default for RCode {
     }
 }
 
+impl TryFrom<u8> for RCode {
+    type Error = MdnsError;
+
+    /// Convert the four bit RCODE of a header into an [`RCode`]
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RCode::NoError),
+            1 => Ok(RCode::FormatError),
+            2 => Ok(RCode::ServerFailure),
+            3 => Ok(RCode::NameError),
+            4 => Ok(RCode::NotImplemented),
+            5 => Ok(RCode::Refused),
+            _ => Err(MdnsError::InvalidMessage {}),
+        }
+    }
+}
+
 impl Header {
     /// New Header
     ///
@@ -207,6 +238,35 @@ impl Header {
     }
 }
 
+/// Packs the 12 bytes of a `Header` into the lower 96 bits of a `u128`, most significant byte first
+///
+/// `0x0000_8400_0001_0003_0000_0000` is a response with the AA bit set, 1 question and 3 answers
+impl From<Header> for u128 {
+    fn from(header: Header) -> Self {
+        header
+            .to_bytes()
+            .iter()
+            .fold(0, |packed, byte| (packed << 8) | *byte as u128)
+    }
+}
+
+/// Unpacks a `Header` from the lower 96 bits of a `u128`
+///
+/// Returns [`MdnsError::InvalidMessage`] if any of the upper 32 bits are set
+/// or the bits do not contain a valid OPCODE or RCODE
+impl TryFrom<u128> for Header {
+    type Error = MdnsError;
+
+    fn try_from(packed: u128) -> Result<Self, Self::Error> {
+        if packed >> 96 != 0 {
+            error!("Packed Header is larger than 12 bytes");
+            return Err(MdnsError::InvalidMessage {});
+        }
+
+        Header::from_bytes(&packed.to_be_bytes()[4..])
+    }
+}
+
 #[test]
 fn test_header() {
     let header = Header::new();
@@ -217,3 +277,82 @@ fn test_header() {
     //Test that the unpacked Header is 12 bytes in length
     assert!(header.pack().unwrap().len() == 12);
 }
+
+#[test]
+fn test_header_u128() {
+    //Response with the AA bit set, 1 question and 3 answers
+    let header = Header::try_from(0x0000_8400_0001_0003_0000_0000_u128).unwrap();
+    assert!(header.qr);
+    assert!(header.aa);
+    assert_eq!(header.qdcount, 1);
+    assert_eq!(header.ancount, 3);
+    assert_eq!(u128::from(header), 0x0000_8400_0001_0003_0000_0000);
+
+    //Round trip through u128
+    let headers = [
+        Header::new(),
+        Header {
+            id: 0xBEEF,
+            qr: true,
+            aa: true,
+            tc: true,
+            rcode: RCode::Refused,
+            qdcount: 1,
+            ancount: 2,
+            nscount: 3,
+            arcount: 4,
+            ..Default::default()
+        },
+        Header {
+            opcode: OpCode::ServerStatusRequest,
+            rd: true,
+            ra: true,
+            arcount: u16::MAX,
+            ..Default::default()
+        },
+    ];
+
+    for header in headers {
+        let packed = u128::from(header.clone());
+        assert_eq!(packed.to_be_bytes()[4..], header.to_bytes());
+        assert_eq!(Header::try_from(packed).unwrap(), header);
+    }
+
+    //More than 96 bits or an invalid OPCODE do not unpack
+    assert!(Header::try_from(1_u128 << 96).is_err());
+    assert!(Header::try_from(0x0000_7800_0000_0000_0000_0000_u128).is_err());
+}
+
+#[test]
+fn test_opcode_rcode() {
+    let opcodes = [
+        OpCode::StandardQuery,
+        OpCode::InverseQuery,
+        OpCode::ServerStatusRequest,
+    ];
+
+    for opcode in opcodes {
+        assert_eq!(OpCode::try_from(opcode.to_primitive()).unwrap(), opcode);
+    }
+
+    let rcodes = [
+        RCode::NoError,
+        RCode::FormatError,
+        RCode::ServerFailure,
+        RCode::NameError,
+        RCode::NotImplemented,
+        RCode::Refused,
+    ];
+
+    for rcode in rcodes {
+        assert_eq!(RCode::try_from(rcode.to_primitive()).unwrap(), rcode);
+    }
+
+    //Reserved codes do not convert
+    for code in [3, 15, u8::MAX] {
+        assert!(OpCode::try_from(code).is_err());
+    }
+    for code in [6, 15, u8::MAX] {
+        assert!(RCode::try_from(code).is_err());
+    }
+}
@@ -1,44 +1,96 @@
+//Handlers match on Events with catch-all arms so new Events can be added to the chain
+#![allow(clippy::single_match, clippy::collapsible_match)]
+
 //Logging
 extern crate pretty_env_logger;
 #[macro_use]
 extern crate log;
 
-use async_stream::try_stream;
-use futures::{executor::block_on, stream::FuturesUnordered, Stream, StreamExt};
+use async_stream::{stream, try_stream};
+use cache::CacheEntry;
+use futures::{executor::block_on, pin_mut, stream::select_all, Stream, StreamExt, TryStreamExt};
 use message::MdnsMessage;
-use protocols::handler::{Event, Handler};
+use protocols::handler::{chain, Event, Handler, RecordCallback};
+use question::QType;
+use rand::{thread_rng, Rng};
 use record::ResourceRecord;
-use service::{Query, Service, ServiceState};
-use std::{io, time::Duration};
+use service::{
+    BrowseFilter, Query, RegistrationOptions, ResolvedService, Service, ServiceBuilder,
+    ServiceEvent, ServiceState,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+    time::{Duration, SystemTime},
+};
 use thiserror::Error;
+use timeout::TimeoutMap;
 use tokio::{
+    runtime::RuntimeFlavor,
     select,
-    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-    time::interval,
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    },
+    task::JoinHandle,
+    time::{interval_at, timeout_at, Instant},
 };
 use tokio_util::{codec::BytesCodec, udp::UdpFramed};
 
 use crate::{
     protocols::{
-        announce::AnnouncementHandler, goodbye_packet::GoodbyeHandler, probe::ProbeHandler,
+        announce::AnnouncementHandler,
+        browse::BrowseHandler,
+        cache_flush::CacheFlushHandler,
+        continuous_query::ContinuousQueryHandler,
+        goodbye_packet::GoodbyeHandler,
+        network_change::NetworkChangeHandler,
+        probe::ProbeHandler,
+        query_handler::QueryHandler,
+        response_handler::{removed_instances, txt_changes, ResponseHandler},
+        sleep_proxy::SleepProxyHandler,
+        update_ttl::{RecordExpiryCallback, UpdateTTLHandler},
+    },
+    utility::{
+        create_framed_socket, create_socket, enumerate_network_interfaces, get_hostname,
+        parse_hosts_file, send_message_to, source_address_check, MdnsSocket, SendTarget,
+        SocketConfig, SocketFactory,
     },
-    utility::{create_socket, send_message},
 };
 
 const IP_ANY: [u8; 4] = [0, 0, 0, 0];
 
+/// Default time [`DnsSd2::lookup()`] waits for answers
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// TXT record changes kept for slow subscribers of [`DnsSd2::with_txt_updated_stream()`]
+const TXT_UPDATE_CAPACITY: usize = 16;
+
+pub mod cache;
 pub mod header;
 pub mod message;
 pub mod name;
+pub mod prelude;
 pub mod protocols;
 pub mod question;
 pub mod record;
 pub mod records;
+pub mod registration;
 pub mod service;
+#[cfg(test)]
+mod test_utils;
+pub mod timeout;
 pub mod utility;
 
+pub use records::{AAAARecord, ARecord, CNAMERecord, PTRRecord, SRVRecord, TXTRecord};
+
 ///Mdns Error Types
+///
+/// Non-exhaustive, handle unknown errors with a `_` arm
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum MdnsError {
     #[error("Address is already taken")]
     AddressAlreadyTaken {
@@ -53,6 +105,33 @@ pub enum MdnsError {
     Closing {},
     #[error("Invalid Mdns Message")]
     InvalidMessage {},
+    #[error("Invalid Service: {reason}")]
+    InvalidService { reason: String },
+    #[error("Timed out")]
+    Timeout {},
+    #[error("Could not read hosts file")]
+    HostsFile { source: io::Error },
+    #[error("Service is not reachable from any network interface")]
+    NotReachable {},
+}
+
+/// Operational statistics of a [`DnsSd2`] client, see [`DnsSd2::statistics()`]
+///
+/// Available without the `metrics` feature
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BrowseStatistics {
+    /// Messages sent on the socket
+    pub messages_sent: u64,
+    /// Messages received on the socket
+    pub messages_received: u64,
+    /// Conflicts found while probing
+    pub probe_conflicts: u64,
+    /// Records currently in the cache
+    pub cache_record_count: usize,
+    /// Services which finished registering
+    pub active_registrations: usize,
+    /// Seconds since the client was created
+    pub uptime_seconds: u64,
 }
 
 /// Construct DnsSd2 to allow for searching and registering services
@@ -62,9 +141,19 @@ pub enum MdnsError {
 /// Attribute | Explanation
 /// :--|:--
 /// Records | Contains a Vec of [`ResourceRecord`] currently active on the network
-/// Registrations | May contain a registered [`Service`]
+/// Registrations | Contains the registered [`Service`]s
 /// Query | May contain an active search
 /// Tx.Rx | Channel for communicating (closing)
+/// Socket Factory | Creates the socket, see [`DnsSd2::with_socket_factory()`]
+/// Goodbye Repeat Count | Number of goodbye packets sent when dropped, see [`DnsSd2::with_goodbye_repeat_count()`]
+/// Lookup Timeout | Time to wait for answers in [`DnsSd2::lookup()`], see [`DnsSd2::with_lookup_timeout()`]
+/// Record Expiry Callbacks | Called when a record expires from the cache, see [`DnsSd2::on_record_expiry()`]
+/// Record Added Callbacks | Called when a record is added to the cache, see [`DnsSd2::on_record_added()`]
+/// Record Removed Callbacks | Called when a record expires or is flushed from the cache, see [`DnsSd2::on_record_removed()`]
+/// Statistics | Counters of sent and received messages, see [`DnsSd2::statistics()`]
+/// Created At | Creation time of the client, used for the uptime in [`DnsSd2::statistics()`]
+/// Log Level | Maximum log level applied when the event loop starts, see [`DnsSd2::set_log_level()`]
+/// Packet Dump | Whether sent and received packets are logged, see [`DnsSd2::enable_packet_dump()`]
 ///
 /// ## Example
 ///
@@ -76,10 +165,24 @@ pub enum MdnsError {
 /// ```
 pub struct DnsSd2 {
     records: Vec<ResourceRecord>,
-    registration: Option<Service>,
+    registrations: Vec<Service>,
     query: Option<Query>,
     pub tx: UnboundedSender<Event>,
     rx: UnboundedReceiver<Event>,
+    socket_factory: Box<dyn SocketFactory>,
+    goodbye_repeat_count: u8,
+    send_goodbye_on_drop: bool,
+    lookup_timeout: Duration,
+    source_address_check: bool,
+    record_expiry_callbacks: Vec<RecordExpiryCallback>,
+    record_added_callbacks: Vec<RecordCallback>,
+    record_removed_callbacks: Vec<RecordCallback>,
+    statistics: BrowseStatistics,
+    created_at: Instant,
+    log_level: Option<log::LevelFilter>,
+    packet_dump: bool,
+    known_txt_records: HashMap<String, Vec<String>>,
+    txt_updates: broadcast::Sender<(String, Vec<String>)>,
 }
 
 impl Default for DnsSd2 {
@@ -88,10 +191,24 @@ impl Default for DnsSd2 {
 
         Self {
             records: Default::default(),
-            registration: Default::default(),
+            registrations: Default::default(),
             query: Default::default(),
             tx,
             rx,
+            socket_factory: Box::new(create_framed_socket),
+            goodbye_repeat_count: 1,
+            send_goodbye_on_drop: true,
+            lookup_timeout: LOOKUP_TIMEOUT,
+            source_address_check: false,
+            record_expiry_callbacks: vec![],
+            record_added_callbacks: vec![],
+            record_removed_callbacks: vec![],
+            statistics: Default::default(),
+            created_at: Instant::now(),
+            log_level: None,
+            packet_dump: false,
+            known_txt_records: HashMap::new(),
+            txt_updates: broadcast::channel(TXT_UPDATE_CAPACITY).0,
         }
     }
 }
@@ -102,47 +219,110 @@ impl Drop for DnsSd2 {
     /// When dropped or when receiving [`Event::Closing{}`]
     /// Sends out Goodbye Packets if client initiated with [`DnsSd2::register()`]
     /// To properly unregister a [`Service`] on the network
+    ///
+    /// No Goodbye Packets are sent if disabled with [`DnsSd2::send_goodbye_on_drop()`]
     fn drop(&mut self) {
         debug!("Dropping DnsSd2");
-        let handler = GoodbyeHandler::default();
-        //Socket
-        let udp_socket = create_socket().expect("Failed to create socket");
 
-        let mut frame = UdpFramed::new(udp_socket, BytesCodec::new());
+        if !self.send_goodbye_on_drop {
+            debug!("Not sending goodbye packets");
+            return;
+        }
 
+        let handler = GoodbyeHandler::default().with_repeat_count(self.goodbye_repeat_count);
+        let mut timeouts = vec![];
         let mut queue = vec![];
 
-        if self
-            .handle(&handler, &Event::Closing(), &mut vec![], &mut queue)
-            .is_ok()
-        {
+        //Note: The goodbye handler does not await, so blocking on it returns right away
+        let mut result =
+            block_on(self.handle(&handler, &Event::Closing(), &mut timeouts, &mut queue));
+
+        //There is no event loop left to wait for repeated goodbyes, queue them right away
+        while result.is_ok() {
+            //Each registration adds the same timeout, which is handled for all registrations at once
+            timeouts.dedup();
+
+            match timeouts.pop() {
+                Some(t) => {
+                    result = block_on(self.handle(
+                        &handler,
+                        &Event::TimeElapsed(t),
+                        &mut timeouts,
+                        &mut queue,
+                    ))
+                }
+                None => break,
+            }
+        }
+
+        if result.is_ok() && !queue.is_empty() {
+            //Socket
+            let mut frame = (self.socket_factory)().expect("Failed to create socket");
+
             //Note: We block here because Drop must be synchronous
-            for message in queue {
-                block_on(send_message(&mut frame, &message)).expect("Failed to send goodbye");
+            let messages_sent = &mut self.statistics.messages_sent;
+            let send = || {
+                for (message, target) in queue {
+                    block_on(send_message_to(&mut frame, &message, target.socket_addr()))
+                        .expect("Failed to send goodbye");
+                    *messages_sent += 1;
+                }
+            };
+
+            //When dropped on a runtime worker, such as an aborted callback task, hand the worker's
+            //other tasks and the IO driver to another thread while blocking
+            match tokio::runtime::Handle::try_current() {
+                Ok(h) if h.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                    tokio::task::block_in_place(send)
+                }
+                _ => send(),
             }
         }
     }
 }
 
-impl<'a> DnsSd2 {
+impl DnsSd2 {
     /// Runs Chain of Responsibility for this client
     ///
     /// This function is called in the [`DnsSd2::init()`] loop
     /// Each part of the chain handles a different part of the MDNS Protocol
     ///
     /// Should return `Ok(())` or it propogates an [`MdnsError`]
-    /// Mutates records, registration, query and timeouts depending on Handler input
-    pub fn handle<T: Handler<'a>>(
+    /// Mutates records, registrations, query and timeouts depending on Handler input
+    ///
+    /// The chain runs once for each registration, the records and query are only handled along with the first registration
+    ///
+    /// Each run is awaited, so handlers can await as well, see [`protocols::handler::AsyncHandler`]
+    pub async fn handle<T: protocols::handler::AsyncHandler>(
         &mut self,
         h: &T,
         event: &Event,
         timeouts: &mut Vec<(ServiceState, u64)>,
-        queue: &mut Vec<MdnsMessage>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
     ) -> Result<(), MdnsError> {
-        let mut registration = None;
-        if self.registration.is_some() {
-            registration = self.registration.as_mut();
+        let (mut registration, others) = match self.registrations.split_first_mut() {
+            Some((first, others)) => (Some(first), others),
+            None => (None, &mut [][..]),
+        };
+
+        //The first registration runs last, so the cache is handled after all registrations are up to date
+        for other in others {
+            //Browse commands start a single query
+            if matches!(event, Event::Browse(..)) {
+                break;
+            }
+
+            h.handle(
+                event,
+                &mut vec![],
+                &mut Some(other),
+                &mut None,
+                timeouts,
+                queue,
+            )
+            .await?;
         }
+
         h.handle(
             event,
             &mut self.records,
@@ -150,12 +330,557 @@ impl<'a> DnsSd2 {
             &mut self.query,
             timeouts,
             queue,
-        )?;
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Create sockets with the given [`SocketFactory`] instead of [`create_framed_socket()`]
+    ///
+    /// Allows running the client on another socket, e.g. an in-memory socket for testing
+    pub fn with_socket_factory(mut self, factory: impl SocketFactory + 'static) -> Self {
+        self.socket_factory = Box::new(factory);
+        self
+    }
+
+    /// Send the goodbye packets `n` times when dropped, defaults to 1
+    ///
+    /// Repeating goodbyes makes unregistering more reliable on lossy networks
+    ///
+    /// [RFC6762 Section 10.1 - Goodbye Packets](https://www.rfc-editor.org/rfc/rfc6762#section-10.1)
+    pub fn with_goodbye_repeat_count(mut self, n: u8) -> Self {
+        self.goodbye_repeat_count = n;
+        self
+    }
+
+    /// Send goodbye packets for the registered services when dropped if `flag` is true, defaults to true
+    ///
+    /// Skip them if the services register again right away, e.g. when restarting,
+    /// so other hosts do not remove them from their caches in the meantime
+    pub fn send_goodbye_on_drop(mut self, flag: bool) -> Self {
+        self.send_goodbye_on_drop = flag;
+        self
+    }
+
+    /// Change whether goodbye packets are sent when dropped, see [`DnsSd2::send_goodbye_on_drop()`]
+    pub fn set_send_goodbye_on_drop(&mut self, flag: bool) {
+        self.send_goodbye_on_drop = flag;
+    }
+
+    /// Wait `timeout` for answers in [`DnsSd2::lookup()`], defaults to 2s
+    pub fn with_lookup_timeout(mut self, timeout: Duration) -> Self {
+        self.lookup_timeout = timeout;
+        self
+    }
+
+    /// Start with `records` in the cache, e.g. records persisted before a restart
+    ///
+    /// Services and lookups are answered from the cache right away instead of waiting for the network.
+    /// Goodbye records with a TTL of 0 and records with more TTL left than they were received with are skipped,
+    /// use [`DnsSd2::import_cache()`] to account for the time since the records were exported
+    pub fn with_initial_records(mut self, records: Vec<ResourceRecord>) -> Self {
+        let records: Vec<ResourceRecord> = records
+            .into_iter()
+            .filter(|r| !r.is_goodbye() && r.ttl <= r.original_ttl)
+            .collect();
+
+        debug!("Starting with {} cached records", records.len());
+
+        self.records.extend(records);
+        self
+    }
+
+    /// Do not receive the multicast messages this machine sends when `flag` is true, defaults to false
+    ///
+    /// With multiple clients on the same machine, each client receives the probes of the others and its own,
+    /// which the probe handler sees as conflicts. Probe responses should come from other hosts,
+    /// see [RFC6762 Section 8.1 - Probing](https://www.rfc-editor.org/rfc/rfc6762#section-8.1).
+    /// Keep the loop enabled to test with multiple clients on a single machine.
+    ///
+    /// Replaces the socket factory with [`create_socket()`] and [`SocketConfig::disable_multicast_loop`]
+    ///
+    /// ## Platform Notes
+    /// - Linux and macOS apply the option to sent messages, so no socket on this machine receives them,
+    ///   including other responders such as mDNSResponder on macOS
+    /// - Windows applies the option to received messages instead
+    pub fn disable_multicast_loop(self, flag: bool) -> Self {
+        let config = SocketConfig {
+            disable_multicast_loop: flag,
+            ..Default::default()
+        };
+
+        self.with_socket_factory(move || {
+            let socket = UdpFramed::new(create_socket(&config)?, BytesCodec::new());
+            Ok(Box::new(socket) as Box<dyn MdnsSocket>)
+        })
+    }
+
+    /// Ignore messages from sources outside the local link, see [`utility::source_address_check()`]
+    ///
+    /// [RFC6762 Section 11 - Source Address Check](https://www.rfc-editor.org/rfc/rfc6762#section-11)
+    pub fn with_source_address_check(mut self) -> Self {
+        self.source_address_check = true;
+        self
+    }
+
+    /// Set the maximum level of the [`log`] crate when the event loop starts
+    ///
+    /// e.g. `log::LevelFilter::Trace` shows all protocol state transitions while debugging.
+    /// The level is global to the [`log`] crate, so it also applies to the logs of the application
+    pub fn set_log_level(&mut self, level: log::LevelFilter) -> &mut Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    /// Log every packet sent and received by the event loop as a hex dump at TRACE level
+    pub fn enable_packet_dump(&mut self, enabled: bool) -> &mut Self {
+        self.packet_dump = enabled;
+        self
+    }
+
+    /// Hex dump of a sent or received packet, see [`DnsSd2::enable_packet_dump()`]
+    fn dump_packet(direction: &str, bytes: &[u8], addr: std::net::SocketAddr) {
+        trace!(
+            "{} {} bytes {}: {:02x?}",
+            direction,
+            bytes.len(),
+            addr,
+            bytes
+        );
+    }
+
+    /// Snapshot of the operational statistics of this client
+    ///
+    /// A dependency free alternative to the `metrics` feature, see [`BrowseStatistics`]
+    pub fn statistics(&self) -> BrowseStatistics {
+        BrowseStatistics {
+            cache_record_count: self.records.len(),
+            active_registrations: self
+                .registrations
+                .iter()
+                .filter(|r| r.state == ServiceState::Registered)
+                .count(),
+            uptime_seconds: self.created_at.elapsed().as_secs(),
+            ..self.statistics.clone()
+        }
+    }
+
+    /// Call `f` with each [`ResourceRecord`] whose TTL reaches 0 while the event loop runs
+    ///
+    /// Lets clients react to services disappearing from the cache without polling the records
+    ///
+    /// The callbacks are called from within the event loop so they should not block
+    pub fn on_record_expiry(
+        &mut self,
+        f: impl Fn(&ResourceRecord) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.record_expiry_callbacks.push(Box::new(f));
+        self
+    }
+
+    /// Call `f` with each [`ResourceRecord`] added to the cache while the event loop runs
+    ///
+    /// Records received again with the same data refresh the cached record and are not added
+    ///
+    /// The callbacks are called from within the event loop so they should not block
+    pub fn on_record_added(
+        &mut self,
+        f: impl Fn(ResourceRecord) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.record_added_callbacks.push(Box::new(f));
+        self
+    }
+
+    /// Call `f` with each [`ResourceRecord`] removed from the cache while the event loop runs
+    ///
+    /// Records are removed when their TTL reaches 0 or when they are flushed by a record with the cache flush bit
+    ///
+    /// The callbacks are called from within the event loop so they should not block
+    pub fn on_record_removed(
+        &mut self,
+        f: impl Fn(ResourceRecord) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.record_removed_callbacks.push(Box::new(f));
+        self
+    }
+
+    /// Stream of `(instance name, TXT records)` for each TXT record change of a service while the event loop runs
+    ///
+    /// The first TXT record received for a service is not a change, later TXT records with different strings are
+    ///
+    /// Runs alongside the stream of [`DnsSd2::browse()`], the stream ends when this client is dropped
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::DnsSd2;
+    ///
+    /// let updates = client.with_txt_updated_stream();
+    /// tokio::spawn(async move {
+    ///     pin_mut!(updates);
+    ///
+    ///     while let Some((instance, txt_records)) = updates.next().await {
+    ///         debug!("{} changed its TXT records to {:?}", instance, txt_records);
+    ///     }
+    /// });
+    /// ```
+    pub fn with_txt_updated_stream(&self) -> impl Stream<Item = (String, Vec<String>)> {
+        let mut rx = self.txt_updates.subscribe();
+
+        stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(update) => yield update,
+                    Err(RecvError::Lagged(n)) => warn!("Skipped {} TXT record changes", n),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    /// Describe the metrics recorded by this client
+    ///
+    /// Only available with the `metrics` feature, metrics are recorded with the
+    /// [`metrics`] crate so any installed recorder or exporter can collect them
+    ///
+    /// Metric | Type | Explanation
+    /// :--|:--|:--
+    /// mdns_messages_received_total | Counter | Messages received on the socket
+    /// mdns_messages_sent_total | Counter | Messages sent on the socket
+    /// mdns_probe_conflicts_total | Counter | Conflicts found while probing
+    /// mdns_registrations_active | Gauge | Services which finished registering
+    /// mdns_cache_records_count | Gauge | Records currently in the cache
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(self) -> Self {
+        metrics::describe_counter!(
+            "mdns_messages_received_total",
+            "Mdns messages received on the socket"
+        );
+        metrics::describe_counter!(
+            "mdns_messages_sent_total",
+            "Mdns messages sent on the socket"
+        );
+        metrics::describe_counter!(
+            "mdns_probe_conflicts_total",
+            "Conflicts found while probing"
+        );
+        metrics::describe_gauge!(
+            "mdns_registrations_active",
+            "Services which finished registering"
+        );
+        metrics::describe_gauge!("mdns_cache_records_count", "Records currently in the cache");
+
+        self
+    }
+
+    /// Cached records belonging to the service instances and address of a host
+    ///
+    /// Returns records whose name is the host or starts with the host label, compared case insensitive
+    /// e.g. 'MyMachine.local' and 'MyMachine._http._tcp.local' for host 'MyMachine'
+    pub fn records_for_service(&self, host: &str) -> Vec<&ResourceRecord> {
+        let prefix = host.to_ascii_lowercase() + ".";
+
+        self.records
+            .iter()
+            .filter(|r| {
+                let name = r.name.as_str().to_ascii_lowercase();
+                name == host.to_ascii_lowercase() || name.starts_with(&prefix)
+            })
+            .collect()
+    }
+
+    /// Cached records of the given [`QType`]
+    pub fn records_for_type(&self, qtype: QType) -> Vec<&ResourceRecord> {
+        self.records
+            .iter()
+            .filter(|r| r.record_type == qtype)
+            .collect()
+    }
+
+    /// Export the cached records, e.g. to persist them over a restart
+    ///
+    /// See [`CacheEntry`] and [`DnsSd2::import_cache()`]
+    pub fn export_cache(&self) -> Vec<CacheEntry> {
+        self.records.iter().map(CacheEntry::new).collect()
+    }
+
+    /// Import exported records into the cache
+    ///
+    /// Records whose TTL expired since they were exported are skipped, the others are cached with their remaining TTL.
+    /// Of a record which is already cached the one with the most TTL left is kept, see [`cache::merge()`].
+    /// Returns the number of imported records
+    pub fn import_cache(&mut self, entries: Vec<CacheEntry>) -> usize {
+        let records: Vec<ResourceRecord> = entries
+            .into_iter()
+            .filter_map(CacheEntry::into_record)
+            .collect();
+
+        debug!("Imported {} cached records", records.len());
+
+        let imported = records.len();
+        cache::merge(&mut self.records, records);
+        imported
+    }
+
+    /// Serve the `.local` names of a hosts file, e.g. `/etc/hosts`, with static records
+    ///
+    /// Each non-loopback address becomes an A or AAAA record for each of its `.local` names,
+    /// these records never expire and are answered when queried, see [`record::STATIC_TTL`].
+    /// Returns the number of imported records or [`MdnsError::HostsFile`] if the file cannot be read
+    pub fn import_hosts_file(&mut self, path: &Path) -> Result<usize, MdnsError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|source| MdnsError::HostsFile { source })?;
+        let records = parse_hosts_file(&contents);
+
+        debug!("Imported {} records from {}", records.len(), path.display());
+
+        let imported = records.len();
+        self.records.extend(records);
+        Ok(imported)
+    }
+
+    /// Cached records with the given name, compared case insensitive
+    pub fn records_for_name(&self, name: &str) -> Vec<&ResourceRecord> {
+        self.records
+            .iter()
+            .filter(|r| r.name.as_str().eq_ignore_ascii_case(name))
+            .collect()
+    }
+
+    /// Look up the records of the given [`QType`] with `name` once
+    ///
+    /// Returns the matching cached records right away, see [`DnsSd2::with_initial_records()`].
+    /// Otherwise sends a single query and returns the matching records of the first response which contains any,
+    /// use [`QType::Any`] to match all records with `name`
+    ///
+    /// Returns [`MdnsError::Timeout`] if no records arrive within the lookup timeout, see [`DnsSd2::with_lookup_timeout()`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::{question::QType, DnsSd2};
+    ///
+    /// let records = client.lookup("MyMachine.local", QType::A).await?;
+    /// ```
+    pub async fn lookup(
+        &mut self,
+        name: &str,
+        qtype: QType,
+    ) -> Result<Vec<ResourceRecord>, MdnsError> {
+        debug!("Lookup {:?} records of {}", qtype, name);
+
+        let cached: Vec<ResourceRecord> = self
+            .records_for_name(name)
+            .into_iter()
+            .filter(|r| !r.is_goodbye() && (qtype == QType::Any || r.record_type == qtype))
+            .cloned()
+            .collect();
+
+        if !cached.is_empty() {
+            debug!(
+                "Found {} cached {:?} records of {}",
+                cached.len(),
+                qtype,
+                name
+            );
+            return Ok(cached);
+        }
+
+        //Socket
+        let mut frame = (self.socket_factory)().expect("Failed to create socket");
+
+        send_message_to(
+            &mut frame,
+            &MdnsMessage::query_type(name, qtype),
+            SendTarget::Multicast.socket_addr(),
+        )
+        .await?;
+        self.statistics.messages_sent += 1;
+
+        let deadline = Instant::now() + self.lookup_timeout;
+        let mut found = vec![];
+
+        while found.is_empty() {
+            let (bytes, addr) = match timeout_at(deadline, frame.next()).await {
+                Ok(Some(f)) => f?,
+                _ => {
+                    debug!("No {:?} records of {} found", qtype, name);
+                    return Err(MdnsError::Timeout {});
+                }
+            };
+            self.statistics.messages_received += 1;
+
+            #[cfg(feature = "metrics")]
+            metrics::counter!("mdns_messages_received_total").increment(1);
+
+            match MdnsMessage::try_from(&bytes[..]) {
+                Ok(message) => found.extend(
+                    message
+                        .answers
+                        .into_iter()
+                        .chain(message.additionals)
+                        .filter(|r| {
+                            r.name.as_str().eq_ignore_ascii_case(name)
+                                && (qtype == QType::Any || r.record_type == qtype)
+                        }),
+                ),
+                Err(e) => debug!("Ignoring message from {}: {}", addr, e),
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Check whether a host name is available without registering it
+    ///
+    /// Runs only the probing of the [`ProbeHandler`] for `host`, including the initial 0-250ms delay.
+    /// Nothing is announced when probing finishes
+    ///
+    /// Returns `Ok(false)` if another host responds with records for the name,
+    /// or [`MdnsError::Timeout`] if the socket closes before probing finishes
+    ///
+    /// [RFC6762 Section 8.1 - Probing](https://www.rfc-editor.org/rfc/rfc6762#section-8.1)
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::DnsSd2;
+    ///
+    /// if client.is_name_available("MyMachine").await? {
+    ///     debug!("MyMachine.local is available");
+    /// }
+    /// ```
+    pub async fn is_name_available(&mut self, host: &str) -> Result<bool, MdnsError> {
+        debug!("Probing whether {}.local is available", host);
+
+        //Socket
+        let mut frame = (self.socket_factory)().expect("Failed to create socket");
+
+        //Only probe, the chain ends before announcing
+        let probe_handler = ProbeHandler::default();
+        let mut probe = Service {
+            host: host.into(),
+            ..Default::default()
+        };
+        let name = probe.host.clone() + ".local";
+
+        let mut timeouts = TimeoutMap::default();
+        let mut event = Event::Ttl();
+
+        loop {
+            let mut new_timeouts = vec![];
+            let mut queue = vec![];
+
+            probe_handler.handle(
+                &event,
+                &mut vec![],
+                &mut Some(&mut probe),
+                &mut None,
+                &mut new_timeouts,
+                &mut queue,
+            )?;
+
+            //Probing finished without conflicts
+            if probe.state == ServiceState::FirstAnnouncement {
+                break;
+            }
+
+            for (s, t) in new_timeouts {
+                timeouts.insert(s, t);
+            }
+
+            for (message, target) in queue {
+                send_message_to(&mut frame, &message, target.socket_addr()).await?;
+                self.statistics.messages_sent += 1;
+            }
+
+            event = select! {
+                f = frame.next() => {
+                    let (bytes, addr) = match f {
+                        Some(f) => f?,
+                        None => {
+                            warn!("Socket closed");
+                            return Err(MdnsError::Timeout {});
+                        }
+                    };
+                    self.statistics.messages_received += 1;
+
+                    match MdnsMessage::try_from(&bytes[..]) {
+                        //Any response with records for the name is a conflict
+                        Ok(message) if message.is_response() => {
+                            if message
+                                .answers
+                                .iter()
+                                .chain(&message.additionals)
+                                .any(|r| r.name.as_str().eq_ignore_ascii_case(&name))
+                            {
+                                debug!("{} is taken", name);
+                                self.statistics.probe_conflicts += 1;
+
+                                #[cfg(feature = "metrics")]
+                                metrics::counter!("mdns_probe_conflicts_total").increment(1);
+
+                                return Ok(false);
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => debug!("Ignoring message from {}: {}", addr, e),
+                    }
+                    continue;
+                }
+                t = timeouts.next(), if !timeouts.is_empty() => {
+                    Event::TimeElapsed(t.unwrap_or_default())
+                }
+            };
+        }
+
+        debug!("{} is available", name);
+        Ok(true)
+    }
+
+    /// Send a handcrafted message to the multicast group
+    ///
+    /// Bypasses the chain of handlers, nothing is probed, cached or answered.
+    /// Misusing it can violate [RFC6762](https://www.rfc-editor.org/rfc/rfc6762), e.g. by claiming names of other hosts
+    ///
+    /// Returns [`MdnsError::InvalidMessage`] if the message fails [`MdnsMessage::validate()`]
+    ///
+    /// Requires the `raw-api` feature
+    #[cfg(feature = "raw-api")]
+    pub async fn send_raw(&mut self, message: MdnsMessage) -> Result<(), MdnsError> {
+        self.send_raw_unicast(message, SendTarget::Multicast.socket_addr())
+            .await
+    }
+
+    /// Send a handcrafted message to the given address, see [`DnsSd2::send_raw()`]
+    ///
+    /// Requires the `raw-api` feature
+    #[cfg(feature = "raw-api")]
+    pub async fn send_raw_unicast(
+        &mut self,
+        message: MdnsMessage,
+        addr: std::net::SocketAddr,
+    ) -> Result<(), MdnsError> {
+        message.validate()?;
+
+        debug!("Sending raw message to {}", addr);
+
+        //Socket
+        let mut frame = (self.socket_factory)().expect("Failed to create socket");
+
+        send_message_to(&mut frame, &message, addr).await?;
+        self.statistics.messages_sent += 1;
+
         Ok(())
     }
 
     /// Registers an Mdns [`Service`]
     ///
+    /// A service can be registered while other services of this instance are running,
+    /// e.g. by sending [`Event::Register`] with a clone of `tx` while the stream is polled
+    /// - A new host, service and protocol combination is probed and announced, the running services stay registered
+    /// - Registering the same host, service and protocol again only updates its TXT records, see [`DnsSd2::update_txt_records()`]
+    ///
     /// ## Example
     ///
     /// ```rust, ignore
@@ -190,133 +915,2497 @@ impl<'a> DnsSd2 {
         self.init().await
     }
 
-    /// Browse for an Mdns [`Service`]
+    /// Registers an Mdns [`Service`] with the hostname of this machine as host
+    ///
+    /// See [`utility::get_hostname()`]
     ///
     /// ## Example
     ///
     /// ```rust, ignore
     /// use dns_sd2::Dns_Sd2;
     ///
-    /// let stream = client.browse("_services._udp.local".into()).await;
+    /// let stream = client.register_local("_myservice", "_udp", 53000, vec![]).await;
     ///
     /// //This is necessary to iterate the Stream
     /// pin_mut!(stream);
     ///
     /// while let Some(Ok(s)) = stream.next().await {
-    ///     debug!("Found a service {:?}", s);
+    ///     debug!("Registered a service {:?}", s);
     /// }
     /// ```
-    pub async fn browse(
+    pub async fn register_local(
         &mut self,
-        name: String,
+        service: &str,
+        protocol: &str,
+        port: u16,
+        txt_records: Vec<String>,
     ) -> impl Stream<Item = Result<Service, MdnsError>> + '_ {
-        debug!("Browse for Service {}", name);
-
-        self.tx
-            .send(Event::Browse(name))
-            .expect("Failed to send with Tx");
+        let host = get_hostname().unwrap_or_else(|e| {
+            warn!("{}, using localhost", e);
+            "localhost".into()
+        });
 
-        self.init().await
+        self.register(host, service.into(), protocol.into(), port, txt_records)
+            .await
     }
 
-    /// Called by [`browse()`] or [`register()`] to run main loop
+    /// Registers multiple Mdns [`Service`]s at once
     ///
-    /// This starts the main event loop for the library and builds the chain of responsibility
+    /// All services share the socket and the event loop, they are probed and announced simultaneously
     ///
-    /// A select! loop picks between a 1s Interval Stream, a dynamic interval stream set by the chain and the UdpFramed Stream
+    /// The stream yields [`ServiceEvent::Added`] for each service which finished registering
     ///
-    /// Returns a stream for registration or search
-    pub async fn init(&mut self) -> impl Stream<Item = Result<Service, MdnsError>> + '_ {
-        info!("Initializing Event Loop");
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::{DnsSd2, service::RegistrationOptions};
+    ///
+    /// let http = RegistrationOptions {
+    ///     host: "MyMachine".into(),
+    ///     service: "_http".into(),
+    ///     protocol: "_tcp".into(),
+    ///     port: 80,
+    ///     ..Default::default()
+    /// };
+    /// let ssh = RegistrationOptions {
+    ///     service: "_ssh".into(),
+    ///     port: 22,
+    ///     ..http.clone()
+    /// };
+    ///
+    /// let stream = client.register_many(vec![http, ssh]).await;
+    ///
+    /// //This is necessary to iterate the Stream
+    /// pin_mut!(stream);
+    ///
+    /// while let Some(Ok(event)) = stream.next().await {
+    ///     debug!("{:?}", event);
+    /// }
+    /// ```
+    pub async fn register_many(
+        &mut self,
+        services: Vec<RegistrationOptions>,
+    ) -> impl Stream<Item = Result<ServiceEvent, MdnsError>> + '_ {
+        for s in services {
+            debug!(
+                "Register Service {}.{}.{}.local with port {} with TXT Records {:?}",
+                s.host, s.service, s.protocol, s.port, s.txt_records
+            );
 
-        try_stream! {
-                //Socket
-                let udp_socket = create_socket().expect("Failed to create socket");
+            self.tx
+                .send(Event::Register(
+                    s.host,
+                    s.service,
+                    s.protocol,
+                    s.port,
+                    s.txt_records,
+                ))
+                .expect("Failed to send with Tx");
+        }
 
-                let mut frame = UdpFramed::new(udp_socket, BytesCodec::new());
+        self.init().await.filter_map(|s| async move {
+            match s {
+                Ok(s) if s.state == ServiceState::Registered => Some(Ok(ServiceEvent::Added(s))),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }
+        })
+    }
 
-                //Chain of responsibility
-                let mut probe_handler = ProbeHandler::default();
-                let mut announcement_handler = AnnouncementHandler::default();
-                let goodbye_handler = GoodbyeHandler::default();
+    /// Register an Mdns [`Service`] for `duration` and unregister it afterwards
+    ///
+    /// For short-lived services, e.g. in a test harness. The service keeps answering queries for `duration`
+    /// after it finished registering, then the client is dropped and sends its goodbye packets
+    ///
+    /// Returns the first error of the registration
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::{DnsSd2, service::RegistrationOptions};
+    ///
+    /// let http = RegistrationOptions {
+    ///     host: "MyMachine".into(),
+    ///     service: "_http".into(),
+    ///     protocol: "_tcp".into(),
+    ///     port: 80,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// DnsSd2::default().register_ephemeral(http, Duration::from_secs(60)).await?;
+    /// ```
+    pub async fn register_ephemeral(
+        mut self,
+        opts: RegistrationOptions,
+        duration: Duration,
+    ) -> Result<(), MdnsError> {
+        {
+            let stream = self.register_many(vec![opts]).await;
+            pin_mut!(stream);
 
-                //Set Chain Order from back to front
-                announcement_handler.set_next(&goodbye_handler);
-                probe_handler.set_next(&announcement_handler);
+            //Registered
+            loop {
+                match stream.next().await {
+                    Some(Ok(ServiceEvent::Added(_))) => break,
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                }
+            }
 
+            //Keep answering queries until the duration has passed
+            let deadline = Instant::now() + duration;
 
-                //Collection of timer futures
-                let mut timeouts = FuturesUnordered::new();
-                //Normal 1s TTL Timer
-                let mut interval = interval(Duration::from_secs(1));
+            while let Ok(event) = timeout_at(deadline, stream.next()).await {
+                match event {
+                    Some(Err(e)) => return Err(e),
+                    Some(Ok(_)) => {}
+                    None => break,
+                }
+            }
+        }
 
-                loop {
-                    let result = select! {
-                        //Received a message on the Socket
-                        _ = frame.next() => {
-                            Event::Message(MdnsMessage::default())
-                        }
-                        //Received a Command from the client
-                        c = self.rx.recv() => {
-                            c.expect("Should contain a Command")
-                        }
-                        //Close signal handler
-                        _close = tokio::signal::ctrl_c() => {
-                            debug!("Ctrl C! Closing");
-                            Event::Closing()
-                        }
-                        //A dynamic timeout has finished
-                        t = timeouts.next(), if !timeouts.is_empty() => {
-                            debug!("Timed out for {:?} ms", t);
-                            Event::TimeElapsed(t.unwrap_or_default())
-                        }
-                        //TTL 1s timer has ticked
-                        _ = interval.tick() => {
-                            Event::Ttl()
-                        }
-                    };
+        debug!("Unregistering after {:?}", duration);
 
-                    //Check for specific command or signals
-                    match &result{
-                        Event::Register(host, service, protocol, port, txt_records) => {
-                            self.registration = Some(Service{host: host.into(), service: service.into(), protocol: protocol.into(), port: *port, txt_records: txt_records.to_vec(), state: ServiceState::Prelude})
+        //Dropping the client sends the goodbye packets
+        drop(self);
+
+        Ok(())
+    }
+
+    /// Browse for an Mdns [`Service`]
+    ///
+    /// A found service which sends goodbye packets is yielded again with [`ServiceState::Removed`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::Dns_Sd2;
+    ///
+    /// let stream = client.browse("_services._udp.local".into()).await;
+    ///
+    /// //This is necessary to iterate the Stream
+    /// pin_mut!(stream);
+    ///
+    /// while let Some(Ok(s)) = stream.next().await {
+    ///     debug!("Found a service {:?}", s);
+    /// }
+    /// ```
+    pub async fn browse(
+        &mut self,
+        name: String,
+    ) -> impl Stream<Item = Result<Service, MdnsError>> + '_ {
+        self.browse_with_filter(name, BrowseFilter::default()).await
+    }
+
+    /// Browse for an Mdns [`Service`] with TXT records matching the [`BrowseFilter`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::{service::BrowseFilter, DnsSd2};
+    ///
+    /// let filter = BrowseFilter::default().require_txt("version", "2");
+    ///
+    /// let stream = client.browse_with_filter("_http._tcp.local".into(), filter).await;
+    ///
+    /// //This is necessary to iterate the Stream
+    /// pin_mut!(stream);
+    ///
+    /// while let Some(Ok(s)) = stream.next().await {
+    ///     debug!("Found a version 2 service {:?}", s);
+    /// }
+    /// ```
+    pub async fn browse_with_filter(
+        &mut self,
+        name: String,
+        filter: BrowseFilter,
+    ) -> impl Stream<Item = Result<Service, MdnsError>> + '_ {
+        debug!("Browse for Service {} with {:?}", name, filter);
+
+        self.tx
+            .send(Event::Browse(name, filter, thread_rng().gen_range(0..=500)))
+            .expect("Failed to send with Tx");
+
+        self.init().await
+    }
+
+    /// Browse for an Mdns [`Service`] for `duration` and collect the services found
+    ///
+    /// A snapshot of the services available right now, the browse stops after `duration`
+    ///
+    /// Services are resolved with the cached A and AAAA records of their host,
+    /// services without a cached A record are skipped.
+    /// Returns an empty Vec if no services are found
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::DnsSd2;
+    ///
+    /// let services = client.browse_for("_http._tcp.local".into(), Duration::from_secs(3)).await?;
+    /// ```
+    pub async fn browse_for(
+        &mut self,
+        name: String,
+        duration: Duration,
+    ) -> Result<Vec<ResolvedService>, MdnsError> {
+        let deadline = Instant::now() + duration;
+        let mut services = vec![];
+
+        {
+            let stream = self.browse(name).await;
+            pin_mut!(stream);
+
+            while let Ok(Some(s)) = timeout_at(deadline, stream.next()).await {
+                let s = s?;
+
+                //Each iteration of the event loop yields an empty service
+                if s.state == ServiceState::Removed {
+                    let instance = s.to_dns_sd_string();
+                    services.retain(|f: &Service| {
+                        !f.to_dns_sd_string().eq_ignore_ascii_case(&instance)
+                    });
+                } else if !s.host.is_empty() {
+                    services.push(s);
+                }
+            }
+        }
+
+        Ok(services
+            .into_iter()
+            .filter_map(|s| self.resolve(s))
+            .collect())
+    }
+
+    /// Browse for an Mdns [`Service`] on every network interface
+    ///
+    /// Creates a socket bound to each non-loopback multicast interface, see [`SocketConfig::interface`],
+    /// browses on all of them concurrently and merges the results into a single stream
+    ///
+    /// A service found on multiple interfaces is yielded once as [`ServiceEvent::Found`],
+    /// a service which sends goodbye packets is yielded as [`ServiceEvent::Removed`]
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::DnsSd2;
+    ///
+    /// let stream = DnsSd2::browse_all_interfaces("_services._udp.local".into());
+    ///
+    /// //This is necessary to iterate the Stream
+    /// pin_mut!(stream);
+    ///
+    /// while let Some(Ok(event)) = stream.next().await {
+    ///     debug!("{:?}", event);
+    /// }
+    /// ```
+    pub fn browse_all_interfaces(
+        name: String,
+    ) -> impl Stream<Item = Result<ServiceEvent, MdnsError>> {
+        let clients = enumerate_network_interfaces()
+            .into_iter()
+            .filter(|i| !i.is_loopback && i.is_multicast)
+            .map(|i| {
+                debug!("Browse for Service {} on {}", name, i.name);
+
+                let config = SocketConfig {
+                    interface: Some(i.name),
+                    ..Default::default()
+                };
+
+                DnsSd2::default().with_socket_factory(move || {
+                    let socket = UdpFramed::new(create_socket(&config)?, BytesCodec::new());
+                    Ok(Box::new(socket) as Box<dyn MdnsSocket>)
+                })
+            })
+            .collect();
+
+        Self::browse_clients(clients, name)
+    }
+
+    /// Browse with each of `clients` concurrently, services are yielded once by instance name
+    fn browse_clients(
+        clients: Vec<DnsSd2>,
+        name: String,
+    ) -> impl Stream<Item = Result<ServiceEvent, MdnsError>> {
+        let streams = clients.into_iter().map(|mut client| {
+            let name = name.clone();
+
+            Box::pin(try_stream! {
+                let stream = client.browse(name).await;
+                pin_mut!(stream);
+
+                while let Some(service) = stream.next().await {
+                    let service: Service = service?;
+                    yield service;
+                }
+            })
+        });
+
+        let mut found = HashSet::new();
+
+        select_all(streams).try_filter_map(move |service: Service| {
+            let instance = service.to_dns_sd_string();
+
+            let event = if service.state == ServiceState::Removed {
+                found
+                    .remove(&instance.to_ascii_lowercase())
+                    .then_some(ServiceEvent::Removed(instance))
+            } else {
+                //Each iteration of the event loop yields an empty service
+                (!service.host.is_empty() && found.insert(instance.to_ascii_lowercase()))
+                    .then_some(ServiceEvent::Found(service))
+            };
+
+            async move { Ok(event) }
+        })
+    }
+
+    /// Resolve the addresses of a [`Service`] with the cached A and AAAA records of its host
+    ///
+    /// Browsed services carry the addresses of their SRV target, see [`Service::target`] and [`Service::addresses`],
+    /// other services are looked up by the host of their instance name
+    fn resolve(&self, service: Service) -> Option<ResolvedService> {
+        let host = service
+            .target
+            .clone()
+            .unwrap_or_else(|| service.host.clone() + ".local");
+
+        let address = |qtype| {
+            self.records_for_name(&host)
+                .into_iter()
+                .filter(|r| r.record_type == qtype)
+                .find_map(|r| r.rdata.as_ref().map(|rdata| rdata.to_bytes()))
+        };
+
+        let ip_v4 = service
+            .addresses
+            .iter()
+            .find_map(|ip| match ip {
+                IpAddr::V4(ip) => Some(*ip),
+                IpAddr::V6(_) => None,
+            })
+            .or_else(|| {
+                address(QType::A)
+                    .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+                    .map(Ipv4Addr::from)
+            });
+        let ip_v6 = service
+            .addresses
+            .iter()
+            .find_map(|ip| match ip {
+                IpAddr::V6(ip) => Some(*ip),
+                IpAddr::V4(_) => None,
+            })
+            .or_else(|| {
+                address(QType::Aaaa)
+                    .and_then(|bytes| <[u8; 16]>::try_from(bytes).ok())
+                    .map(Ipv6Addr::from)
+            });
+
+        match ip_v4 {
+            Some(ip_v4) => Some(ResolvedService {
+                //Wall clock time of the registration, so it can be persisted
+                registered_at: service
+                    .registered_at
+                    .and_then(|t| SystemTime::now().checked_sub(t.elapsed()))
+                    .unwrap_or_else(SystemTime::now),
+                service,
+                ip_v4,
+                ip_v6,
+            }),
+            None => {
+                debug!("No A record cached for {}, skipping", host);
+                None
+            }
+        }
+    }
+
+    /// Observe all Mdns traffic on the network
+    ///
+    /// Yields every record of every received message in order of answers, authorities and additionals,
+    /// without registering, browsing or sending any messages
+    ///
+    /// Messages which can not be parsed are skipped
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::DnsSd2;
+    ///
+    /// let stream = client.observe();
+    ///
+    /// //This is necessary to iterate the Stream
+    /// pin_mut!(stream);
+    ///
+    /// while let Some(Ok(r)) = stream.next().await {
+    ///     debug!("Observed a record {:?}", r);
+    /// }
+    /// ```
+    pub fn observe(&mut self) -> impl Stream<Item = Result<ResourceRecord, MdnsError>> + '_ {
+        info!("Observing Mdns traffic");
+
+        try_stream! {
+            //Socket
+            let mut frame = (self.socket_factory)().expect("Failed to create socket");
+
+            while let Some(f) = frame.next().await {
+                let (bytes, addr) = f?;
+                self.statistics.messages_received += 1;
+
+                #[cfg(feature = "metrics")]
+                metrics::counter!("mdns_messages_received_total").increment(1);
+
+                match MdnsMessage::try_from(&bytes[..]) {
+                    Ok(message) => {
+                        for record in message
+                            .answers
+                            .into_iter()
+                            .chain(message.authorities)
+                            .chain(message.additionals)
+                        {
+                            yield record;
                         }
-                        Event::Closing{} => {return}
-                        _ => {}
                     }
+                    Err(e) => debug!("Ignoring message from {}: {}", addr, e),
+                }
+            }
+        }
+    }
 
-                    //Fill a Vec with new timeouts and a Vec with a queue of messages we will send with the socket
-                    let mut new_timeouts = vec![];
-                    let mut queue = vec![];
+    /// Pause the registered [`Service`]
+    ///
+    /// Suspends announcements and responses without sending goodbye packets, e.g. before the system goes to sleep
+    pub fn pause(&self) {
+        debug!("Pause");
 
+        self.tx
+            .send(Event::Pause())
+            .expect("Failed to send with Tx");
+    }
 
-                    //Execute the chain
-                    self.handle(&probe_handler, &result, &mut new_timeouts, &mut queue)?;
+    /// Resume the registered [`Service`]
+    ///
+    /// Announces the service again, e.g. after the system wakes from sleep and the network may have changed
+    pub fn resume(&self) {
+        debug!("Resume");
 
-                    let s = Service::default();
-                    yield s;
+        self.tx
+            .send(Event::Resume())
+            .expect("Failed to send with Tx");
+    }
 
-                    //Add the resulting timeouts from the chain to our dynamic interval futures
-                    for (s, t) in new_timeouts {
-                        timeouts.push(sleep_for(s,t));
-                    }
+    /// Signal that the network of the local machine changed, e.g. after switching Wi-Fi networks
+    ///
+    /// Registered services send goodbye packets for their old address records and are announced
+    /// with the new addresses, without probing again, see [`protocols::network_change::NetworkChangeHandler`]
+    ///
+    /// The cached network interfaces are enumerated again, see [`utility::refresh_network_interfaces()`]
+    pub fn network_change(&self) {
+        debug!("Network change");
+        utility::refresh_network_interfaces();
 
-                    //Send the messages in the queue with our socket
-                    for message in queue{
-                        send_message(&mut frame, &message).await.expect("Should send Message");
-                    }
+        self.tx
+            .send(Event::NetworkChange())
+            .expect("Failed to send with Tx");
+    }
 
+    /// Cancel the active browse without closing the client
+    ///
+    /// The browse stream yields a service with [`ServiceState::BrowseCancelled`] and ends,
+    /// unless services are registered with this client, which stay registered.
+    /// Calling [`DnsSd2::browse()`] again starts a new browse
+    pub fn cancel_browse(&self) {
+        debug!("Cancel browse");
+
+        self.tx
+            .send(Event::CancelBrowse())
+            .expect("Failed to send with Tx");
+    }
 
+    /// Signal that the local machine goes to sleep or wakes up
+    ///
+    /// While sleeping, a registered [`Service`] with a `sleep-proxy` TXT record key keeps being announced,
+    /// see [`protocols::sleep_proxy::SleepProxyHandler`]
+    ///
+    /// Requires the `sleep-proxy` feature, without it the signal is ignored
+    pub fn set_sleep_mode(&self, sleeping: bool) {
+        debug!("Sleep mode {}", sleeping);
 
-                }
+        self.tx
+            .send(Event::SleepModeChanged(sleeping))
+            .expect("Failed to send with Tx");
+    }
+
+    /// Update the TXT records of each [`Service`] registered with `host`
+    ///
+    /// The new TXT record is announced without probing the service again
+    ///
+    /// [RFC6763 Section 6.7 - Version Tag](https://www.rfc-editor.org/rfc/rfc6763#section-6.7)
+    ///
+    /// Returns [`MdnsError::ServiceRemoved`] if no service is registered with `host`
+    pub fn update_txt_records(&self, host: &str, new_txt: Vec<String>) -> Result<(), MdnsError> {
+        let instances: Vec<String> = self
+            .registrations
+            .iter()
+            .filter(|r| r.host.eq_ignore_ascii_case(host))
+            .map(Service::to_dns_sd_string)
+            .collect();
+
+        if instances.is_empty() {
+            return Err(MdnsError::ServiceRemoved {});
         }
+
+        for instance in instances {
+            debug!("Update TXT Records of {} to {:?}", instance, new_txt);
+
+            self.tx
+                .send(Event::UpdateTxt(instance, new_txt.clone()))
+                .expect("Failed to send with Tx");
+        }
+
+        Ok(())
     }
-}
 
-/// Sleep for a certain duration
-///
-/// Pass along the [`ServiceState`] for identification of finished timeouts in the  [`Handler`] chain
-async fn sleep_for(state: ServiceState, duration: u64) -> (ServiceState, u64) {
-    tokio::time::sleep(Duration::from_millis(duration)).await;
-    (state, duration)
+    /// Change the host name of the registered services, e.g. after the user enters a new name
+    ///
+    /// Registered services send goodbye packets for their old name, then all services are probed and announced again.
+    /// The host name is a single label (e.g. 'MyMachine') of at most 63 characters, without '.local'
+    ///
+    /// Returns [`MdnsError::InvalidService`] if the host name is invalid
+    /// or [`MdnsError::NameAlreadyTaken`] if records of another host with this name are cached,
+    /// conflicts found while probing are returned by the event loop
+    pub fn set_host_name(&self, hostname: String) -> Result<(), MdnsError> {
+        let reason = if hostname.is_empty() {
+            Some("Host name should not be empty".to_string())
+        } else if hostname.contains('.') {
+            Some(format!("Host {} should not contain dots", hostname))
+        } else if hostname.len() > 63 {
+            Some(format!("Host {} should be at most 63 characters", hostname))
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            return Err(MdnsError::InvalidService { reason });
+        }
+
+        let ours = self
+            .registrations
+            .iter()
+            .any(|r| r.host.eq_ignore_ascii_case(&hostname));
+
+        if !ours
+            && !self
+                .records_for_name(&(hostname.clone() + ".local"))
+                .is_empty()
+        {
+            return Err(MdnsError::NameAlreadyTaken {});
+        }
+
+        debug!("Set Host Name to {}", hostname);
+
+        self.tx
+            .send(Event::SetHostName(hostname))
+            .expect("Failed to send with Tx");
+
+        Ok(())
+    }
+
+    /// Whether a [`Service`] with `host`, `service` and `protocol` is registered with this instance
+    fn is_registered(&self, host: &str, service: &str, protocol: &str) -> bool {
+        self.registrations.iter().any(|r| {
+            r.host.eq_ignore_ascii_case(host)
+                && r.service.eq_ignore_ascii_case(service)
+                && r.protocol.eq_ignore_ascii_case(protocol)
+        })
+    }
+
+    /// Browse for an Mdns [`Service`] and call `callback` for each service found
+    ///
+    /// Drives the [`browse()`] stream in a spawned task, the callback is called from within this task so it should not block
+    ///
+    /// Services which send goodbye packets are passed as [`ServiceEvent::Removed`]
+    ///
+    /// The returned [`JoinHandle`] can be awaited for the first error or aborted to stop browsing
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::DnsSd2;
+    ///
+    /// let handle = DnsSd2::default().on_service_found("_services._udp.local".into(), |event| {
+    ///     debug!("{:?}", event);
+    /// });
+    ///
+    /// handle.abort();
+    /// ```
+    pub fn on_service_found<F>(
+        mut self,
+        name: String,
+        callback: F,
+    ) -> JoinHandle<Result<(), MdnsError>>
+    where
+        F: Fn(ServiceEvent) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let stream = self.browse(name).await;
+            pin_mut!(stream);
+
+            while let Some(service) = stream.next().await {
+                let service = service?;
+
+                if service.state == ServiceState::Removed {
+                    callback(ServiceEvent::Removed(service.to_dns_sd_string()));
+                } else {
+                    callback(ServiceEvent::Found(service));
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Register an Mdns [`Service`] and call `callback` with its registration
+    ///
+    /// Drives the [`register()`] stream in a spawned task, the callback is called from within this task so it should not block
+    ///
+    /// The returned [`JoinHandle`] can be awaited for the first error or aborted to unregister
+    ///
+    /// ## Example
+    ///
+    /// ```rust, ignore
+    /// use dns_sd2::DnsSd2;
+    ///
+    /// let handle = DnsSd2::default().on_registered(
+    ///     "MyMachine".into(),
+    ///     "_myservice".into(),
+    ///     "_udp".into(),
+    ///     53000,
+    ///     vec![],
+    ///     |event| debug!("{:?}", event),
+    /// );
+    /// ```
+    pub fn on_registered<F>(
+        mut self,
+        host: String,
+        service: String,
+        protocol: String,
+        port: u16,
+        txt_records: Vec<String>,
+        callback: F,
+    ) -> JoinHandle<Result<(), MdnsError>>
+    where
+        F: Fn(ServiceEvent) + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let stream = self
+                .register(host, service, protocol, port, txt_records)
+                .await;
+            pin_mut!(stream);
+
+            while let Some(service) = stream.next().await {
+                callback(ServiceEvent::Registered(service?));
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Called by [`browse()`] or [`register()`] to run main loop
+    ///
+    /// This starts the main event loop for the library and builds the chain of responsibility
+    ///
+    /// A select! loop picks between a 1s Interval Stream, a dynamic interval stream set by the chain and the socket Stream
+    ///
+    /// Returns a stream for registration or search
+    pub async fn init(&mut self) -> impl Stream<Item = Result<Service, MdnsError>> + '_ {
+        info!("Initializing Event Loop");
+
+        if let Some(level) = self.log_level {
+            log::set_max_level(level);
+        }
+
+        try_stream! {
+                //Socket
+                let mut frame = (self.socket_factory)().expect("Failed to create socket");
+
+                //Callbacks are owned by the loop, the chain borrows them while it borrows self mutably
+                let expiry_callbacks = std::mem::take(&mut self.record_expiry_callbacks);
+                let added_callbacks = std::mem::take(&mut self.record_added_callbacks);
+                let removed_callbacks = std::mem::take(&mut self.record_removed_callbacks);
+
+                //Chain of responsibility, events pass through the handlers from front to back
+                let handler = chain(ProbeHandler::default(),
+                    chain(AnnouncementHandler::default(),
+                    chain(NetworkChangeHandler::default(),
+                    chain(QueryHandler::default(),
+                    chain(CacheFlushHandler::default().with_removed_callbacks(&removed_callbacks),
+                    chain(ResponseHandler::default().with_added_callbacks(&added_callbacks),
+                    chain(BrowseHandler::default(),
+                    chain(ContinuousQueryHandler::default(),
+                    chain(GoodbyeHandler::default(),
+                    chain(UpdateTTLHandler::default().with_expiry_callbacks(&expiry_callbacks).with_removed_callbacks(&removed_callbacks),
+                    SleepProxyHandler::default()))))))))));
+
+
+                //Pending timeouts of the chain
+                let mut timeouts = TimeoutMap::default();
+                //Normal 1s TTL Timer, the first tick follows after 1s
+                let mut interval = interval_at(Instant::now() + Duration::from_secs(1), Duration::from_secs(1));
+                //Resolved services waiting to pass through the chain as Event::Discovered
+                let mut discovered = VecDeque::new();
+
+                loop {
+                    let result = select! {
+                        //A resolved service passes through the chain before it is yielded
+                        _ = std::future::ready(()), if !discovered.is_empty() => {
+                            Event::Discovered(discovered.pop_front().expect("Should contain a Service"))
+                        }
+                        //Received a message on the Socket
+                        f = frame.next() => {
+                            self.statistics.messages_received += 1;
+
+                            #[cfg(feature = "metrics")]
+                            metrics::counter!("mdns_messages_received_total").increment(1);
+
+                            match f {
+                                Some(Ok((_, addr))) if self.source_address_check && !source_address_check(&addr.ip()) => {
+                                    debug!("Ignoring message from {} outside the local link", addr);
+                                    continue;
+                                }
+                                Some(Ok((bytes, addr))) => {
+                                    if self.packet_dump {
+                                        Self::dump_packet("Received", &bytes, addr);
+                                    }
+
+                                    match MdnsMessage::try_from(&bytes[..]) {
+                                        Ok(message) => {
+                                            //Goodbyes of browsed services are handled after the message
+                                            if let Some(q) = &self.query {
+                                                for instance in removed_instances(&message, q) {
+                                                    self.tx.send(Event::ServiceRemoved(instance)).expect("Failed to send with Tx");
+                                                }
+                                            }
+
+                                            for update in txt_changes(&message, &mut self.known_txt_records) {
+                                                //Fails without subscribers, see with_txt_updated_stream()
+                                                let _ = self.txt_updates.send(update);
+                                            }
+
+                                            Event::Message(message)
+                                        }
+                                        Err(e) => {
+                                            debug!("Ignoring message from {}: {}", addr, e);
+                                            continue;
+                                        }
+                                    }
+                                }
+                                Some(Err(e)) => {
+                                    warn!("Failed to receive message: {}", e);
+                                    continue;
+                                }
+                                None => {
+                                    warn!("Socket closed");
+                                    return;
+                                }
+                            }
+                        }
+                        //Received a Command from the client
+                        c = self.rx.recv() => {
+                            c.expect("Should contain a Command")
+                        }
+                        //Close signal handler
+                        _close = tokio::signal::ctrl_c() => {
+                            debug!("Ctrl C! Closing");
+                            Event::Closing()
+                        }
+                        //A dynamic timeout has finished
+                        t = timeouts.next(), if !timeouts.is_empty() => {
+                            debug!("Timed out for {:?} ms", t);
+                            Event::TimeElapsed(t.unwrap_or_default())
+                        }
+                        //TTL 1s timer has ticked
+                        _ = interval.tick() => {
+                            Event::Ttl()
+                        }
+                    };
+
+                    //Registering a service again updates its TXT records instead of probing it again
+                    let result = match result {
+                        Event::Register(host, service, protocol, _port, txt_records) if self.is_registered(&host, &service, &protocol) => {
+                            debug!("{}.{}.{}.local is already registered, updating its TXT Records", host, service, protocol);
+                            Event::UpdateTxt(format!("{}.{}.{}.local", host, service, protocol), txt_records)
+                        }
+                        e => e,
+                    };
+
+                    //Check for specific command or signals
+                    match &result{
+                        Event::Register(host, service, protocol, port, txt_records) => {
+                            let mut builder = ServiceBuilder::default().host(host).service(service).protocol(protocol).port(*port);
+
+                            for txt in txt_records {
+                                builder = match txt.split_once('=') {
+                                    Some((key, value)) => builder.txt(key, value),
+                                    None => builder.txt_flag(txt),
+                                };
+                            }
+
+                            //An invalid service does not end the stream of the other registrations
+                            match builder.build() {
+                                Ok(service) => self.registrations.push(service),
+                                Err(reason) => warn!("Skipping invalid service {}.{}.{}.local: {}", host, service, protocol, reason),
+                            }
+                        }
+                        Event::CancelBrowse() => {
+                            if let Some(q) = self.query.take() {
+                                debug!("Browse for {} cancelled", q.name);
+
+                                timeouts.cancel(ServiceState::BrowseDelay);
+
+                                yield Service { state: ServiceState::BrowseCancelled, ..Default::default() };
+
+                                if self.registrations.is_empty() {
+                                    return;
+                                }
+                            }
+                        }
+                        Event::ServiceRemoved(instance) => {
+                            if let Ok((host, service, protocol)) = Service::parse_dns_sd(instance) {
+                                yield Service { host, service, protocol, state: ServiceState::Removed, ..Default::default() };
+                            }
+                        }
+                        Event::Discovered(service) => {
+                            yield service.clone();
+                        }
+                        Event::Closing() => {return}
+                        _ => {}
+                    }
+
+                    //Fill a Vec with new timeouts and a Vec with a queue of messages we will send with the socket
+                    let mut new_timeouts = vec![];
+                    let mut queue = vec![];
+
+
+                    let registered: Vec<bool> = self.registrations.iter().map(|r| r.state == ServiceState::Registered).collect();
+                    let conflicts: u32 = self.registrations.iter().map(|r| r.probe_conflicts).sum();
+
+                    //Execute the chain
+                    let handled = self.handle(&handler, &result, &mut new_timeouts, &mut queue).await;
+
+                    //Conflicts found by the ProbeHandler, counted before a conflict error ends the stream
+                    let new_conflicts = self.registrations.iter().map(|r| r.probe_conflicts).sum::<u32>().saturating_sub(conflicts);
+                    self.statistics.probe_conflicts += new_conflicts as u64;
+
+                    #[cfg(feature = "metrics")]
+                    metrics::counter!("mdns_probe_conflicts_total").increment(new_conflicts as u64);
+
+                    handled?;
+
+                    let s = Service::default();
+                    yield s;
+
+                    //Services which finished registering
+                    for (r, was_registered) in self.registrations.iter().zip(registered) {
+                        if r.state == ServiceState::Registered && !was_registered {
+                            yield r.clone();
+                        }
+                    }
+
+                    //Resolved services which passed the browse filter
+                    //A complete browse keeps resolving services which are announced later, until it is cancelled
+                    if let Some(q) = &mut self.query {
+                        discovered.extend(q.services.drain(..));
+                    }
+
+                    //Add the resulting timeouts from the chain to our pending timeouts
+                    for (s, t) in new_timeouts {
+                        timeouts.insert(s, t);
+                    }
+
+                    //Send the messages in the queue with our socket
+                    for (message, target) in queue{
+                        if self.packet_dump {
+                            Self::dump_packet("Sending", &message.to_bytes(), target.socket_addr());
+                        }
+                        send_message_to(&mut frame, &message, target.socket_addr()).await.expect("Should send Message");
+                        self.statistics.messages_sent += 1;
+                    }
+
+
+
+                }
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_socket_factory() {
+    use crate::{test_utils::MockUdpSocket, utility::SendTarget};
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let stream = client
+        .register(
+            "TestMachine".into(),
+            "_test".into(),
+            "_tcp".into(),
+            53000,
+            vec![],
+        )
+        .await;
+    pin_mut!(stream);
+
+    //The first probe is sent within 250ms after registering
+    while socket.sent_messages.lock().unwrap().is_empty() {
+        stream.next().await.unwrap().unwrap();
+    }
+
+    let sent = socket.sent_messages.lock().unwrap();
+
+    assert_eq!(sent[0].0, MdnsMessage::probe(&service).to_bytes());
+    assert_eq!(sent[0].1, SendTarget::Multicast.socket_addr());
+}
+
+#[test]
+fn test_drop_goodbye_repeat() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .with_goodbye_repeat_count(3);
+
+    client.registrations.push(
+        ServiceBuilder::default()
+            .host("TestMachine")
+            .service("_test")
+            .protocol("_tcp")
+            .port(53000)
+            .build()
+            .unwrap(),
+    );
+
+    drop(client);
+
+    assert_eq!(socket.sent_messages.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn test_drop_without_goodbye() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .send_goodbye_on_drop(false);
+
+    client.registrations.push(
+        ServiceBuilder::default()
+            .host("TestMachine")
+            .service("_test")
+            .protocol("_tcp")
+            .port(53000)
+            .build()
+            .unwrap(),
+    );
+
+    drop(client);
+
+    assert!(socket.sent_messages.lock().unwrap().is_empty());
+
+    //Enabled again at runtime
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .send_goodbye_on_drop(false);
+    client.set_send_goodbye_on_drop(true);
+    client.registrations.push(
+        ServiceBuilder::default()
+            .host("TestMachine")
+            .service("_test")
+            .protocol("_tcp")
+            .port(53000)
+            .build()
+            .unwrap(),
+    );
+
+    drop(client);
+
+    assert_eq!(socket.sent_messages.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_records_lookup() {
+    use crate::name::Name;
+
+    let mut client = DnsSd2::default();
+
+    client.records = vec![
+        ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        ),
+        ResourceRecord::create_srv_record(
+            "TestMachine._test._tcp.local".into(),
+            53000,
+            "TestMachine.local".into(),
+        ),
+        ResourceRecord::create_txt_record(
+            Name::new("TestMachine._test._tcp.local".into()).unwrap(),
+            vec!["key=value".into()],
+        ),
+        ResourceRecord::create_a_record(
+            Name::new("TestMachine2.local".into()).unwrap(),
+            [192, 168, 1, 3],
+        ),
+        ResourceRecord::create_ptr_record("TestMachine2".into(), "_test".into(), "_tcp".into()),
+    ];
+
+    let types: Vec<QType> = client
+        .records_for_service("testmachine")
+        .iter()
+        .map(|r| r.record_type)
+        .collect();
+
+    assert_eq!(types, vec![QType::A, QType::Srv, QType::Txt]);
+    assert_eq!(client.records_for_type(QType::A).len(), 2);
+    assert_eq!(client.records_for_name("TESTMACHINE2.local").len(), 1);
+    assert_eq!(client.records_for_name("_test._tcp.local").len(), 1);
+}
+
+#[tokio::test]
+async fn test_observe() {
+    use crate::test_utils::MockUdpSocket;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let socket = MockUdpSocket::default();
+    let addr = "192.168.1.2:5353".parse().unwrap();
+
+    let announcement = MdnsMessage::announce(&service);
+    let goodbye = MdnsMessage::goodbye(&service);
+
+    socket.receive(announcement.to_bytes(), addr);
+    socket.receive(vec![0xFF; 3], addr);
+    socket.receive(goodbye.to_bytes(), addr);
+
+    let expected: Vec<Vec<u8>> = announcement
+        .answers
+        .iter()
+        .chain(&announcement.additionals)
+        .chain(&goodbye.answers)
+        .map(|r| r.to_bytes().unwrap())
+        .collect();
+
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let observed: Vec<Vec<u8>> = client
+        .observe()
+        .take(expected.len())
+        .map(|r| r.unwrap().to_bytes().unwrap())
+        .collect()
+        .await;
+
+    assert_eq!(observed, expected);
+    assert!(socket.sent_messages.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_update_txt_records() {
+    let mut client = DnsSd2::default();
+
+    //Nothing registered yet
+    assert!(matches!(
+        client.update_txt_records("TestMachine", vec!["version=2".into()]),
+        Err(MdnsError::ServiceRemoved {})
+    ));
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .txt("version", "1")
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+    client.registrations.push(service);
+
+    assert!(client
+        .update_txt_records("OtherMachine", vec!["version=2".into()])
+        .is_err());
+    client
+        .update_txt_records("TestMachine", vec!["version=2".into()])
+        .unwrap();
+
+    let event = client.rx.try_recv().unwrap();
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+    block_on(client.handle(
+        &AnnouncementHandler::default(),
+        &event,
+        &mut timeouts,
+        &mut queue,
+    ))
+    .unwrap();
+
+    assert_eq!(client.registrations[0].txt_records, ["version=2"]);
+    assert_eq!(queue.len(), 1);
+
+    //Only the TXT record is announced again
+    let (message, _) = &queue[0];
+    assert_eq!(message.answers.len(), 1);
+    assert_eq!(message.answers[0].record_type, QType::Txt);
+    assert_eq!(
+        message.answers[0].name.as_str(),
+        "TestMachine._test._tcp.local"
+    );
+
+    //Length prefixed 'version=2' should be in the encoded message
+    let bytes = message.to_bytes();
+    let mut expected = vec![9];
+    expected.extend(b"version=2");
+
+    assert!(bytes.windows(expected.len()).any(|w| w == expected));
+
+    //Mark the registration as handled so dropping the client sends no goodbye
+    client.registrations.clear();
+}
+
+#[test]
+fn test_set_host_name() {
+    use crate::name::Name;
+
+    let mut client = DnsSd2::default();
+
+    for invalid in ["", "MyMachine.local", &"a".repeat(64)] {
+        assert!(matches!(
+            client.set_host_name(invalid.into()),
+            Err(MdnsError::InvalidService { .. })
+        ));
+    }
+
+    //Another host uses the name
+    client.records.push(ResourceRecord::create_a_record(
+        Name::new("OtherMachine.local".into()).unwrap(),
+        [192, 168, 1, 3],
+    ));
+    assert!(matches!(
+        client.set_host_name("OtherMachine".into()),
+        Err(MdnsError::NameAlreadyTaken {})
+    ));
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+    client.registrations.push(service);
+
+    client.set_host_name("NewMachine".into()).unwrap();
+
+    let event = client.rx.try_recv().unwrap();
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+    block_on(client.handle(
+        &AnnouncementHandler::default(),
+        &event,
+        &mut timeouts,
+        &mut queue,
+    ))
+    .unwrap();
+
+    //Goodbye for the old name, the service is probed again with the new name
+    assert_eq!(queue.len(), 1);
+    assert!(queue[0].0.answers.iter().all(|r| r.is_goodbye()));
+    assert_eq!(
+        queue[0].0.answers[1].name.as_str(),
+        "TestMachine._test._tcp.local"
+    );
+
+    assert_eq!(client.registrations[0].host, "NewMachine");
+    assert_eq!(client.registrations[0].state, ServiceState::Prelude);
+    assert_eq!(client.registrations[0].elapsed_since_registered(), None);
+
+    //Mark the registration as handled so dropping the client sends no goodbye
+    client.registrations.clear();
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_register_many() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let options = ["_http", "_ssh", "_printer"].map(|service| RegistrationOptions {
+        host: "TestMachine".into(),
+        service: service.into(),
+        protocol: "_tcp".into(),
+        port: 53000,
+        txt_records: vec![],
+    });
+
+    let stream = client.register_many(options.to_vec()).await;
+    pin_mut!(stream);
+
+    let mut added = vec![];
+    while added.len() < options.len() {
+        match stream.next().await {
+            Some(Ok(ServiceEvent::Added(s))) => added.push(s.service),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    added.sort();
+    assert_eq!(added, ["_http", "_printer", "_ssh"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_register_invalid() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .send_goodbye_on_drop(false);
+    let tx = client.tx.clone();
+
+    {
+        let stream = client
+            .register(
+                "TestMachine".into(),
+                "_test".into(),
+                "_tcp".into(),
+                53000,
+                vec![],
+            )
+            .await;
+        pin_mut!(stream);
+
+        while stream.next().await.unwrap().unwrap().state != ServiceState::Registered {}
+
+        //The invalid protocol is skipped, the stream keeps running
+        tx.send(Event::Register(
+            "TestMachine".into(),
+            "_other".into(),
+            "_xyz".into(),
+            53000,
+            vec![],
+        ))
+        .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            assert!(stream.next().await.unwrap().is_ok());
+        }
+    }
+
+    assert_eq!(client.registrations.len(), 1);
+    assert_eq!(client.registrations[0].state, ServiceState::Registered);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_register_again() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+    let tx = client.tx.clone();
+
+    let other = ServiceBuilder::default()
+        .host("OtherMachine")
+        .service("_other")
+        .protocol("_udp")
+        .port(53001)
+        .build()
+        .unwrap();
+
+    {
+        let stream = client
+            .register(
+                "TestMachine".into(),
+                "_test".into(),
+                "_tcp".into(),
+                53000,
+                vec![],
+            )
+            .await;
+        pin_mut!(stream);
+
+        while stream.next().await.unwrap().unwrap().state != ServiceState::Registered {}
+
+        //Register a second service while the first one is running
+        tx.send(Event::Register(
+            "OtherMachine".into(),
+            "_other".into(),
+            "_udp".into(),
+            53001,
+            vec![],
+        ))
+        .unwrap();
+
+        //The second service is probed
+        let probe = MdnsMessage::probe(&other).to_bytes();
+        while !socket
+            .sent_messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(bytes, _)| *bytes == probe)
+        {
+            stream.next().await.unwrap().unwrap();
+        }
+
+        //Registering the first service again only updates its TXT records
+        tx.send(Event::Register(
+            "TestMachine".into(),
+            "_test".into(),
+            "_tcp".into(),
+            53000,
+            vec!["version=2".into()],
+        ))
+        .unwrap();
+        stream.next().await.unwrap().unwrap();
+    }
+
+    assert_eq!(client.registrations.len(), 2);
+    assert_eq!(client.registrations[0].state, ServiceState::Registered);
+    assert_eq!(client.registrations[0].txt_records, ["version=2"]);
+    assert_ne!(client.registrations[1].state, ServiceState::Prelude);
+    assert_ne!(client.registrations[1].state, ServiceState::Registered);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_register_again_same_host() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+    let tx = client.tx.clone();
+
+    {
+        let stream = client
+            .register(
+                "TestMachine".into(),
+                "_http".into(),
+                "_tcp".into(),
+                53000,
+                vec!["version=1".into()],
+            )
+            .await;
+        pin_mut!(stream);
+
+        while stream.next().await.unwrap().unwrap().state != ServiceState::Registered {}
+
+        //A second service of the same host
+        tx.send(Event::Register(
+            "TestMachine".into(),
+            "_ssh".into(),
+            "_tcp".into(),
+            53001,
+            vec!["version=1".into()],
+        ))
+        .unwrap();
+
+        while stream.next().await.unwrap().unwrap().service != "_ssh" {}
+
+        //Registering the second service again, in another case, only updates its own TXT records
+        tx.send(Event::Register(
+            "testmachine".into(),
+            "_ssh".into(),
+            "_tcp".into(),
+            53001,
+            vec!["version=2".into()],
+        ))
+        .unwrap();
+        stream.next().await.unwrap().unwrap();
+    }
+
+    assert_eq!(client.registrations.len(), 2);
+    assert_eq!(client.registrations[0].txt_records, ["version=1"]);
+    assert_eq!(client.registrations[1].txt_records, ["version=2"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_register_ephemeral() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let options = RegistrationOptions {
+        host: "TestMachine".into(),
+        service: "_test".into(),
+        protocol: "_tcp".into(),
+        port: 53000,
+        txt_records: vec![],
+    };
+
+    let start = Instant::now();
+    client
+        .register_ephemeral(options, Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    assert!(start.elapsed() >= Duration::from_secs(5));
+
+    //The goodbye packet is sent last
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let sent = socket.sent_messages.lock().unwrap();
+    assert_eq!(
+        sent.last().unwrap().0,
+        MdnsMessage::goodbye(&service).to_bytes()
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_log_level() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    client
+        .set_log_level(log::LevelFilter::Trace)
+        .enable_packet_dump(true);
+
+    {
+        let stream = client
+            .register(
+                "TestMachine".into(),
+                "_test".into(),
+                "_tcp".into(),
+                53000,
+                vec![],
+            )
+            .await;
+        pin_mut!(stream);
+
+        while socket.sent_messages.lock().unwrap().is_empty() {
+            stream.next().await.unwrap().unwrap();
+        }
+
+        assert_eq!(log::max_level(), log::LevelFilter::Trace);
+    }
+
+    client
+        .set_log_level(log::LevelFilter::Info)
+        .enable_packet_dump(false);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_statistics() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    assert_eq!(client.statistics(), BrowseStatistics::default());
+
+    {
+        let stream = client
+            .register(
+                "TestMachine".into(),
+                "_test".into(),
+                "_tcp".into(),
+                53000,
+                vec![],
+            )
+            .await;
+        pin_mut!(stream);
+
+        while let Some(s) = stream.next().await {
+            if s.unwrap().state == ServiceState::Registered {
+                break;
+            }
+        }
+    }
+
+    let statistics = client.statistics();
+
+    //Two probes and the first announcement, the second announcement is sent after the service is yielded
+    assert!(statistics.messages_sent >= 3);
+    assert_eq!(statistics.active_registrations, 1);
+    assert_eq!(statistics.probe_conflicts, 0);
+    assert!(statistics.uptime_seconds >= 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_lookup() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let socket = MockUdpSocket::default();
+    let addr = "192.168.1.2:5353".parse().unwrap();
+
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    //A response to another question is skipped
+    let mut other = MdnsMessage::default();
+    other.header.qr = true;
+    other.answers.push(ResourceRecord::create_a_record(
+        Name::new("OtherMachine.local".into()).unwrap(),
+        [192, 168, 1, 3],
+    ));
+    other.header.ancount = 1;
+    socket.receive(other.to_bytes(), addr);
+
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.answers.push(ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    ));
+    response.header.ancount = 1;
+    socket.receive(response.to_bytes(), addr);
+
+    let records = client.lookup("testmachine.local", QType::A).await.unwrap();
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].to_bytes(), response.answers[0].to_bytes());
+    assert_eq!(
+        socket.sent_messages.lock().unwrap()[0].0,
+        MdnsMessage::query_type("testmachine.local", QType::A).to_bytes()
+    );
+
+    //Without answers the lookup times out
+    let mut client = client.with_lookup_timeout(Duration::from_millis(500));
+    let start = Instant::now();
+
+    assert!(matches!(
+        client.lookup("TestMachine.local", QType::Txt).await,
+        Err(MdnsError::Timeout {})
+    ));
+    assert_eq!(start.elapsed(), Duration::from_millis(500));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_browse_for() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    //Without services the browse completes with an empty snapshot
+    //The first query is sent after a random delay of up to 500ms
+    let start = Instant::now();
+    let services = client
+        .browse_for("_test._tcp.local".into(), Duration::from_millis(600))
+        .await
+        .unwrap();
+
+    assert!(services.is_empty());
+    assert_eq!(start.elapsed(), Duration::from_millis(600));
+    assert!(!socket.sent_messages.lock().unwrap().is_empty());
+
+    //Services are resolved with the cached address records of their host
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    assert!(client.resolve(service.clone()).is_none());
+
+    client.records.push(ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    ));
+
+    let resolved = client.resolve(service).unwrap();
+    assert_eq!(resolved.ip_v4, Ipv4Addr::new(192, 168, 1, 2));
+    assert_eq!(resolved.ip_v6, None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_browse_for_srv_target() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let socket = MockUdpSocket::default();
+    let responder = socket.clone();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    //The SRV target differs from the host of the instance name
+    let instance = "Office Printer._test._tcp.local".to_string();
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.answers = vec![
+        ResourceRecord::create_ptr_record("Office Printer".into(), "_test".into(), "_tcp".into()),
+        ResourceRecord::create_srv_record(instance.clone(), 53000, "printer.local".into()),
+        ResourceRecord::create_txt_record(Name::new(instance).unwrap(), vec![]),
+        ResourceRecord::create_a_record(
+            Name::new("printer.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        ),
+        ResourceRecord::create_aaaa_record_from_ipv6(
+            Name::new("printer.local".into()).unwrap(),
+            Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2),
+        ),
+    ];
+    response.fix_counts();
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        responder.receive(response.to_bytes(), "192.168.1.2:5353".parse().unwrap());
+    });
+
+    let services = client
+        .browse_for("_test._tcp.local".into(), Duration::from_secs(2))
+        .await
+        .unwrap();
+
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].service.host, "Office Printer");
+    assert_eq!(services[0].service.target.as_deref(), Some("printer.local"));
+    assert_eq!(services[0].ip_v4, Ipv4Addr::new(192, 168, 1, 2));
+    assert_eq!(
+        services[0].ip_v6,
+        Some(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2))
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_browse_for_removed() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let socket = MockUdpSocket::default();
+    let responder = socket.clone();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let instance = "TestMachine._test._tcp.local".to_string();
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.answers = vec![
+        ResourceRecord::create_ptr_record("TestMachine".into(), "_test".into(), "_tcp".into()),
+        ResourceRecord::create_srv_record(instance.clone(), 53000, "TestMachine.local".into()),
+        ResourceRecord::create_txt_record(Name::new(instance).unwrap(), vec![]),
+        ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        ),
+    ];
+    response.fix_counts();
+
+    //The goodbye packet names the instance in a different case
+    let mut goodbye =
+        ResourceRecord::create_ptr_record("testmachine".into(), "_test".into(), "_tcp".into());
+    goodbye.ttl = 0;
+    let mut goodbye_response = MdnsMessage::default();
+    goodbye_response.header.qr = true;
+    goodbye_response.add_answer(goodbye);
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        responder.receive(response.to_bytes(), "192.168.1.2:5353".parse().unwrap());
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        responder.receive(
+            goodbye_response.to_bytes(),
+            "192.168.1.2:5353".parse().unwrap(),
+        );
+    });
+
+    let services = client
+        .browse_for("_test._tcp.local".into(), Duration::from_secs(2))
+        .await
+        .unwrap();
+
+    assert!(services.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_browse_clients() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    //PTR, SRV, TXT and A records of a service
+    let response = |host: &str| {
+        let instance = format!("{}._test._tcp.local", host);
+        let target = format!("{}.local", host);
+
+        let mut message = MdnsMessage::default();
+        message.header.qr = true;
+        message.answers = vec![
+            ResourceRecord::create_ptr_record(host.into(), "_test".into(), "_tcp".into()),
+            ResourceRecord::create_srv_record(instance.clone(), 53000, target.clone()),
+            ResourceRecord::create_txt_record(Name::new(instance).unwrap(), vec![]),
+            ResourceRecord::create_a_record(Name::new(target).unwrap(), [192, 168, 1, 2]),
+        ];
+        message.fix_counts();
+        message
+    };
+
+    //The first interface sees one service, the second interface sees both
+    let interfaces = [vec!["TestMachine"], vec!["TestMachine", "OtherMachine"]].map(|hosts| {
+        let socket = MockUdpSocket::default();
+        let responder = socket.clone();
+
+        //Respond after the first query was sent
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(600)).await;
+
+            for host in hosts {
+                responder.receive(
+                    response(host).to_bytes(),
+                    "192.168.1.2:5353".parse().unwrap(),
+                );
+            }
+        });
+
+        DnsSd2::default().with_socket_factory(socket.factory())
+    });
+
+    let stream = DnsSd2::browse_clients(interfaces.into(), "_test._tcp.local".into());
+    pin_mut!(stream);
+
+    //The browse keeps running, stop once both services are found
+    let mut found = vec![];
+    while found.len() < 2 {
+        match stream.next().await.unwrap().unwrap() {
+            ServiceEvent::Found(s) => found.push(s.host),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    found.sort();
+    assert_eq!(found, ["OtherMachine", "TestMachine"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_cancel_browse() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+    let tx = client.tx.clone();
+
+    {
+        let stream = client.browse("_test._tcp.local".into()).await;
+        pin_mut!(stream);
+
+        while socket.sent_messages.lock().unwrap().is_empty() {
+            stream.next().await.unwrap().unwrap();
+        }
+
+        //The stream ends after the cancelled state
+        tx.send(Event::CancelBrowse()).unwrap();
+
+        let mut last = None;
+        while let Some(s) = stream.next().await {
+            last = Some(s.unwrap().state);
+        }
+        assert_eq!(last, Some(ServiceState::BrowseCancelled));
+    }
+
+    assert!(client.query.is_none());
+
+    //A new browse starts fresh
+    let sent = socket.sent_messages.lock().unwrap().len();
+    let stream = client.browse("_test._tcp.local".into()).await;
+    pin_mut!(stream);
+
+    while socket.sent_messages.lock().unwrap().len() == sent {
+        stream.next().await.unwrap().unwrap();
+    }
+
+    let instance = "TestMachine._test._tcp.local".to_string();
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response
+        .add_answer(ResourceRecord::create_ptr_record(
+            "TestMachine".into(),
+            "_test".into(),
+            "_tcp".into(),
+        ))
+        .add_answer(ResourceRecord::create_srv_record(
+            instance.clone(),
+            53000,
+            "TestMachine.local".into(),
+        ))
+        .add_answer(ResourceRecord::create_txt_record(
+            Name::new(instance).unwrap(),
+            vec![],
+        ))
+        .add_answer(ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        ));
+    socket.receive(response.to_bytes(), "192.168.1.2:5353".parse().unwrap());
+
+    let found = loop {
+        let s = stream.next().await.unwrap().unwrap();
+        if !s.host.is_empty() {
+            break s.host;
+        }
+    };
+    assert_eq!(found, "TestMachine");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_continuous_query() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    client
+        .tx
+        .send(Event::Browse(
+            "_test._tcp.local".into(),
+            BrowseFilter::default(),
+            0,
+        ))
+        .unwrap();
+
+    let stream = client.init().await;
+    pin_mut!(stream);
+
+    //Messages are sent after the chain's results are yielded, on the next poll
+    let start = Instant::now();
+    let mut handled = start;
+    let mut sent = vec![];
+    while sent.len() < 4 {
+        stream.next().await.unwrap().unwrap();
+
+        if socket.sent_messages.lock().unwrap().len() > sent.len() {
+            sent.push((handled - start).as_millis());
+        }
+        handled = Instant::now();
+    }
+
+    //The first query, then refresh queries after 1s, 2s and 4s
+    assert_eq!(sent, [0, 1000, 3000, 7000]);
+
+    let query = MdnsMessage::query("_test._tcp.local").to_bytes();
+    assert!(socket
+        .sent_messages
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|(bytes, _)| *bytes == query));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_browse_with_registration() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let socket = MockUdpSocket::default();
+    let responder = socket.clone();
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .send_goodbye_on_drop(false);
+
+    //Browse for another service while registering ours
+    client
+        .tx
+        .send(Event::Browse(
+            "_other._tcp.local".into(),
+            BrowseFilter::default(),
+            0,
+        ))
+        .unwrap();
+
+    let instance = "OtherMachine._other._tcp.local".to_string();
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response
+        .add_answer(ResourceRecord::create_ptr_record(
+            "OtherMachine".into(),
+            "_other".into(),
+            "_tcp".into(),
+        ))
+        .add_answer(ResourceRecord::create_srv_record(
+            instance.clone(),
+            53000,
+            "OtherMachine.local".into(),
+        ))
+        .add_answer(ResourceRecord::create_txt_record(
+            Name::new(instance).unwrap(),
+            vec![],
+        ))
+        .add_answer(ResourceRecord::create_a_record(
+            Name::new("OtherMachine.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        ));
+
+    //Respond after the first query was sent, the browse completes before our service is registered
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        responder.receive(response.to_bytes(), "192.168.1.2:5353".parse().unwrap());
+    });
+
+    let stream = client
+        .register(
+            "TestMachine".into(),
+            "_test".into(),
+            "_tcp".into(),
+            53000,
+            vec![],
+        )
+        .await;
+    pin_mut!(stream);
+
+    let mut found = vec![];
+    loop {
+        let s = stream.next().await.unwrap().unwrap();
+
+        if s.state == ServiceState::Registered {
+            assert_eq!(s.host, "TestMachine");
+            break;
+        } else if !s.host.is_empty() {
+            found.push(s.host);
+        }
+    }
+    assert_eq!(found, ["OtherMachine"]);
+
+    //The registration keeps running
+    assert!(stream.next().await.unwrap().is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_is_name_available() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    //Without responses the name is available after probing twice
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    assert!(client.is_name_available("TestMachine").await.unwrap());
+
+    let probe = MdnsMessage::probe(&Service {
+        host: "TestMachine".into(),
+        ..Default::default()
+    });
+    let sent = socket.sent_messages.lock().unwrap().clone();
+    assert_eq!(sent.len(), 2);
+    assert!(sent.iter().all(|(bytes, _)| *bytes == probe.to_bytes()));
+
+    //Another host responds with records for the name
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.answers.push(ResourceRecord::create_a_record(
+        Name::new("testmachine.local".into()).unwrap(),
+        [192, 168, 1, 3],
+    ));
+    response.header.ancount = 1;
+    socket.receive(response.to_bytes(), "192.168.1.3:5353".parse().unwrap());
+
+    assert!(!client.is_name_available("TestMachine").await.unwrap());
+    assert_eq!(client.statistics().probe_conflicts, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_probe_conflict() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .send_goodbye_on_drop(false);
+
+    {
+        let stream = client
+            .register(
+                "TestMachine".into(),
+                "_test".into(),
+                "_tcp".into(),
+                53000,
+                vec![],
+            )
+            .await;
+        pin_mut!(stream);
+
+        while socket.sent_messages.lock().unwrap().is_empty() {
+            stream.next().await.unwrap().unwrap();
+        }
+
+        //Another host defends the instance name with its own SRV record
+        let mut response = MdnsMessage::default();
+        response.header.qr = true;
+        response.add_answer(ResourceRecord::create_srv_record(
+            "TestMachine._test._tcp.local".into(),
+            8080,
+            "OtherMachine.local".into(),
+        ));
+        socket.receive(response.to_bytes(), "192.168.1.3:5353".parse().unwrap());
+
+        let error = loop {
+            if let Err(e) = stream.next().await.unwrap() {
+                break e;
+            }
+        };
+        assert!(matches!(error, MdnsError::NameAlreadyTaken {}));
+    }
+
+    assert_eq!(client.statistics().probe_conflicts, 1);
+}
+
+#[cfg(feature = "metrics")]
+#[tokio::test(start_paused = true)]
+async fn test_probe_conflict_metrics() {
+    use crate::test_utils::MockUdpSocket;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    //Another host defends the instance name with its own SRV record
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.add_answer(ResourceRecord::create_srv_record(
+        "TestMachine._test._tcp.local".into(),
+        8080,
+        "OtherMachine.local".into(),
+    ));
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .send_goodbye_on_drop(false)
+        .with_metrics();
+
+    {
+        let stream = client
+            .register(
+                "TestMachine".into(),
+                "_test".into(),
+                "_tcp".into(),
+                53000,
+                vec![],
+            )
+            .await;
+        pin_mut!(stream);
+
+        while socket.sent_messages.lock().unwrap().is_empty() {
+            stream.next().await.unwrap().unwrap();
+        }
+
+        socket.receive(response.to_bytes(), "192.168.1.3:5353".parse().unwrap());
+
+        while let Some(Ok(_)) = stream.next().await {}
+    }
+
+    let conflicts = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find(|(key, _, _, _)| key.key().name() == "mdns_probe_conflicts_total")
+        .map(|(_, _, _, value)| value);
+
+    assert_eq!(conflicts, Some(DebugValue::Counter(1)));
+}
+
+#[cfg(feature = "raw-api")]
+#[tokio::test]
+async fn test_send_raw() {
+    use crate::test_utils::MockUdpSocket;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let announcement = MdnsMessage::announce(&service);
+    let addr: std::net::SocketAddr = "192.168.1.2:5353".parse().unwrap();
+
+    client.send_raw(announcement.clone()).await.unwrap();
+    client
+        .send_raw_unicast(announcement.clone(), addr)
+        .await
+        .unwrap();
+
+    //Inconsistent messages are not sent
+    let mut invalid = announcement.clone();
+    invalid.header.ancount += 1;
+    assert!(matches!(
+        client.send_raw(invalid).await,
+        Err(MdnsError::InvalidMessage {})
+    ));
+
+    assert_eq!(
+        *socket.sent_messages.lock().unwrap(),
+        vec![
+            (announcement.to_bytes(), SendTarget::Multicast.socket_addr()),
+            (announcement.to_bytes(), addr),
+        ]
+    );
+    assert_eq!(client.statistics().messages_sent, 2);
+}
+
+#[test]
+fn test_export_import_cache() {
+    use crate::name::Name;
+
+    let mut client = DnsSd2::default();
+
+    let mut fresh = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    fresh.original_ttl = 4500;
+    fresh.ttl = 4500;
+
+    let mut old = ResourceRecord::create_a_record(
+        Name::new("TestMachine2.local".into()).unwrap(),
+        [192, 168, 1, 3],
+    );
+    old.original_ttl = 120;
+    old.ttl = 60;
+
+    client.records = vec![fresh, old];
+
+    //Simulate a restart 100 seconds later
+    let entries: Vec<CacheEntry> = client
+        .export_cache()
+        .into_iter()
+        .map(|e| CacheEntry {
+            cached_at: e.cached_at - Duration::from_secs(100),
+            ..e
+        })
+        .collect();
+    assert!(entries.iter().all(|e| e.cached_at < SystemTime::now()));
+
+    let mut restarted = DnsSd2::default();
+    assert_eq!(restarted.import_cache(entries), 1);
+
+    assert_eq!(restarted.records.len(), 1);
+    assert_eq!(restarted.records[0].name.as_str(), "TestMachine.local");
+    assert_eq!(restarted.records[0].ttl, 4400);
+}
+
+#[test]
+fn test_import_cache_duplicates() {
+    use crate::name::Name;
+
+    let mut client = DnsSd2::default();
+
+    let mut record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    record.original_ttl = 4500;
+    record.ttl = 4500;
+    client.records = vec![record];
+
+    //Importing a record which is already cached keeps a single entry
+    let entries = client.export_cache();
+    assert_eq!(client.import_cache(entries.clone()), 1);
+    assert_eq!(client.import_cache(entries), 1);
+
+    assert_eq!(client.records.len(), 1);
+    assert_eq!(client.records[0].ttl, 4500);
+}
+
+#[test]
+fn test_import_hosts_file() {
+    use crate::record::STATIC_TTL;
+
+    let path = std::env::temp_dir().join(format!("dns_sd2_hosts_{}", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Static entries\n\
+         127.0.0.1\tlocalhost Loopback.local\n\
+         ::1 localhost ip6-localhost\n\
+         192.168.1.2\tprinter.local  scanner.LOCAL\tprinter.example.com # office\n\
+         fe80::1 nas.local\n\
+         #10.0.0.1 commented.local\n\
+         not-an-ip other.local\n",
+    )
+    .unwrap();
+
+    let mut client = DnsSd2::default();
+    let imported = client.import_hosts_file(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(imported.unwrap(), 3);
+    assert!(client.records.iter().all(|r| r.ttl == STATIC_TTL));
+
+    let names = |qtype| {
+        client
+            .records_for_type(qtype)
+            .iter()
+            .map(|r| r.name.as_str().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(QType::A), ["printer.local", "scanner.LOCAL"]);
+    assert_eq!(names(QType::Aaaa), ["nas.local"]);
+
+    assert!(matches!(
+        client.import_hosts_file(&path),
+        Err(MdnsError::HostsFile { .. })
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_record_callbacks() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+    use std::sync::{Arc, Mutex};
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let changes = Arc::new(Mutex::new(vec![]));
+
+    for callback in ["first", "second"] {
+        let added = changes.clone();
+        let removed = changes.clone();
+
+        client
+            .on_record_added(move |r| {
+                added
+                    .lock()
+                    .unwrap()
+                    .push((callback, "added", r.name.as_str().to_string()))
+            })
+            .on_record_removed(move |r| {
+                removed
+                    .lock()
+                    .unwrap()
+                    .push((callback, "removed", r.name.as_str().to_string()))
+            });
+    }
+
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.answers.push(ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    ));
+    response.header.ancount = 1;
+    socket.receive(response.to_bytes(), "192.168.1.2:5353".parse().unwrap());
+
+    //The record is cached and expires after its TTL of 60 seconds
+    client
+        .browse_for("_test._tcp.local".into(), Duration::from_secs(90))
+        .await
+        .unwrap();
+
+    let name = "TestMachine.local".to_string();
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            ("first", "added", name.clone()),
+            ("second", "added", name.clone()),
+            ("first", "removed", name.clone()),
+            ("second", "removed", name),
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_service_removed() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let socket = MockUdpSocket::default();
+    let responder = socket.clone();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let service = Service {
+        host: "TestMachine".into(),
+        service: "_test".into(),
+        protocol: "_tcp".into(),
+        port: 53000,
+        ..Default::default()
+    };
+
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.answers = vec![
+        ResourceRecord::create_ptr_record("TestMachine".into(), "_test".into(), "_tcp".into()),
+        ResourceRecord::create_srv_record(
+            "TestMachine._test._tcp.local".into(),
+            53000,
+            "TestMachine.local".into(),
+        ),
+        ResourceRecord::create_txt_record(
+            Name::new("TestMachine._test._tcp.local".into()).unwrap(),
+            vec![],
+        ),
+        ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        ),
+    ];
+    response.fix_counts();
+
+    //Respond after the first query was sent, then say goodbye before the browse completes
+    tokio::spawn(async move {
+        let addr: std::net::SocketAddr = "192.168.1.2:5353".parse().unwrap();
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        responder.receive(response.to_bytes(), addr);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        responder.receive(MdnsMessage::goodbye(&service).to_bytes(), addr);
+    });
+
+    {
+        let stream = client.browse("_test._tcp.local".into()).await;
+        pin_mut!(stream);
+
+        let mut events = vec![];
+        while events.len() < 2 {
+            let s = stream.next().await.unwrap().unwrap();
+            if !s.host.is_empty() {
+                events.push((s.to_dns_sd_string(), s.state));
+            }
+        }
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].0, "TestMachine._test._tcp.local");
+        assert_eq!(
+            events[1],
+            ("TestMachine._test._tcp.local".into(), ServiceState::Removed)
+        );
+    }
+
+    //The service is no longer part of the query
+    assert!(client.query.as_ref().unwrap().services.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_txt_updated_stream() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+    use futures::FutureExt;
+
+    let socket = MockUdpSocket::default();
+    let responder = socket.clone();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let updates = client.with_txt_updated_stream();
+    pin_mut!(updates);
+
+    let txt = |txt_records: Vec<String>| {
+        let mut message = MdnsMessage::default();
+        message.header.qr = true;
+        message.answers.push(ResourceRecord::create_txt_record(
+            Name::new("TestMachine._test._tcp.local".into()).unwrap(),
+            txt_records,
+        ));
+        message.fix_counts();
+        message.to_bytes()
+    };
+
+    //The same TXT records twice, then a change
+    tokio::spawn(async move {
+        let addr: std::net::SocketAddr = "192.168.1.2:5353".parse().unwrap();
+
+        for txt_records in [vec!["version=1"], vec!["version=1"], vec!["version=2"]] {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            responder.receive(
+                txt(txt_records.into_iter().map(String::from).collect()),
+                addr,
+            );
+        }
+    });
+
+    client
+        .browse_for("_test._tcp.local".into(), Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        updates.next().await,
+        Some((
+            "TestMachine._test._tcp.local".to_string(),
+            vec!["version=2".to_string()]
+        ))
+    );
+    assert!(updates.next().now_or_never().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_discovered() {
+    use crate::test_utils::MockUdpSocket;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default().with_socket_factory(socket.factory());
+
+    let service = Service {
+        host: "TestMachine".into(),
+        service: "_test".into(),
+        protocol: "_tcp".into(),
+        ..Default::default()
+    };
+    client.tx.send(Event::Discovered(service)).unwrap();
+
+    let stream = client.init().await;
+    pin_mut!(stream);
+
+    //The event passes through the chain and the service is yielded
+    let mut found = None;
+    while found.is_none() {
+        let s = stream.next().await.unwrap().unwrap();
+        found = (!s.host.is_empty()).then_some(s);
+    }
+
+    assert_eq!(
+        found.unwrap().to_dns_sd_string(),
+        "TestMachine._test._tcp.local"
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_initial_records() {
+    use crate::{name::Name, test_utils::MockUdpSocket};
+
+    let record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+
+    //Goodbyes and records with more TTL left than they started with are skipped
+    let mut stale = record.clone();
+    stale.ttl = stale.original_ttl + 1;
+
+    let socket = MockUdpSocket::default();
+    let mut client = DnsSd2::default()
+        .with_socket_factory(socket.factory())
+        .with_initial_records(vec![record.clone(), record.as_goodbye(), stale]);
+
+    assert_eq!(client.records.len(), 1);
+
+    //Answered from the cache without a query
+    let records = client.lookup("TestMachine.local", QType::A).await.unwrap();
+
+    assert_eq!(records, vec![record]);
+    assert!(socket.sent_messages.lock().unwrap().is_empty());
 }
@@ -4,7 +4,15 @@ use crate::{
     question::{QClass, QType, Question},
     record::ResourceRecord,
     service::Service,
+    utility::local_ipv6_addresses,
+    MdnsError,
 };
+use bytes::{BufMut, Bytes, BytesMut};
+use packed_struct::PackedStruct;
+use std::net::Ipv6Addr;
+
+/// Maximum size of a message without fragmentation
+pub const MAX_MESSAGE_SIZE: usize = 512;
 
 /// Message struct for an MDNS Message
 ///
@@ -24,7 +32,7 @@ use crate::{
 ///
 /// let message = MdnsMessage::default();
 /// ```
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct MdnsMessage {
     ///Header        See Header.rs
     pub header: Header,
@@ -58,7 +66,70 @@ pub struct MdnsMessage {
 }
 
 impl MdnsMessage {
+    /// Parse a MdnsMessage from the bytes of a received UDP packet
+    ///
+    /// Returns [`MdnsError::InvalidMessage`] if the packet is malformed or has a non-zero ID
+    ///
+    /// [RFC6762 Section 18.1 - ID (Query Identifier)](https://www.rfc-editor.org/rfc/rfc6762#section-18.1)
+    ///
+    /// Questions and records of a type this crate does not know are skipped,
+    /// the header counts are set to the number of parsed questions and records
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dns_sd2::{message::MdnsMessage, service::Service};
+    ///
+    /// let bytes = MdnsMessage::announce(&Service::default()).to_bytes();
+    ///
+    /// let message = MdnsMessage::parse(&bytes).unwrap();
+    ///
+    /// assert!(message.header.qr);
+    /// ```
+    pub fn parse(bytes: &[u8]) -> Result<MdnsMessage, MdnsError> {
+        //HEADER
+        let header = Header::from_bytes(bytes)?;
+        let mut offset = 12;
+
+        //Mdns messages always have an ID of zero
+        if header.id != 0 {
+            return Err(MdnsError::InvalidMessage {});
+        }
+
+        let mut message = MdnsMessage {
+            header,
+            ..Default::default()
+        };
+
+        //QUESTIONS
+        for _ in 0..message.header.qdcount {
+            let (question, next) = Question::parse(bytes, offset)?;
+            message.questions.extend(question);
+            offset = next;
+        }
+
+        //ANSWERS, AUTHORITIES and ADDITIONALS
+        for (count, section) in [
+            (message.header.ancount, &mut message.answers),
+            (message.header.nscount, &mut message.authorities),
+            (message.header.arcount, &mut message.additionals),
+        ] {
+            for _ in 0..count {
+                let (record, next) = ResourceRecord::parse(bytes, offset)?;
+                section.extend(record);
+                offset = next;
+            }
+        }
+
+        message.fix_counts();
+
+        Ok(message)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
+        //Inconsistencies are logged by validate, the message is serialized as is
+        let _ = self.validate();
+
         let mut bytes: Vec<u8> = vec![];
 
         //HEADER
@@ -93,6 +164,259 @@ impl MdnsMessage {
         bytes
     }
 
+    /// Encode this message into a single buffer, see [`MdnsMessage::write_to()`]
+    ///
+    /// Equal to [`MdnsMessage::to_bytes()`] without allocating for each question and record,
+    /// used to send messages, see [`crate::utility::send_message_to()`]
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(MAX_MESSAGE_SIZE);
+        self.write_to(&mut buf);
+        buf.freeze()
+    }
+
+    /// Write the encoded message to `buf`
+    ///
+    /// Records without RDATA are skipped, like in [`MdnsMessage::to_bytes()`]
+    pub fn write_to(&self, buf: &mut BytesMut) {
+        //Inconsistencies are logged by validate, the message is serialized as is
+        let _ = self.validate();
+
+        //HEADER
+        buf.put_slice(&self.header.pack().expect("Failed to pack Header"));
+
+        //QUESTIONS
+        for question in &self.questions {
+            question.write_to(buf);
+        }
+
+        //ANSWERS, AUTHORITIES and ADDITIONALS
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            record.write_to(buf);
+        }
+    }
+
+    /// Whether the encoded message is larger than `mtu` octets
+    pub fn would_exceed_mtu(&self, mtu: usize) -> bool {
+        self.to_bytes().len() > mtu
+    }
+
+    /// Whether the encoded message is larger than the DNS over UDP limit of [`MAX_MESSAGE_SIZE`]
+    ///
+    /// [RFC1035 Section 4.2.1 - UDP usage](https://www.rfc-editor.org/rfc/rfc1035#section-4.2.1)
+    pub fn would_exceed_dns_limit(&self) -> bool {
+        self.would_exceed_mtu(MAX_MESSAGE_SIZE)
+    }
+
+    /// Estimate the encoded size of this message without serializing it
+    ///
+    /// Sums the header, the names and fixed fields, and the `rdlength` of each record with RDATA.
+    /// Equal to the length of [`MdnsMessage::to_bytes()`] as long as the `rdlength` fields are up to date
+    pub fn byte_count(&self) -> usize {
+        //Header is 12 octets, questions have 4 octets for type and class
+        //and records 10 octets for type, class, ttl and rdlength
+        let questions: usize = self
+            .questions
+            .iter()
+            .map(|q| q.name.encoded_len() + 4)
+            .sum();
+
+        let records: usize = self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+            .filter(|r| r.rdata.is_some())
+            .map(|r| r.name.encoded_len() + 10 + r.rdlength as usize)
+            .sum();
+
+        12 + questions + records
+    }
+
+    /// Verify that this message is internally consistent
+    ///
+    /// - The header counts match the number of questions and records
+    /// - All names are valid, see [`Name::is_valid()`]
+    /// - The ID is zero, [RFC6762 Section 18.1 - ID (Query Identifier)](https://www.rfc-editor.org/rfc/rfc6762#section-18.1)
+    ///
+    /// Returns [`MdnsError::InvalidMessage`] and logs the reason for the first violation
+    pub fn validate(&self) -> Result<(), MdnsError> {
+        let counts = [
+            ("qdcount", self.header.qdcount, self.questions.len()),
+            ("ancount", self.header.ancount, self.answers.len()),
+            ("nscount", self.header.nscount, self.authorities.len()),
+            ("arcount", self.header.arcount, self.additionals.len()),
+        ];
+
+        let mut names = self.questions.iter().map(|q| &q.name).chain(
+            self.answers
+                .iter()
+                .chain(&self.authorities)
+                .chain(&self.additionals)
+                .map(|r| &r.name),
+        );
+
+        let reason = if let Some((field, count, len)) = counts
+            .iter()
+            .find(|(_, count, len)| *count as usize != *len)
+        {
+            format!("header {} is {} but there are {}", field, count, len)
+        } else if let Some(name) = names.find(|n| !n.is_valid()) {
+            format!("name {} is invalid", name.as_str())
+        } else if self.header.id != 0 {
+            format!("ID is {} instead of 0", self.header.id)
+        } else {
+            return Ok(());
+        };
+
+        warn!("Invalid Mdns Message: {}", reason);
+
+        Err(MdnsError::InvalidMessage {})
+    }
+
+    /// Whether this message is a response (QR bit set)
+    pub fn is_response(&self) -> bool {
+        self.header.qr
+    }
+
+    /// Whether this message is a query (QR bit not set)
+    pub fn is_query(&self) -> bool {
+        !self.header.qr
+    }
+
+    /// Whether the responder is the authority for the records in this message (AA bit)
+    pub fn is_authoritative(&self) -> bool {
+        self.header.aa
+    }
+
+    /// Whether more known answers follow in subsequent messages (TC bit)
+    ///
+    /// [RFC6762 Section 18.5 - TC (Truncated) Bit](https://www.rfc-editor.org/rfc/rfc6762#section-18.5)
+    pub fn is_truncated(&self) -> bool {
+        self.header.tc
+    }
+
+    /// Number of questions according to the header
+    pub fn question_count(&self) -> u16 {
+        self.header.qdcount
+    }
+
+    /// Number of answers according to the header
+    pub fn answer_count(&self) -> u16 {
+        self.header.ancount
+    }
+
+    /// Number of authority records according to the header
+    pub fn authority_count(&self) -> u16 {
+        self.header.nscount
+    }
+
+    /// Number of additional records according to the header
+    pub fn additional_count(&self) -> u16 {
+        self.header.arcount
+    }
+
+    /// Whether this message holds any answers
+    pub fn has_answers(&self) -> bool {
+        !self.answers.is_empty()
+    }
+
+    /// Whether this message holds any additional records
+    pub fn has_additional_records(&self) -> bool {
+        !self.additionals.is_empty()
+    }
+
+    /// Total number of questions and records in all four sections
+    pub fn count_records(&self) -> usize {
+        self.questions.len() + self.answers.len() + self.authorities.len() + self.additionals.len()
+    }
+
+    /// Whether any answer in this message answers the question `q`
+    ///
+    /// Names are compared case-insensitively, a question of type or class ANY matches every answer
+    pub fn answers_question(&self, q: &Question) -> bool {
+        self.answers.iter().any(|answer| {
+            answer.name.as_str().eq_ignore_ascii_case(q.name.as_str())
+                && (q.qtype == QType::Any || answer.record_type == q.qtype)
+                && (q.qclass == QClass::Any || answer.record_class == q.qclass)
+        })
+    }
+
+    /// Questions in this message asking for `name`, compared case-insensitively
+    pub fn questions_for_name<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Question> {
+        self.questions
+            .iter()
+            .filter(move |q| q.name.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// Set the header counts to the number of questions and records in each section
+    ///
+    /// Called after parsing, call it after pushing to the sections of a message by hand
+    ///
+    /// Records added with [`MdnsMessage::add_answer()`] and the other `add_` methods are counted already
+    pub fn fix_counts(&mut self) {
+        self.header.qdcount = self.questions.len() as u16;
+        self.header.ancount = self.answers.len() as u16;
+        self.header.nscount = self.authorities.len() as u16;
+        self.header.arcount = self.additionals.len() as u16;
+    }
+
+    /// Add a question and increment the QDCOUNT
+    pub fn add_question(&mut self, q: Question) -> &mut Self {
+        self.questions.push(q);
+        self.header.qdcount += 1;
+        self
+    }
+
+    /// Add an answer and increment the ANCOUNT
+    pub fn add_answer(&mut self, r: ResourceRecord) -> &mut Self {
+        self.answers.push(r);
+        self.header.ancount += 1;
+        self
+    }
+
+    /// Add an authority record and increment the NSCOUNT
+    pub fn add_authority(&mut self, r: ResourceRecord) -> &mut Self {
+        self.authorities.push(r);
+        self.header.nscount += 1;
+        self
+    }
+
+    /// Add an additional record and increment the ARCOUNT
+    pub fn add_additional(&mut self, r: ResourceRecord) -> &mut Self {
+        self.additionals.push(r);
+        self.header.arcount += 1;
+        self
+    }
+
+    /// Consuming version of [`MdnsMessage::add_question()`]
+    pub fn with_question(mut self, q: Question) -> Self {
+        self.add_question(q);
+        self
+    }
+
+    /// Consuming version of [`MdnsMessage::add_answer()`]
+    pub fn with_answer(mut self, r: ResourceRecord) -> Self {
+        self.add_answer(r);
+        self
+    }
+
+    /// Consuming version of [`MdnsMessage::add_authority()`]
+    pub fn with_authority(mut self, r: ResourceRecord) -> Self {
+        self.add_authority(r);
+        self
+    }
+
+    /// Consuming version of [`MdnsMessage::add_additional()`]
+    pub fn with_additional(mut self, r: ResourceRecord) -> Self {
+        self.add_additional(r);
+        self
+    }
+
     /// Create a MdnsMessage for probing
     ///
     /// returns a Query type message requesting ANY class and type
@@ -103,118 +427,1053 @@ impl MdnsMessage {
     ///
     /// The authorities section SHOULD be filled with ALL the proposed records  
     /// These records are used in case of Probe Tiebreaking
+    ///
+    /// A service without a service name only probes its host name and claims the address records
+    ///
+    /// ## RFC Reference
+    /// - [RFC6762 Section 8.2 - Simultaneous Probe Tiebreaking](https://www.rfc-editor.org/rfc/rfc6762#section-8.2)
     pub fn probe(service: &Service) -> MdnsMessage {
         let mut message = MdnsMessage::default();
-        message.questions.push(Question {
-            name: Name::new(service.host.clone() + ".local").expect("Should be valid"),
+        message.add_question(Question {
             qtype: QType::Any,
-            qclass: QClass::Any,
             unicast_question: true,
+            ..Question::for_service(Name::local_for(&service.host).as_str())
         });
-        message.header.qdcount = 1;
 
-        let srv = ResourceRecord::create_srv_record(
-            service.host.clone() + "." + &service.service + "." + &service.protocol + ".local",
-            service.port,
-            service.host.clone() + ".local",
-        );
+        let has_service = !service.service.is_empty();
 
-        let a = ResourceRecord::create_a_record(
-            Name::new(service.host.clone() + ".local").expect("Should be valid"),
-            [192, 168, 1, 123],
-        );
+        if has_service {
+            message.add_authority(ResourceRecord::create_srv_record_from_service(service));
+        }
+
+        //Only claim address records for the addresses this machine has
+        if let Some(ip) = service.local_ipv4_address() {
+            message.add_authority(ResourceRecord::create_a_record_from_service(service, ip));
+        }
 
-        message.authorities.push(srv);
+        if has_service {
+            message.add_authority(ResourceRecord::create_ptr_record_from_service(service));
 
-        message.authorities.push(a);
+            message.add_authority(ResourceRecord::create_txt_record(
+                Name::new(service.to_dns_sd_string()).expect("Should be valid"),
+                service.txt_records.clone(),
+            ));
+        }
 
-        message.header.nscount = 2;
+        //Every AAAA record which is announced later is claimed
+        for aaaa in aaaa_records(service, local_ipv6_addresses()) {
+            message.add_authority(aaaa);
+        }
 
         message
     }
 
-    pub fn announce(service: &Service) -> MdnsMessage {
+    /// Creates an unsollicited response with only the TXT record of the given service
+    ///
+    /// Used when the TXT records of a registered service change, no probing is needed
+    ///
+    /// [RFC6763 Section 6.7 - Version Tag](https://www.rfc-editor.org/rfc/rfc6763#section-6.7)
+    pub fn txt_update(service: &Service) -> MdnsMessage {
         let mut message = MdnsMessage::default();
 
         message.header.qr = true;
         message.header.aa = true;
 
-        let ptr = ResourceRecord::create_ptr_record(
-            service.host.clone(),
-            service.service.clone(),
-            service.protocol.clone(),
+        let mut txt = ResourceRecord::create_txt_record(
+            Name::new(service.to_dns_sd_string()).expect("Should be valid"),
+            service.txt_records.clone(),
         );
 
-        let mut srv = ResourceRecord::create_srv_record(
-            service.host.clone() + "." + &service.service + "." + &service.protocol + ".local",
-            service.port,
-            service.host.clone() + ".local",
-        );
+        txt.cache_flush = true;
 
-        srv.cache_flush = true;
+        message.add_answer(txt);
 
-        let mut a = ResourceRecord::create_a_record(
-            Name::new(service.host.clone() + ".local").expect("Should be valid"),
-            [192, 168, 178, 19],
-        );
+        message
+    }
 
-        a.cache_flush = true;
-        
-        let mut txt = ResourceRecord::create_txt_record(Name::new(service.host.clone() + ".local").expect("Should be valid"), service.txt_records.clone());
+    pub fn announce(service: &Service) -> MdnsMessage {
+        let mut message = MdnsMessage::default();
 
-        txt.cache_flush = true;
-        
-        message.answers.push(ptr);
+        message.header.qr = true;
+        message.header.aa = true;
+
+        let ptr = ResourceRecord::create_ptr_record_from_service(service);
+
+        let srv = srv_record(service);
+
+        message.add_answer(ptr);
+
+        //Subtype PTR records point to the same service instance
+        for subtype in &service.subtypes {
+            let mut subtype_ptr = ResourceRecord::create_ptr_record_from_service(service);
+            subtype_ptr.name = Name::local()
+                .prepend(&service.protocol)
+                .and_then(|n| n.prepend(&service.service))
+                .and_then(|n| n.prepend("_sub"))
+                .and_then(|n| n.prepend(subtype))
+                .unwrap_or_default();
+
+            message.add_answer(subtype_ptr);
+        }
+
+        //Address records of our host
+        for additional in MdnsMessage::additional_records_for(&srv, service) {
+            message.add_additional(additional);
+        }
+        message.add_answer(srv);
+
+        message.add_additional(txt_record(service));
+
+        //Only address records exist for our host name, so resolvers need not query for others
+        let mut host_types = vec![];
+
+        for qtype in [QType::A, QType::Aaaa] {
+            if message.additionals.iter().any(|r| r.record_type == qtype) {
+                host_types.push(qtype);
+            }
+        }
 
-        message.answers.push(srv);
+        message.add_additional(ResourceRecord::create_nsec_record(
+            Name::local_for(&service.host),
+            host_types,
+        ));
 
-        message.additionals.push(a);
-        
-        message.additionals.push(txt);
+        message
+    }
+
+    /// Create a MdnsMessage responding to a question for one of our records
+    ///
+    /// PTR answers carry the SRV, TXT and A records as additionals
+    /// and SRV answers carry the A record, so the querier does not need to query again
+    ///
+    /// Records which are unique to our host have the cache flush bit set
+    ///
+    /// ## RFC Reference
+    /// - [RFC6762 Section 6 - Responding](https://www.rfc-editor.org/rfc/rfc6762#section-6)
+    /// - [RFC6763 Section 12 - DNS Additional Record Generation](https://www.rfc-editor.org/rfc/rfc6763#section-12)
+    pub fn response(service: &Service, qtype: QType) -> MdnsMessage {
+        let mut message = MdnsMessage::default();
+
+        message.header.qr = true;
+        message.header.aa = true;
+
+        let answer = match qtype {
+            QType::Ptr => Some(ResourceRecord::create_ptr_record_from_service(service)),
+            QType::Srv => Some(srv_record(service)),
+            QType::Txt => Some(txt_record(service)),
+            QType::A => a_record(service),
+            _ => None,
+        };
 
-        message.header.ancount = 2;
+        if let Some(answer) = answer {
+            for additional in MdnsMessage::additional_records_for(&answer, service) {
+                message.add_additional(additional);
+            }
+            message.add_answer(answer);
+        }
 
-        message.header.arcount = 2;
+        match qtype {
+            QType::Aaaa => {
+                for aaaa in aaaa_records(service, local_ipv6_addresses()) {
+                    message.add_answer(aaaa);
+                }
+            }
+            _ => {}
+        }
 
         message
     }
 
+    /// Additional records which help the querier of `answer`, so it does not have to query for them
+    ///
+    /// - PTR: SRV, TXT, A and an AAAA record for each Ipv6 address
+    /// - SRV: A and an AAAA record for each Ipv6 address
+    /// - Others: None
+    ///
+    /// [RFC6763 Section 12 - DNS Additional Record Generation](https://www.rfc-editor.org/rfc/rfc6763#section-12)
+    pub fn additional_records_for(
+        answer: &ResourceRecord,
+        registration: &Service,
+    ) -> Vec<ResourceRecord> {
+        let mut additionals = vec![];
+
+        match answer.record_type {
+            QType::Ptr => {
+                additionals.push(srv_record(registration));
+                additionals.push(txt_record(registration));
+            }
+            QType::Srv => {}
+            _ => return additionals,
+        }
+
+        additionals.extend(a_record(registration));
+
+        //Dual-stack hosts announce all their addresses, single-stack hosts have no AAAA records
+        additionals.extend(aaaa_records(registration, local_ipv6_addresses()));
+
+        additionals
+    }
+
+    /// Create a MdnsMessage querying for the PTR records of a service type
+    ///
+    /// Set as multicast (QM) so other queriers benefit from the responses
+    ///
+    /// ## RFC Reference
+    /// - [RFC6762 Section 5.2 - Continuous Multicast DNS Querying](https://www.rfc-editor.org/rfc/rfc6762#section-5.2)
+    /// - [RFC6763 Section 4.1 - Structured Service Instance Names](https://www.rfc-editor.org/rfc/rfc6763#section-4.1)
+    pub fn query(name: &str) -> MdnsMessage {
+        MdnsMessage::query_type(name, QType::Ptr)
+    }
+
+    /// Create a MdnsMessage querying for the records of the given [`QType`] with `name`
+    ///
+    /// e.g. the A record of 'MyMachine.local' or the TXT record of 'MyMachine._http._tcp.local'
+    pub fn query_type(name: &str, qtype: QType) -> MdnsMessage {
+        let mut message = MdnsMessage::default();
+
+        message.add_question(Question {
+            qtype,
+            qclass: QClass::In,
+            ..Question::for_service(name)
+        });
+
+        message
+    }
+
+    /// Add known answers to a query, replacing its answers
+    ///
+    /// Responders do not answer with records the querier already knows,
+    /// see [`MdnsMessage::split_for_known_answers()`] for queries with many known answers
+    ///
+    /// [RFC6762 Section 7.1 - Known-Answer Suppression](https://www.rfc-editor.org/rfc/rfc6762#section-7.1)
+    pub fn with_known_answers(mut self, answers: Vec<ResourceRecord>) -> Self {
+        self.answers = answers;
+        self.fix_counts();
+        self
+    }
+
+    /// Add the cached records which answer `query` as known answers, split over multiple messages if necessary
+    ///
+    /// Only records with more than half of their TTL remaining are known answers
+    ///
+    /// The first message contains the questions, the following messages only known answers.
+    /// All messages except the last have the TC bit set so responders wait for the rest
+    ///
+    /// ## RFC Reference
+    /// - [RFC6762 Section 7.1 - Known-Answer Suppression](https://www.rfc-editor.org/rfc/rfc6762#section-7.1)
+    /// - [RFC6762 Section 7.2 - Multipacket Known-Answer Suppression](https://www.rfc-editor.org/rfc/rfc6762#section-7.2)
+    pub fn split_for_known_answers(
+        query: MdnsMessage,
+        cache: &[ResourceRecord],
+    ) -> Vec<MdnsMessage> {
+        let known_answers: Vec<_> = cache
+            .iter()
+            .filter(|r| {
+                r.ttl as u64 * 2 > r.original_ttl as u64
+                    && query.questions.iter().any(|q| {
+                        q.name.as_str().eq_ignore_ascii_case(r.name.as_str())
+                            && (q.qtype == r.record_type || q.qtype == QType::Any)
+                    })
+            })
+            .collect();
+
+        let mut messages = vec![];
+        let mut message = query.with_known_answers(vec![]);
+        let mut size = message.to_bytes().len();
+
+        for record in known_answers {
+            let record_size = record.to_bytes().map(|b| b.len()).unwrap_or_default();
+
+            //Each message holds at least one known answer
+            if size + record_size > MAX_MESSAGE_SIZE && !message.answers.is_empty() {
+                message.header.tc = true;
+                messages.push(message);
+
+                message = MdnsMessage::default();
+                size = message.to_bytes().len();
+            }
+
+            message.add_answer(record.clone());
+            size += record_size;
+        }
+
+        messages.push(message);
+
+        messages
+    }
+
     pub fn goodbye(service: &Service) -> MdnsMessage {
         let mut message = MdnsMessage::default();
 
         message.header.qr = true;
         message.header.aa = true;
 
-        let mut ptr = ResourceRecord::create_ptr_record(
-            service.host.clone(),
-            service.service.clone(),
-            service.protocol.clone(),
-        );
+        let ptr = ResourceRecord::create_ptr_record_from_service(service);
+
+        let srv = ResourceRecord::create_srv_record_from_service(service);
+
+        message.add_answer(ptr.as_goodbye());
+
+        message.add_answer(srv.as_goodbye());
+
+        if let Some(ip) = service.local_ipv4_address() {
+            let a = ResourceRecord::create_a_record_from_service(service, ip);
+
+            message.add_answer(a.as_goodbye());
+        }
+
+        for aaaa in aaaa_records(service, local_ipv6_addresses()) {
+            message.add_answer(aaaa.as_goodbye());
+        }
+
+        message
+    }
+
+    /// Create a goodbye MdnsMessage retiring a single record, e.g. the A record after an IP address change
+    ///
+    /// [RFC6762 Section 10.1 - Goodbye Packets](https://www.rfc-editor.org/rfc/rfc6762#section-10.1)
+    pub fn goodbye_for_record(record: &ResourceRecord) -> MdnsMessage {
+        let mut message = MdnsMessage::default();
+
+        message.header.qr = true;
+        message.header.aa = true;
+
+        message.add_answer(record.as_goodbye());
+
+        message
+    }
+
+    /// Create goodbye MdnsMessages retiring all `records`, split over multiple messages if necessary
+    ///
+    /// Each message holds as many records as fit in [`MAX_MESSAGE_SIZE`], but at least one
+    pub fn goodbye_for_records(records: &[ResourceRecord]) -> Vec<MdnsMessage> {
+        let mut messages = vec![];
+        let mut message = MdnsMessage::default();
+
+        message.header.qr = true;
+        message.header.aa = true;
+
+        let mut size = message.to_bytes().len();
+
+        for record in records {
+            let record_size = record.to_bytes().map(|b| b.len()).unwrap_or_default();
+
+            //Each message holds at least one record
+            if size + record_size > MAX_MESSAGE_SIZE && !message.answers.is_empty() {
+                messages.push(message.clone());
+                message.answers.clear();
+                message.fix_counts();
+                size = message.to_bytes().len();
+            }
+
+            message.add_answer(record.as_goodbye());
+            size += record_size;
+        }
+
+        if !message.answers.is_empty() {
+            messages.push(message);
+        }
+
+        messages
+    }
+}
 
-        ptr.ttl = 0;
-        let mut srv = ResourceRecord::create_srv_record(
-            service.host.clone() + "." + &service.service + "." + &service.protocol + ".local",
-            service.port,
-            service.host.clone() + ".local",
+impl TryFrom<&[u8]> for MdnsMessage {
+    type Error = MdnsError;
+
+    /// Parse a MdnsMessage from the bytes of a received UDP packet, see [`MdnsMessage::parse()`]
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        MdnsMessage::parse(bytes)
+    }
+}
+
+impl From<&MdnsMessage> for Vec<u8> {
+    /// Encode a MdnsMessage, see [`MdnsMessage::to_bytes()`]
+    fn from(message: &MdnsMessage) -> Self {
+        message.to_bytes()
+    }
+}
+
+/// SRV record of our service, unique to our host
+fn srv_record(service: &Service) -> ResourceRecord {
+    let mut srv = ResourceRecord::create_srv_record_from_service(service);
+    srv.cache_flush = true;
+    srv
+}
+
+/// TXT record of our service, unique to our host
+fn txt_record(service: &Service) -> ResourceRecord {
+    let mut txt = ResourceRecord::create_txt_record(
+        Name::new(service.to_dns_sd_string()).expect("Should be valid"),
+        service.txt_records.clone(),
+    );
+    txt.cache_flush = true;
+    txt
+}
+
+/// A record of our host, if this machine has an Ipv4 address
+fn a_record(service: &Service) -> Option<ResourceRecord> {
+    service.local_ipv4_address().map(|ip| {
+        let mut a = ResourceRecord::create_a_record_from_service(service, ip);
+        a.cache_flush = true;
+        a
+    })
+}
+
+/// AAAA records of our host for each of `addresses`, see [`local_ipv6_addresses()`]
+fn aaaa_records(service: &Service, addresses: Vec<Ipv6Addr>) -> Vec<ResourceRecord> {
+    addresses
+        .into_iter()
+        .map(|ip| {
+            let mut aaaa =
+                ResourceRecord::create_aaaa_record_from_ipv6(Name::local_for(&service.host), ip);
+            aaaa.cache_flush = true;
+            aaaa
+        })
+        .collect()
+}
+
+#[test]
+fn test_probe_message() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .txt("key", "value")
+        .build()
+        .unwrap();
+
+    let message = MdnsMessage::probe(&service);
+
+    //SRV, PTR and TXT are always claimed, A only with an address and AAAA for each address
+    let ipv6_addresses = local_ipv6_addresses().len();
+    let expected = 3 + service.local_ipv4_address().is_some() as usize + ipv6_addresses;
+
+    assert_eq!(message.authorities.len(), expected);
+    assert_eq!(
+        message
+            .authorities
+            .iter()
+            .filter(|r| r.record_type == QType::Aaaa)
+            .count(),
+        ipv6_addresses
+    );
+    assert_eq!(message.header.nscount as usize, message.authorities.len());
+
+    //Each authority encodes its RDATA with a matching RDLENGTH
+    for authority in &message.authorities {
+        let bytes = authority.to_bytes().unwrap();
+        let rdata_length = authority.rdata.as_ref().unwrap().to_bytes().len();
+        let offset = bytes.len() - rdata_length - 2;
+
+        assert_eq!(
+            u16::from_be_bytes([bytes[offset], bytes[offset + 1]]),
+            authority.rdlength
         );
+        assert_eq!(authority.rdlength as usize, rdata_length);
+    }
+}
+
+#[test]
+fn test_announce_txt() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .txt("key", "value")
+        .build()
+        .unwrap();
+
+    let message = MdnsMessage::announce(&service);
+
+    let txt = message
+        .additionals
+        .iter()
+        .find(|r| r.record_type == QType::Txt)
+        .expect("Announcement should contain a TXT record");
+
+    assert_eq!(txt.ttl, 4500);
+
+    //Length prefixed 'key=value' should be in the encoded message
+    let bytes = message.to_bytes();
+    let mut expected = vec![9];
+    expected.extend(b"key=value");
+
+    assert!(bytes.windows(expected.len()).any(|w| w == expected));
+}
+
+#[test]
+fn test_announce_nsec() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let message = MdnsMessage::announce(&service);
+
+    let nsec = message
+        .additionals
+        .iter()
+        .find(|r| r.record_type == QType::Nsec)
+        .expect("Announcement should contain a NSEC record");
+
+    assert_eq!(nsec.name.as_str(), "TestMachine.local");
+    assert!(nsec.cache_flush);
+    assert_eq!(message.header.arcount as usize, message.additionals.len());
+}
+
+#[test]
+fn test_announce_aaaa() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    //Every address of a dual-stack host gets an AAAA record
+    let addresses = vec!["fe80::1".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+    let records = aaaa_records(&service, addresses);
+
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| r.record_type == QType::Aaaa
+        && r.cache_flush
+        && r.name.as_str() == "TestMachine.local"));
+
+    let mut expected = 0xfe80_u16.to_be_bytes().to_vec();
+    expected.extend([0; 13]);
+    expected.push(1);
+    assert_eq!(records[0].rdata.as_ref().unwrap().to_bytes(), expected);
+
+    //Single-stack hosts have no AAAA records
+    assert!(aaaa_records(&service, vec![]).is_empty());
+
+    //Announcements and goodbyes hold the AAAA records of this machine
+    let local = local_ipv6_addresses().len();
+    let aaaa_count = |records: &[ResourceRecord]| {
+        records
+            .iter()
+            .filter(|r| r.record_type == QType::Aaaa)
+            .count()
+    };
+
+    let announcement = MdnsMessage::announce(&service);
+    assert_eq!(aaaa_count(&announcement.additionals), local);
+    assert_eq!(
+        announcement.header.arcount as usize,
+        announcement.additionals.len()
+    );
 
-        srv.ttl = 0;
+    let goodbye = MdnsMessage::goodbye(&service);
+    assert_eq!(aaaa_count(&goodbye.answers), local);
+    assert!(goodbye.answers.iter().all(|r| r.is_goodbye()));
+}
+
+#[test]
+fn test_parse_message() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .txt("key", "value")
+        .build()
+        .unwrap();
+
+    //Round trip of our own messages
+    for message in [
+        MdnsMessage::probe(&service),
+        MdnsMessage::announce(&service),
+        MdnsMessage::goodbye(&service),
+    ] {
+        let bytes = Vec::from(&message);
+        let parsed = MdnsMessage::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(parsed.questions.len(), message.questions.len());
+        assert_eq!(parsed.answers.len(), message.answers.len());
+        assert_eq!(parsed.authorities.len(), message.authorities.len());
+        assert_eq!(parsed.additionals.len(), message.additionals.len());
+        assert_eq!(Vec::from(&parsed), bytes);
+    }
+
+    //Header too short
+    assert!(MdnsMessage::try_from(&[0; 11][..]).is_err());
+
+    //Question count without questions
+    let mut bytes = [0; 12];
+    bytes[5] = 1;
+    assert!(MdnsMessage::try_from(&bytes[..]).is_err());
+
+    //Reserved opcode
+    let mut bytes = [0; 12];
+    bytes[2] = 0b0111_1000;
+    assert!(MdnsMessage::try_from(&bytes[..]).is_err());
+
+    //Non-zero ID
+    let mut bytes = [0; 12];
+    bytes[1] = 1;
+    assert!(matches!(
+        MdnsMessage::try_from(&bytes[..]),
+        Err(MdnsError::InvalidMessage {})
+    ));
+}
+
+#[test]
+fn test_message_id() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    for message in [
+        MdnsMessage::probe(&service),
+        MdnsMessage::announce(&service),
+        MdnsMessage::goodbye(&service),
+    ] {
+        assert_eq!(message.header.id, 0);
+    }
+}
+
+#[test]
+fn test_validate() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    for message in [
+        MdnsMessage::default(),
+        MdnsMessage::probe(&service),
+        MdnsMessage::announce(&service),
+        MdnsMessage::goodbye(&service),
+        MdnsMessage::query("_test._tcp.local"),
+    ] {
+        message.validate().unwrap();
+    }
+
+    let mut questions = MdnsMessage::query("_test._tcp.local");
+    questions.header.qdcount = 2;
+
+    let mut answers = MdnsMessage::announce(&service);
+    answers.header.ancount += 1;
+
+    let mut authorities = MdnsMessage::probe(&service);
+    authorities.header.nscount = 0;
+
+    let mut additionals = MdnsMessage::announce(&service);
+    additionals.additionals.pop();
+
+    let mut name = MdnsMessage::query("_test._tcp.local");
+    name.questions[0] = Question {
+        name: Name::new("TestMachine..local".into()).unwrap(),
+        qtype: QType::Ptr,
+        qclass: QClass::In,
+        unicast_question: false,
+    };
+
+    let mut id = MdnsMessage::default();
+    id.header.id = 1;
+
+    for message in [questions, answers, authorities, additionals, name, id] {
+        assert!(matches!(
+            message.validate(),
+            Err(MdnsError::InvalidMessage {})
+        ));
+    }
+}
+
+#[test]
+fn test_split_for_known_answers() {
+    let cache: Vec<_> = (0..40)
+        .map(|i| {
+            ResourceRecord::create_ptr_record(
+                format!("TestMachine{}", i),
+                "_test".into(),
+                "_tcp".into(),
+            )
+        })
+        .collect();
+
+    let messages =
+        MdnsMessage::split_for_known_answers(MdnsMessage::query("_test._tcp.local"), &cache);
+
+    assert!(messages.len() > 1);
+    assert_eq!(messages[0].questions.len(), 1);
+    assert_eq!(
+        messages.iter().map(|m| m.answers.len()).sum::<usize>(),
+        cache.len()
+    );
+
+    for (i, message) in messages.iter().enumerate() {
+        assert_eq!(message.header.tc, i < messages.len() - 1);
+        assert_eq!(message.header.ancount as usize, message.answers.len());
+        assert!(message.to_bytes().len() <= MAX_MESSAGE_SIZE);
+    }
+
+    //Records with less than half their TTL left and other names are no known answers
+    let mut expiring =
+        ResourceRecord::create_ptr_record("Expiring".into(), "_test".into(), "_tcp".into());
+    expiring.ttl = expiring.original_ttl / 2;
+    let other = ResourceRecord::create_ptr_record("Other".into(), "_other".into(), "_tcp".into());
+
+    let messages = MdnsMessage::split_for_known_answers(
+        MdnsMessage::query("_test._tcp.local"),
+        &[expiring, other, cache[0].clone()],
+    );
+
+    assert_eq!(messages.len(), 1);
+    assert!(!messages[0].header.tc);
+    assert_eq!(messages[0].answers.len(), 1);
+}
+
+#[test]
+fn test_additional_records_for() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let mut address_types = vec![];
+    if service.local_ipv4_address().is_some() {
+        address_types.push(QType::A);
+    }
+    for _ in local_ipv6_addresses() {
+        address_types.push(QType::Aaaa);
+    }
+
+    let types = |qtype| {
+        let message = MdnsMessage::response(&service, qtype);
+        assert_eq!(message.header.arcount as usize, message.additionals.len());
+
+        match message.answers.first() {
+            Some(answer) => MdnsMessage::additional_records_for(answer, &service)
+                .iter()
+                .map(|r| r.record_type)
+                .collect(),
+            None => vec![],
+        }
+    };
+
+    let mut ptr = vec![QType::Srv, QType::Txt];
+    ptr.extend(&address_types);
 
-        let mut a = ResourceRecord::create_a_record(
-            Name::new(service.host.clone() + ".local").expect("Should be valid"),
-            [192, 168, 178, 19],
+    assert_eq!(types(QType::Ptr), ptr);
+    assert_eq!(types(QType::Srv), address_types);
+    assert!(types(QType::Txt).is_empty());
+    assert!(types(QType::A).is_empty());
+}
+
+#[test]
+fn test_goodbye_for_records() {
+    let a = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+
+    let message = MdnsMessage::goodbye_for_record(&a);
+
+    assert!(message.header.qr && message.header.aa);
+    assert_eq!(message.header.ancount, 1);
+    //The original record is left untouched
+    assert_eq!(a.ttl, 60);
+
+    //TTL is encoded as zero directly after the name, type and class
+    let bytes = message.answers[0].to_bytes().unwrap();
+    let ttl_offset = a.name.to_bytes().len() + 4;
+    assert_eq!(bytes[ttl_offset..ttl_offset + 4], [0, 0, 0, 0]);
+
+    let mut expected = a.to_bytes().unwrap();
+    expected[ttl_offset..ttl_offset + 4].fill(0);
+    assert_eq!(bytes, expected);
+
+    //Batched goodbyes are split when exceeding the maximum message size
+    let records = vec![a; 40];
+    let messages = MdnsMessage::goodbye_for_records(&records);
+
+    assert!(messages.len() > 1);
+    assert_eq!(
+        messages.iter().map(|m| m.answers.len()).sum::<usize>(),
+        records.len()
+    );
+    for message in &messages {
+        assert_eq!(message.header.ancount as usize, message.answers.len());
+        assert!(message.to_bytes().len() <= MAX_MESSAGE_SIZE);
+        assert!(message.answers.iter().all(|r| r.is_goodbye()));
+    }
+
+    assert!(MdnsMessage::goodbye_for_records(&[]).is_empty());
+}
+
+#[test]
+fn test_message_accessors() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let probe = MdnsMessage::probe(&service);
+    assert!(probe.is_query() && !probe.is_response());
+    assert!(!probe.is_authoritative() && !probe.is_truncated());
+    assert_eq!(probe.question_count() as usize, probe.questions.len());
+    assert_eq!(probe.authority_count() as usize, probe.authorities.len());
+    assert!(!probe.has_answers() && !probe.has_additional_records());
+
+    let announce = MdnsMessage::announce(&service);
+    assert!(announce.is_response() && !announce.is_query());
+    assert!(announce.is_authoritative() && !announce.is_truncated());
+    assert_eq!(announce.question_count(), 0);
+    assert_eq!(announce.answer_count() as usize, announce.answers.len());
+    assert_eq!(
+        announce.additional_count() as usize,
+        announce.additionals.len()
+    );
+    assert!(announce.has_answers() && announce.has_additional_records());
+
+    let goodbye = MdnsMessage::goodbye(&service);
+    assert!(goodbye.is_response() && goodbye.is_authoritative());
+    assert_eq!(goodbye.answer_count() as usize, goodbye.answers.len());
+    assert!(goodbye.has_answers());
+}
+
+#[test]
+fn test_add_records() {
+    let record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+
+    let mut message = MdnsMessage::default();
+    message
+        .add_answer(record.clone())
+        .add_answer(record.clone())
+        .add_answer(record.clone());
+
+    assert_eq!(message.header.ancount, 3);
+    assert_eq!(message.answers.len(), 3);
+
+    let message = MdnsMessage::default()
+        .with_question(Question::for_service("TestMachine.local"))
+        .with_authority(record.clone())
+        .with_additional(record.clone())
+        .with_additional(record);
+
+    assert_eq!(message.header.qdcount, 1);
+    assert_eq!(message.header.ancount, 0);
+    assert_eq!(message.header.nscount, 1);
+    assert_eq!(message.header.arcount, 2);
+
+    //The counts match the parsed message
+    let parsed = MdnsMessage::parse(&message.to_bytes()).unwrap();
+    assert_eq!(parsed.header, message.header);
+}
+
+#[test]
+fn test_answers_question() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let response = MdnsMessage::response(&service, QType::Srv);
+    let question = |name: &str, qtype: QType, qclass: QClass| Question {
+        qtype,
+        qclass,
+        ..Question::for_service(name)
+    };
+
+    //Matching name, type and class
+    let query = MdnsMessage::query_type("testmachine._test._tcp.local", QType::Srv);
+    assert!(response.answers_question(&query.questions[0]));
+    assert!(response.answers_question(&question(
+        "TestMachine._test._tcp.local",
+        QType::Any,
+        QClass::Any
+    )));
+
+    //Different name, type or class
+    let query = MdnsMessage::query_type("Other._test._tcp.local", QType::Srv);
+    assert!(!response.answers_question(&query.questions[0]));
+    let query = MdnsMessage::query_type("TestMachine._test._tcp.local", QType::Txt);
+    assert!(!response.answers_question(&query.questions[0]));
+    assert!(!response.answers_question(&question(
+        "TestMachine._test._tcp.local",
+        QType::Srv,
+        QClass::Ch
+    )));
+
+    let mut query = MdnsMessage::query_type("TestMachine._test._tcp.local", QType::Srv);
+    query.add_question(Question::for_service("_test._tcp.local"));
+    query.add_question(Question::for_service("TESTMACHINE._test._tcp.local"));
+    assert_eq!(
+        query
+            .questions_for_name("TestMachine._test._tcp.local")
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn test_fix_counts() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .txt("key", "value")
+        .build()
+        .unwrap();
+
+    let record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+
+    let messages = [
+        MdnsMessage::probe(&service),
+        MdnsMessage::announce(&service),
+        MdnsMessage::txt_update(&service),
+        MdnsMessage::response(&service, QType::Ptr),
+        MdnsMessage::goodbye(&service),
+        MdnsMessage::goodbye_for_record(&record),
+        MdnsMessage::query("_test._tcp.local"),
+        MdnsMessage::query("_test._tcp.local").with_known_answers(vec![record.clone()]),
+    ];
+
+    for message in messages {
+        assert_eq!(message.question_count() as usize, message.questions.len());
+        assert_eq!(message.answer_count() as usize, message.answers.len());
+        assert_eq!(
+            message.authority_count() as usize,
+            message.authorities.len()
         );
+        assert_eq!(
+            message.additional_count() as usize,
+            message.additionals.len()
+        );
+        assert!(message.validate().is_ok());
+    }
 
-        a.ttl = 0;
+    //Records pushed by hand are counted after fixing
+    let mut message = MdnsMessage::query("_test._tcp.local");
+    message.answers.push(record.clone());
+    message.additionals.push(record);
+    assert_eq!(message.count_records(), 3);
+    assert!(message.validate().is_err());
 
-        message.answers.push(ptr);
+    message.fix_counts();
+    assert_eq!(message.answer_count(), 1);
+    assert_eq!(message.additional_count(), 1);
+    assert!(message.validate().is_ok());
+}
 
-        message.answers.push(srv);
+#[test]
+fn test_encode() {
+    use crate::service::ServiceBuilder;
 
-        message.answers.push(a);
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .txt("key", "value")
+        .subtype("_printer")
+        .build()
+        .unwrap();
 
-        message.header.ancount = 3;
+    for message in [
+        MdnsMessage::probe(&service),
+        MdnsMessage::announce(&service),
+        MdnsMessage::goodbye(&service),
+        MdnsMessage::query("_test._tcp.local"),
+        MdnsMessage::default(),
+    ] {
+        assert_eq!(message.encode(), message.to_bytes());
+    }
 
-        message
+    //Records without RDATA are skipped
+    let mut message = MdnsMessage::announce(&service);
+    message.answers[0].rdata = None;
+    assert_eq!(message.encode(), message.to_bytes());
+}
+
+#[test]
+fn test_would_exceed_mtu() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    //A single announcement fits in a DNS datagram
+    let announce = MdnsMessage::announce(&service);
+    let size = announce.to_bytes().len();
+    assert!(!announce.would_exceed_dns_limit());
+    assert!(!announce.would_exceed_mtu(size));
+    assert!(announce.would_exceed_mtu(size - 1));
+    assert_eq!(announce.byte_count(), size);
+
+    //Many TXT records do not
+    let txt = ResourceRecord::create_txt_record(
+        Name::new("TestMachine._test._tcp.local".into()).unwrap(),
+        (0..10).map(|i| format!("key{}=value{}", i, i)).collect(),
+    );
+    let mut large = MdnsMessage::default();
+    for _ in 0..5 {
+        large.add_answer(txt.clone());
     }
+    assert!(large.to_bytes().len() > MAX_MESSAGE_SIZE);
+    assert!(large.would_exceed_dns_limit());
+    assert!(!large.would_exceed_mtu(1500));
+    assert_eq!(large.byte_count(), large.to_bytes().len());
+
+    //Questions are counted as well
+    let query = MdnsMessage::query("_test._tcp.local");
+    assert_eq!(query.byte_count(), query.to_bytes().len());
 }
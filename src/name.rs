@@ -1,3 +1,8 @@
+use bytes::{BufMut, BytesMut};
+use std::str::FromStr;
+
+use crate::MdnsError;
+
 /// Name is a wrapper to provide
 /// methods to properly support division of name into labels
 /// which are properly serialized with prepending lengths and
@@ -13,6 +18,120 @@ impl Name {
         Ok(Name { content: name })
     }
 
+    /// The '.local' domain of Multicast DNS
+    ///
+    /// [RFC6762 Section 3 - Multicast DNS Names](https://www.rfc-editor.org/rfc/rfc6762#section-3)
+    pub fn local() -> Name {
+        Name {
+            content: "local".into(),
+        }
+    }
+
+    /// Host name of `host` in the '.local' domain (e.g. 'MyMachine.local')
+    pub fn local_for(host: &str) -> Name {
+        Name {
+            content: format!("{}.local", host),
+        }
+    }
+
+    /// This name with `label` appended (e.g. 'MyMachine' with 'local' is 'MyMachine.local')
+    ///
+    /// See [`Name::prepend()`] for the labels which are accepted
+    pub fn append(&self, label: &str) -> Result<Name, String> {
+        validate_label(label)?;
+
+        let content = match self.content.strip_suffix('.').unwrap_or(&self.content) {
+            "" => label.to_string(),
+            content => format!("{}.{}", content, label),
+        };
+
+        Ok(Name { content })
+    }
+
+    /// This name with `label` prepended (e.g. '_http' with '_tcp.local' is '_http._tcp.local')
+    ///
+    /// A label with dots adds each of its labels, as instance names in this crate may contain dots (e.g. 'printer.office').
+    /// Labels may not be empty, longer than 63 octets or contain ASCII control characters
+    ///
+    /// ## RFC Reference
+    /// - [RFC1035 Section 2.3.4 - Size limits](https://www.rfc-editor.org/rfc/rfc1035#section-2.3.4)
+    /// - [RFC6763 Section 4.1.1 - Instance Names](https://www.rfc-editor.org/rfc/rfc6763#section-4.1.1)
+    pub fn prepend(&self, label: &str) -> Result<Name, String> {
+        validate_label(label)?;
+
+        let content = match self.content.as_str() {
+            "" => label.to_string(),
+            content => format!("{}.{}", label, content),
+        };
+
+        Ok(Name { content })
+    }
+
+    /// Whether this name can be encoded
+    ///
+    /// Labels may not be empty or longer than 63 octets and the encoded name may not be longer than 255 octets
+    ///
+    /// [RFC1035 Section 2.3.4 - Size limits](https://www.rfc-editor.org/rfc/rfc1035#section-2.3.4)
+    pub fn is_valid(&self) -> bool {
+        let content = self.content.strip_suffix('.').unwrap_or(&self.content);
+
+        if content.is_empty() {
+            return true;
+        }
+
+        content
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= 63)
+            && self.to_bytes().len() <= 255
+    }
+
+    /// Name as a dot separated string (e.g. 'MyMachine.local')
+    pub fn as_str(&self) -> &str {
+        &self.content
+    }
+
+    /// Labels of this name (e.g. 'MyMachine' and 'local'), empty labels are left out
+    pub fn labels(&self) -> Vec<&str> {
+        self.content.split('.').filter(|l| !l.is_empty()).collect()
+    }
+
+    /// The part of this name before `domain`, compared case insensitive
+    ///
+    /// Returns `None` if the name is not in `domain`
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dns_sd2::name::Name;
+    ///
+    /// let name = Name::new("MyMachine._http._tcp.local".into()).unwrap();
+    ///
+    /// assert_eq!(name.relative_to(".local"), Some("MyMachine._http._tcp".into()));
+    /// ```
+    pub fn relative_to(&self, domain: &str) -> Option<String> {
+        let labels = self.labels();
+        let domain: Vec<&str> = domain.split('.').filter(|l| !l.is_empty()).collect();
+
+        let prefix = labels.len().checked_sub(domain.len())?;
+
+        if labels[prefix..]
+            .iter()
+            .zip(&domain)
+            .all(|(l, d)| l.eq_ignore_ascii_case(d))
+        {
+            Some(labels[..prefix].join("."))
+        } else {
+            None
+        }
+    }
+
+    /// Whether this name is in the '.local' domain of Multicast DNS
+    ///
+    /// [RFC6762 Section 3 - Multicast DNS Names](https://www.rfc-editor.org/rfc/rfc6762#section-3)
+    pub fn is_local(&self) -> bool {
+        self.relative_to("local").is_some()
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
 
@@ -22,7 +141,7 @@ impl Name {
         //Or with pointers (See Name Compression Handler)
         //Name is terminated by a zero length Octet
         //[RFC1035 Section 4.1.2 - Question section format](https://www.rfc-editor.org/rfc/rfc1035#section-4.1.2)
-        for label in labels {
+        for label in labels.into_iter().filter(|l| !l.is_empty()) {
             bytes.push(label.len() as u8);
             bytes.extend(label.as_bytes());
         }
@@ -32,4 +151,265 @@ impl Name {
 
         bytes
     }
+
+    /// Write the encoded name to `buf`, see [`Name::to_bytes()`]
+    pub fn write_to(&self, buf: &mut BytesMut) {
+        for label in self.content.split('.').filter(|l| !l.is_empty()) {
+            buf.put_u8(label.len() as u8);
+            buf.put_slice(label.as_bytes());
+        }
+
+        //Name must end with a zero Octet
+        buf.put_u8(0);
+    }
+
+    /// Length of the encoded name in octets, equal to `self.to_bytes().len()`
+    pub fn encoded_len(&self) -> usize {
+        self.content
+            .split('.')
+            .filter(|l| !l.is_empty())
+            .map(|l| l.len() + 1)
+            .sum::<usize>()
+            + 1
+    }
+
+    /// Parse a Name from a message
+    ///
+    /// Starts reading at `offset` and returns the Name with the offset directly after it
+    ///
+    /// Labels are prepended with their length, a length octet with the first two bits set
+    /// is a pointer to a name earlier in the message (See Name Compression Handler)
+    ///
+    /// Pointers may only point backwards and a name may not exceed 255 octets,
+    /// which prevents pointer loops in malformed messages
+    ///
+    /// ## RFC Reference
+    /// - [RFC1035 Section 4.1.4 - Message Compression](https://www.rfc-editor.org/rfc/rfc1035#section-4.1.4)
+    pub fn parse(bytes: &[u8], offset: usize) -> Result<(Name, usize), MdnsError> {
+        let mut labels: Vec<String> = vec![];
+        let mut position = offset;
+        let mut length = 0;
+
+        //Offset after the name, set when the first pointer is followed
+        let mut end = None;
+
+        loop {
+            let octet = *bytes.get(position).ok_or(MdnsError::InvalidMessage {})?;
+
+            match octet & 0b1100_0000 {
+                //Label
+                0b0000_0000 => {
+                    //Name is terminated by a zero length Octet
+                    if octet == 0 {
+                        position += 1;
+                        break;
+                    }
+
+                    let label = bytes
+                        .get(position + 1..position + 1 + octet as usize)
+                        .ok_or(MdnsError::InvalidMessage {})?;
+
+                    length += label.len() + 1;
+                    if length > 255 {
+                        return Err(MdnsError::InvalidMessage {});
+                    }
+
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    position += 1 + label.len();
+                }
+                //Pointer
+                0b1100_0000 => {
+                    let low = *bytes.get(position + 1).ok_or(MdnsError::InvalidMessage {})?;
+                    let pointer = (((octet & 0b0011_1111) as usize) << 8) | low as usize;
+
+                    //Pointers must point to an earlier part of the message
+                    if pointer >= position {
+                        return Err(MdnsError::InvalidMessage {});
+                    }
+
+                    //A pointer counts towards the name length, so loops end at 255 octets
+                    length += 2;
+                    if length > 255 {
+                        return Err(MdnsError::InvalidMessage {});
+                    }
+
+                    if end.is_none() {
+                        end = Some(position + 2);
+                    }
+
+                    position = pointer;
+                }
+                //0b01 and 0b10 are reserved
+                _ => return Err(MdnsError::InvalidMessage {}),
+            }
+        }
+
+        Ok((
+            Name {
+                content: labels.join("."),
+            },
+            end.unwrap_or(position),
+        ))
+    }
+}
+
+/// Check the labels of `label` before adding them to a name, see [`Name::prepend()`]
+fn validate_label(label: &str) -> Result<(), String> {
+    for part in label.split('.') {
+        if part.is_empty() || part.len() > 63 {
+            return Err(format!("Label '{}' should be 1 to 63 octets long", part));
+        }
+
+        if part.chars().any(|c| c.is_ascii_control()) {
+            return Err(format!(
+                "Label {:?} should not contain control characters",
+                part
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+impl FromStr for Name {
+    type Err = String;
+
+    /// Create a Name from a dot separated string (e.g. 'MyMachine.local')
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Name::new(s.into())
+    }
+}
+
+/// Names are compared case insensitive
+///
+/// [RFC6762 Section 16 - Multicast DNS Character Set](https://www.rfc-editor.org/rfc/rfc6762#section-16)
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        self.content.eq_ignore_ascii_case(&other.content)
+    }
+}
+
+impl Eq for Name {}
+
+#[test]
+fn test_name_parse() {
+    let name = Name::new("TestMachine._test._tcp.local".into()).unwrap();
+
+    //Round trip
+    let bytes = name.to_bytes();
+    let (parsed, offset) = Name::parse(&bytes, 0).unwrap();
+    assert_eq!(parsed.as_str(), "TestMachine._test._tcp.local");
+    assert_eq!(offset, bytes.len());
+
+    //Pointer to the '_tcp.local' part of the first name
+    let mut compressed = bytes.clone();
+    let pointer_offset = compressed.len();
+    compressed.extend([3, b'_', b'a', b'b', 0b1100_0000, 18]);
+    let (parsed, offset) = Name::parse(&compressed, pointer_offset).unwrap();
+    assert_eq!(parsed.as_str(), "_ab._tcp.local");
+    assert_eq!(offset, compressed.len());
+
+    //Pointer to itself
+    assert!(Name::parse(&[0b1100_0000, 0], 0).is_err());
+
+    //Pointer loop through a label
+    assert!(Name::parse(&[1, b'a', 0b1100_0000, 0], 2).is_err());
+
+    //Label past the end of the message
+    assert!(Name::parse(&[5, b'a'], 0).is_err());
+}
+
+#[test]
+fn test_name_from_str() {
+    let name: Name = "TestMachine.local".parse().unwrap();
+
+    assert_eq!(name.as_str(), "TestMachine.local");
+    assert_eq!(
+        name.to_bytes(),
+        Name::new("TestMachine.local".into()).unwrap().to_bytes()
+    );
+}
+
+#[test]
+fn test_name_is_valid() {
+    for name in ["", "local", "TestMachine.local", "TestMachine.local."] {
+        assert!(Name::new(name.into()).unwrap().is_valid(), "{}", name);
+    }
+
+    let long_label = "a".repeat(64) + ".local";
+    let long_name = vec!["a".repeat(63); 5].join(".");
+
+    for name in [".local", "TestMachine..local", &long_label, &long_name] {
+        assert!(!Name::new(name.into()).unwrap().is_valid(), "{}", name);
+    }
+}
+
+#[test]
+fn test_name_relative_to() {
+    let name = Name::new("TestMachine._test._tcp.local.".into()).unwrap();
+
+    assert_eq!(name.labels(), ["TestMachine", "_test", "_tcp", "local"]);
+    assert_eq!(
+        name.relative_to(".local"),
+        Some("TestMachine._test._tcp".into())
+    );
+    assert_eq!(
+        name.relative_to("_TCP.LOCAL"),
+        Some("TestMachine._test".into())
+    );
+    assert_eq!(name.relative_to("example.com"), None);
+    assert_eq!(name.relative_to("cal"), None);
+    assert!(name.is_local());
+
+    let name = Name::new("TestMachine.example.com".into()).unwrap();
+
+    assert_eq!(name.relative_to("example.com"), Some("TestMachine".into()));
+    assert_eq!(name.relative_to(".local"), None);
+    assert!(!name.is_local());
+}
+
+#[test]
+fn test_name_eq() {
+    let name: Name = "MyMachine.local".parse().unwrap();
+
+    assert_eq!(name, "mymachine.LOCAL".parse().unwrap());
+    assert_ne!(name, "OtherMachine.local".parse().unwrap());
+}
+
+#[test]
+fn test_name_append_prepend() {
+    let service_type = Name::local()
+        .prepend("_tcp")
+        .and_then(|n| n.prepend("_test"))
+        .unwrap();
+    assert_eq!(service_type.as_str(), "_test._tcp.local");
+    assert_eq!(
+        service_type.prepend("printer.office").unwrap().as_str(),
+        "printer.office._test._tcp.local"
+    );
+
+    assert_eq!(Name::local_for("TestMachine").as_str(), "TestMachine.local");
+    assert_eq!(
+        Name::new("TestMachine".into())
+            .unwrap()
+            .append("local")
+            .unwrap(),
+        Name::local_for("TestMachine")
+    );
+    assert_eq!(Name::default().append("local").unwrap(), Name::local());
+
+    //Labels are 1 to 63 octets without control characters
+    let long_label = "a".repeat(64);
+    for label in [
+        "",
+        "Test..Machine",
+        &long_label,
+        "Test\0Machine",
+        "Test\nMachine",
+    ] {
+        assert!(Name::local().prepend(label).is_err(), "{:?}", label);
+        assert!(Name::local().append(label).is_err(), "{:?}", label);
+    }
+
+    assert!(Name::local().prepend(&"a".repeat(63)).is_ok());
 }
@@ -0,0 +1,79 @@
+//! Commonly used types, import them all with `use dns_sd2::prelude::*;`
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use dns_sd2::prelude::*;
+//!
+//! let client = DnsSd2::default();
+//! let message = MdnsMessage::query("_http._tcp.local");
+//! ```
+
+pub use crate::{
+    cache::CacheEntry,
+    message::MdnsMessage,
+    question::{QClass, QType, Question},
+    record::ResourceRecord,
+    records::{
+        AAAARecord, ARecord, CNAMERecord, NSECRecord, PTRRecord, SRVRecord, TXTRecord,
+        UnknownRecord,
+    },
+    service::{
+        BrowseFilter, Query, ResolvedService, Service, ServiceBuilder, ServiceEvent, ServiceState,
+    },
+    utility::{
+        create_framed_socket, create_socket, enumerate_network_interfaces, get_hostname,
+        is_reachable_ipv4, is_reachable_ipv6, local_ipv4_addresses, local_ipv6_addresses,
+        primary_interface, NetworkInterface, SendTarget, SocketConfig,
+    },
+    DnsSd2, MdnsError,
+};
+
+#[test]
+fn test_prelude() {
+    use crate::{name::Name, record::RData};
+
+    let service: Service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    //Message factory methods
+    assert!(MdnsMessage::announce(&service).header.qr);
+    assert!(!MdnsMessage::probe(&service).header.qr);
+    assert_eq!(MdnsMessage::query("_test._tcp.local").questions.len(), 1);
+
+    //Record types
+    let record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    assert_eq!(record.record_type, QType::A);
+    assert_eq!(
+        record.rdata.unwrap().to_bytes(),
+        ARecord {
+            ip: [192, 168, 1, 2]
+        }
+        .to_bytes()
+    );
+
+    let _ = (QClass::In, ServiceState::Registered, SendTarget::Multicast);
+    let _: Option<(CacheEntry, ResolvedService, ServiceEvent, MdnsError)> = None;
+    let _: Vec<(
+        AAAARecord,
+        NSECRecord,
+        PTRRecord,
+        SRVRecord,
+        TXTRecord,
+        UnknownRecord,
+    )> = vec![];
+    let _ = (
+        local_ipv4_addresses(),
+        local_ipv6_addresses(),
+        primary_interface(),
+    );
+    let _: fn() -> Vec<NetworkInterface> = enumerate_network_interfaces;
+}
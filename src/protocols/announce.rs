@@ -1,8 +1,14 @@
 use crate::{
-    message::MdnsMessage, record::ResourceRecord, service::ServiceState, MdnsError, Query, Service,
+    message::MdnsMessage, question::QType, record::ResourceRecord, registration::Registration,
+    service::ServiceState, utility::SendTarget, MdnsError, Query, Service,
 };
+use rand::{thread_rng, Rng};
+use std::time::Duration;
 
-use super::handler::{Event, Handler};
+use super::{
+    handler::{Event, Handler},
+    query_handler::is_known_answer,
+};
 
 /// Announce MDNS Service
 ///
@@ -16,60 +22,216 @@ use super::handler::{Event, Handler};
 /// - For the unique records, set cache flush bit to '1'
 /// - Wait 1s
 /// - Send unsollicited response again
+///
+/// ## Early Announcement
+/// - Receive a query for our instance or host name while waiting for the second announcement
+/// - Cut the wait short, the service is `ServiceState::EarlyAnnounce`
+/// - Send the second announcement after a random 20-500ms delay
+/// - [RFC6762 Section 6 - Responding](https://www.rfc-editor.org/rfc/rfc6762#section-6)
+///
+/// ## Re-announcement
+/// - Receive a query for our PTR or SRV name while `ServiceState::Registered`
+/// - Only if the last announcement was more than 1s ago and the querier does not know the answers yet,
+///   otherwise the service stays registered and [`super::query_handler::QueryHandler`] responds
+/// - QU questions are left to the [`super::query_handler::QueryHandler`] as well
+/// - Announce again after a random 20-500ms delay, the service is `ServiceState::EarlyAnnounce` meanwhile
+/// - Queries arriving during the delay are coalesced into the same announcement
+/// - [RFC6762 Section 8.3 - Announcing](https://www.rfc-editor.org/rfc/rfc6762#section-8.3)
+///
+/// ## Pause and Resume
+/// - On [`Event::Pause()`] the service is `ServiceState::Paused`, no announcements or responses are sent
+/// - On [`Event::Resume()`] the service waits to be announced again, e.g. after waking from sleep
+///
+/// ## TXT Updates
+/// - On [`Event::UpdateTxt`] the TXT records of the service are replaced
+/// - A registered service announces only its new TXT record, without probing again
+/// - [RFC6763 Section 6.7 - Version Tag](https://www.rfc-editor.org/rfc/rfc6763#section-6.7)
+///
+/// ## Host Name Changes
+/// - On [`Event::SetHostName`] a registered service sends goodbye packets for its old name
+/// - The service is `ServiceState::Prelude` with the new host name and is probed again
 #[derive(Default, Copy, Clone)]
-pub struct AnnouncementHandler<'a> {
-    next: Option<&'a dyn Handler<'a>>,
-}
+pub struct AnnouncementHandler {}
+
+/// Minimum time between announcements of a registered service
+const REANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
 
-impl<'a> Handler<'a> for AnnouncementHandler<'a> {
-    fn set_next(&mut self, next: &'a dyn Handler<'a>) -> &mut dyn Handler<'a> {
-        self.next = Some(next);
-        self
+impl AnnouncementHandler {
+    /// Whether a query asks for the instance or host name of `r`
+    fn is_queried(message: &MdnsMessage, r: &Service) -> bool {
+        let host = r.host.clone() + ".local";
+        let instance = r.to_dns_sd_string();
+
+        let queried = message
+            .questions_for_name(&instance)
+            .chain(message.questions_for_name(&host))
+            .next()
+            .is_some();
+        queried
     }
+
+    /// Whether a query for the PTR or SRV name of registered service `r` is answered by announcing again
+    fn should_reannounce(message: &MdnsMessage, r: &Service) -> bool {
+        if r.announced_at
+            .is_some_and(|t| t.elapsed() <= REANNOUNCE_INTERVAL)
+        {
+            return false;
+        }
+
+        let service_type = r.service_type();
+        let instance = r.to_dns_sd_string();
+
+        message.questions.iter().any(|q| {
+            let name = q.name.as_str();
+
+            let matches = match q.qtype {
+                QType::Ptr => name.eq_ignore_ascii_case(&service_type),
+                QType::Srv => name.eq_ignore_ascii_case(&instance),
+                QType::Any => {
+                    name.eq_ignore_ascii_case(&service_type) || name.eq_ignore_ascii_case(&instance)
+                }
+                _ => false,
+            };
+
+            matches
+                && !q.unicast_question
+                && !MdnsMessage::response(r, q.qtype)
+                    .answers
+                    .iter()
+                    .all(|answer| is_known_answer(message, answer))
+        })
+    }
+}
+
+impl Handler for AnnouncementHandler {
     fn handle(
         &self,
         event: &Event,
-        records: &mut Vec<ResourceRecord>,
+        _records: &mut Vec<ResourceRecord>,
         registration: &mut Option<&mut Service>,
-        query: &mut Option<Query>,
+        _query: &mut Option<Query>,
         timeouts: &mut Vec<(ServiceState, u64)>,
-        queue: &mut Vec<MdnsMessage>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
     ) -> Result<(), MdnsError> {
         if let Some(r) = registration {
-            //TIMEOUTS
             match event {
-                Event::TimeElapsed((s, _t)) => {
-                    //States must match with registered timeouts
-                    if *s == r.state {
-                        match s {
-                            ServiceState::WaitForSecondAnnouncement => {
-                                r.state = ServiceState::SecondAnnouncement
-                            }
-                            _ => {}
-                        }
+                Event::Pause() => {
+                    debug!("Pausing {}.{}.{}.local", r.host, r.service, r.protocol);
+
+                    #[cfg(feature = "metrics")]
+                    if r.state == ServiceState::Registered {
+                        metrics::gauge!("mdns_registrations_active").decrement(1);
                     }
+
+                    r.state = ServiceState::Paused;
+                    r.registered_at = None;
                 }
-                _ => {}
-            }
+                Event::Resume() => {
+                    if r.state == ServiceState::Paused {
+                        debug!("Resuming {}.{}.{}.local", r.host, r.service, r.protocol);
+                        r.state = ServiceState::WaitForAnnouncing;
+                        timeouts.push((r.state, 0));
+                    }
+                }
+                Event::UpdateTxt(instance, txt_records) => {
+                    if r.to_dns_sd_string().eq_ignore_ascii_case(instance) {
+                        r.txt_records = txt_records.clone();
 
-            //STATE MANAGEMENT
-            match r.state {
-                ServiceState::FirstAnnouncement => {
-                    queue.push(MdnsMessage::announce(r));
-                    debug!("First Announcement Sent");
-                    r.state = ServiceState::WaitForSecondAnnouncement;
-                    timeouts.push((r.state, 1000));
+                        if r.state == ServiceState::Registered {
+                            debug!("TXT records updated to {:?}", r.txt_records);
+                            queue.push((MdnsMessage::txt_update(r), SendTarget::Multicast));
+                        }
+                    }
                 }
-                ServiceState::SecondAnnouncement => {
-                    queue.push(MdnsMessage::announce(r));
-                    debug!("Second Announcement Sent, REGISTERED");
-                    r.state = ServiceState::Registered;
+                Event::SetHostName(host) => {
+                    debug!("Renaming {} to {}", r.host, host);
+
+                    if r.state == ServiceState::Registered {
+                        queue.push((MdnsMessage::goodbye(r), SendTarget::Multicast));
+
+                        #[cfg(feature = "metrics")]
+                        metrics::gauge!("mdns_registrations_active").decrement(1);
+                    }
+
+                    r.host = host.clone();
+                    r.state = ServiceState::Prelude;
+                    r.registered_at = None;
                 }
                 _ => {}
             }
-        }
-        if let Some(v) = &self.next {
-            v.handle(event, records, registration, query, timeouts, queue)?;
+
+            Registration::update(r, |registration| {
+                //TIMEOUTS
+                //States must match with registered timeouts
+                let elapsed = |state| matches!(event, Event::TimeElapsed((s, _t)) if *s == state);
+                let query = match event {
+                    Event::Message(message) if message.is_query() => Some(message),
+                    _ => None,
+                };
+
+                let registration = match registration {
+                    Registration::WaitForSecondAnnouncement(a) if elapsed(a.state()) => {
+                        Registration::SecondAnnouncement(a.elapsed())
+                    }
+                    Registration::EarlyAnnounce(a) if elapsed(a.state()) => {
+                        Registration::SecondAnnouncement(a.elapsed())
+                    }
+                    //Incoming query for our name while waiting to announce again
+                    Registration::WaitForSecondAnnouncement(a)
+                        if query.is_some_and(|m| Self::is_queried(m, a.service())) =>
+                    {
+                        debug!(
+                            "Queried for {}, announcing early",
+                            a.service().to_dns_sd_string()
+                        );
+                        Registration::EarlyAnnounce(
+                            a.announce_early(thread_rng().gen_range(20..=500)),
+                        )
+                    }
+                    //Incoming query for our service while registered, announce again
+                    //Further queries are coalesced until the announcement is sent
+                    Registration::Registered(a)
+                        if query.is_some_and(|m| Self::should_reannounce(m, a.service())) =>
+                    {
+                        debug!(
+                            "Queried for {}, announcing again",
+                            a.service().to_dns_sd_string()
+                        );
+
+                        #[cfg(feature = "metrics")]
+                        metrics::gauge!("mdns_registrations_active").decrement(1);
+
+                        Registration::EarlyAnnounce(
+                            a.announce_again(thread_rng().gen_range(20..=500)),
+                        )
+                    }
+                    registration => registration,
+                };
+
+                //STATE MANAGEMENT
+                let registration = match registration {
+                    Registration::FirstAnnouncement(a) => {
+                        let (a, announcement) = a.announce(1000);
+                        queue.push((announcement, SendTarget::Multicast));
+                        debug!("First Announcement Sent");
+                        Registration::WaitForSecondAnnouncement(a)
+                    }
+                    Registration::SecondAnnouncement(a) => {
+                        let (a, announcement) = a.announce();
+                        queue.push((announcement, SendTarget::Multicast));
+                        debug!("Second Announcement Sent, REGISTERED");
+
+                        #[cfg(feature = "metrics")]
+                        metrics::gauge!("mdns_registrations_active").increment(1);
+
+                        Registration::Registered(a)
+                    }
+                    registration => registration,
+                };
+
+                timeouts.extend(registration.pending_timeout());
+                registration
+            });
         }
         Ok(())
     }
@@ -77,16 +239,18 @@ impl<'a> Handler<'a> for AnnouncementHandler<'a> {
 
 #[test]
 fn test_announce_handler() {
+    use crate::service::ServiceBuilder;
+
     //Mock Service
     //Result if Registration Handler worked properly
-    let mut service = Service {
-        host: "TestMachine".into(),
-        service: "_test".into(),
-        protocol: "_tcp".into(),
-        port: 53000,
-        txt_records: vec![],
-        state: ServiceState::FirstAnnouncement,
-    };
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::FirstAnnouncement;
 
     let handler = AnnouncementHandler::default();
 
@@ -126,6 +290,346 @@ fn test_announce_handler() {
         )
         .unwrap();
 
+    assert_eq!(service.state, ServiceState::Registered);
+    assert!(service.elapsed_since_registered().is_some());
+    assert_eq!(queue.len(), 1);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_announce_metrics() {
+    use crate::service::ServiceBuilder;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::FirstAnnouncement;
+
+    let handler = AnnouncementHandler::default();
+
+    metrics::with_local_recorder(&recorder, || {
+        for event in [
+            Event::Ttl(),
+            Event::TimeElapsed((ServiceState::WaitForSecondAnnouncement, 1000)),
+        ] {
+            handler
+                .handle(
+                    &event,
+                    &mut vec![],
+                    &mut Some(&mut service),
+                    &mut None,
+                    &mut vec![],
+                    &mut vec![],
+                )
+                .unwrap();
+        }
+    });
+
+    let registrations = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find(|(key, _, _, _)| key.key().name() == "mdns_registrations_active")
+        .map(|(_, _, _, value)| value);
+
+    assert_eq!(service.state, ServiceState::Registered);
+    assert_eq!(registrations, Some(DebugValue::Gauge(1.0.into())));
+}
+
+#[test]
+fn test_pause_resume() {
+    use crate::{
+        name::Name,
+        protocols::{handler::chain, probe::ProbeHandler, query_handler::QueryHandler},
+        question::{QClass, QType, Question},
+        service::ServiceBuilder,
+    };
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+
+    //Chain as used by the event loop
+    let probe_handler = chain(
+        ProbeHandler::default(),
+        chain(AnnouncementHandler::default(), QueryHandler::default()),
+    );
+
+    let mut query = MdnsMessage::default();
+    query.questions.push(Question {
+        name: Name::new("TestMachine._test._tcp.local".into()).unwrap(),
+        qtype: QType::Srv,
+        qclass: QClass::In,
+        unicast_question: false,
+    });
+
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    //Paused services produce no outgoing messages
+    for event in [
+        Event::Pause(),
+        Event::Ttl(),
+        Event::TimeElapsed((ServiceState::WaitForSecondAnnouncement, 1000)),
+        Event::Message(query),
+    ] {
+        probe_handler
+            .handle(
+                &event,
+                &mut vec![],
+                &mut Some(&mut service),
+                &mut None,
+                &mut timeouts,
+                &mut queue,
+            )
+            .unwrap();
+    }
+
+    assert_eq!(service.state, ServiceState::Paused);
+    assert!(timeouts.is_empty());
+    assert!(queue.is_empty());
+
+    //Resuming announces the service again
+    for event in [
+        Event::Resume(),
+        Event::TimeElapsed((ServiceState::WaitForAnnouncing, 0)),
+    ] {
+        probe_handler
+            .handle(
+                &event,
+                &mut vec![],
+                &mut Some(&mut service),
+                &mut None,
+                &mut timeouts,
+                &mut queue,
+            )
+            .unwrap();
+    }
+
+    assert_eq!(timeouts[0], (ServiceState::WaitForAnnouncing, 0));
+    assert_eq!(service.state, ServiceState::WaitForSecondAnnouncement);
+    assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn test_early_announce() {
+    use crate::{
+        question::{QType, Question},
+        service::ServiceBuilder,
+    };
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::WaitForSecondAnnouncement;
+
+    let handler = AnnouncementHandler::default();
+
+    let mut query = MdnsMessage::default();
+    query.questions.push(Question {
+        qtype: QType::Srv,
+        ..Question::for_service("testmachine._test._tcp.local")
+    });
+    query.header.qdcount = 1;
+
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    //Step 1: Query for our instance name cuts the wait short
+    handler
+        .handle(
+            &Event::Message(query),
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+    assert_eq!(service.state, ServiceState::EarlyAnnounce);
+    assert_eq!(timeouts.len(), 1);
+    assert_eq!(timeouts[0].0, ServiceState::EarlyAnnounce);
+    assert!((20..=500).contains(&timeouts[0].1));
+    assert!(queue.is_empty());
+
+    //Step 2: The random delay has passed, the second announcement is sent
+    handler
+        .handle(
+            &Event::TimeElapsed(timeouts[0]),
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut vec![],
+            &mut queue,
+        )
+        .unwrap();
+
+    assert_eq!(service.state, ServiceState::Registered);
+    assert_eq!(queue.len(), 1);
+    assert_eq!(
+        queue[0].0.to_bytes(),
+        MdnsMessage::announce(&service).to_bytes()
+    );
+}
+
+#[test]
+fn test_reannounce() {
+    use crate::{
+        protocols::{handler::chain, query_handler::QueryHandler},
+        question::{QType, Question},
+        service::ServiceBuilder,
+    };
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+
+    //Chain as used by the event loop
+    let handler = chain(AnnouncementHandler::default(), QueryHandler::default());
+
+    let mut ptr_query = MdnsMessage::default();
+    ptr_query
+        .questions
+        .push(Question::for_service("_test._tcp.local"));
+    ptr_query.header.qdcount = 1;
+
+    let mut srv_query = MdnsMessage::default();
+    srv_query.questions.push(Question {
+        qtype: QType::Srv,
+        ..Question::for_service("TestMachine._test._tcp.local")
+    });
+    srv_query.header.qdcount = 1;
+
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    //Step 1: Two queries for our service are coalesced
+    for query in [ptr_query, srv_query] {
+        handler
+            .handle(
+                &Event::Message(query),
+                &mut vec![],
+                &mut Some(&mut service),
+                &mut None,
+                &mut timeouts,
+                &mut queue,
+            )
+            .unwrap();
+    }
+
+    assert_eq!(service.state, ServiceState::EarlyAnnounce);
+    assert_eq!(timeouts.len(), 1);
+    assert!((20..=500).contains(&timeouts[0].1));
+    assert!(queue.is_empty());
+
+    //Step 2: A single announcement is sent after the delay
+    handler
+        .handle(
+            &Event::TimeElapsed(timeouts[0]),
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut vec![],
+            &mut queue,
+        )
+        .unwrap();
+
     assert_eq!(service.state, ServiceState::Registered);
     assert_eq!(queue.len(), 1);
+    assert_eq!(
+        queue[0].0.to_bytes(),
+        MdnsMessage::announce(&service).to_bytes()
+    );
+}
+
+#[test]
+fn test_reannounce_suppressed() {
+    use crate::{
+        protocols::{handler::chain, query_handler::QueryHandler},
+        question::Question,
+        service::ServiceBuilder,
+    };
+    use tokio::time::Instant;
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+
+    //Chain as used by the event loop
+    let handler = chain(AnnouncementHandler::default(), QueryHandler::default());
+
+    let ptr_query = MdnsMessage::default().with_question(Question::for_service("_test._tcp.local"));
+
+    let handle = |query: MdnsMessage, service: &mut Service| {
+        let mut timeouts = vec![];
+        let mut queue = vec![];
+        handler
+            .handle(
+                &Event::Message(query),
+                &mut vec![],
+                &mut Some(service),
+                &mut None,
+                &mut timeouts,
+                &mut queue,
+            )
+            .unwrap();
+        (timeouts, queue)
+    };
+
+    //Step 1: The querier already knows our PTR record, nothing is announced or answered
+    let ptr = ResourceRecord::create_ptr_record_from_service(&service);
+    let known = ptr_query.clone().with_known_answers(vec![ptr]);
+    let (timeouts, queue) = handle(known, &mut service);
+
+    assert_eq!(service.state, ServiceState::Registered);
+    assert!(timeouts.is_empty());
+    assert!(queue.is_empty());
+
+    //Step 2: Announced less than 1s ago, the QueryHandler answers instead
+    service.announced_at = Some(Instant::now());
+    let (timeouts, queue) = handle(ptr_query.clone(), &mut service);
+
+    assert_eq!(service.state, ServiceState::Registered);
+    assert_eq!(timeouts.len(), 1);
+    assert_eq!(timeouts[0].0, ServiceState::WaitForQueryResponse);
+    assert!(queue.is_empty());
+
+    //Step 3: QU questions are answered by the QueryHandler as well
+    service.announced_at = None;
+    let qu_query = MdnsMessage::default().with_question(Question {
+        unicast_question: true,
+        ..Question::for_service("_test._tcp.local")
+    });
+    let (timeouts, _) = handle(qu_query, &mut service);
+
+    assert_eq!(service.state, ServiceState::Registered);
+    assert_eq!(timeouts[0].0, ServiceState::WaitForQueryResponse);
 }
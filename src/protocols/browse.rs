@@ -1,57 +1,184 @@
 use crate::{
-    message::MdnsMessage, record::ResourceRecord, service::ServiceState, MdnsError, Query, Service,
+    message::MdnsMessage,
+    record::ResourceRecord,
+    service::{BrowseState, ServiceState},
+    utility::SendTarget,
+    MdnsError, Query, Service,
 };
 
 use super::handler::{Event, Handler};
 
-/// Probe MDNS Service
+/// Browse for MDNS Services
 ///
-/// First step in MDNS announcement protocol
+/// Sets up the [`Query`] for a service type and sends the first query,
+/// [`super::continuous_query::ContinuousQueryHandler`] schedules the queries after it
 ///
-/// This step is only available if MdnsResolver state is `State::Probing`
-///
-/// [RFC6762 Section 8.1 - Probing](https://www.rfc-editor.org/rfc/rfc6762#section-8.1)
-/// - Wait for a 0-250ms time period to prevent simultaneous querying by devices on startup
-/// - Query the service
-/// - Wait for 250ms or get a response -> Return Conflict Error
-/// - Query again
-/// - Wait for 250ms or get a response -> Return Conflict Error
-/// - Return Ok -> Service has not been registrered
+/// ## RFC Reference
+/// - [RFC6762 Section 5.2 - Continuous Multicast DNS Querying](https://www.rfc-editor.org/rfc/rfc6762#section-5.2)
 ///
+/// ## Protocol
+/// - Receive a browse command, wait for the initial delay of the [`Query`]
+/// - Send the first query, the next one follows after 1s
 #[derive(Default, Copy, Clone)]
-pub struct BrowseHandler<'a> {
-    next: Option<&'a dyn Handler<'a>>,
-}
+pub struct BrowseHandler {}
 
-impl<'a> Handler<'a> for BrowseHandler<'a> {
-    fn set_next(&mut self, next: &'a dyn Handler<'a>) -> &mut dyn Handler<'a> {
-        self.next = Some(next);
-        self
+impl BrowseHandler {
+    /// Send the first query for `q`, the next one follows after 1s
+    fn send_first_query(q: &mut Query, queue: &mut Vec<(MdnsMessage, SendTarget)>) {
+        debug!("Sending first Query for {}", q.name);
+        queue.push((MdnsMessage::query(&q.name), SendTarget::Multicast));
+
+        q.interval = 1000;
+        q.timeout = 1000;
+        q.state = BrowseState::Sent;
     }
+}
+
+impl Handler for BrowseHandler {
     fn handle(
         &self,
         event: &Event,
-        records: &mut Vec<ResourceRecord>,
-        registration: &mut Option<&mut Service>,
+        _records: &mut Vec<ResourceRecord>,
+        _registration: &mut Option<&mut Service>,
         query: &mut Option<Query>,
         timeouts: &mut Vec<(ServiceState, u64)>,
-        queue: &mut Vec<MdnsMessage>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
     ) -> Result<(), MdnsError> {
         match event {
-            Event::Browse(n) => {
+            Event::Browse(n, filter, delay) => {
                 debug!("Added new Query for {} ", n);
 
-                *query = Some(Query {
+                let mut q = Query {
                     name: n.to_string(),
+                    filter: filter.clone(),
                     ..Default::default()
-                });
+                }
+                .with_initial_delay(*delay);
+
+                if q.initial_delay > 0 {
+                    timeouts.push((ServiceState::BrowseDelay, q.initial_delay));
+                } else {
+                    Self::send_first_query(&mut q, queue);
+                }
+
+                *query = Some(q);
+            }
+            Event::TimeElapsed((ServiceState::BrowseDelay, _t)) => {
+                if let Some(q) = query {
+                    if q.state == BrowseState::Prelude {
+                        Self::send_first_query(q, queue);
+                    }
+                }
             }
             _ => {}
         }
-        if let Some(v) = &self.next {
-            v.handle(event, records, registration, query, timeouts, queue)?;
-        }
 
         Ok(())
     }
 }
+
+#[test]
+fn test_browse_handler() {
+    use crate::service::BrowseFilter;
+
+    let handler = BrowseHandler::default();
+
+    let mut query = None;
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    let mut handle = |event: Event, query: &mut Option<Query>| {
+        handler
+            .handle(
+                &event,
+                &mut vec![],
+                &mut None,
+                query,
+                &mut timeouts,
+                &mut queue,
+            )
+            .unwrap();
+    };
+
+    //Step 1: Wait for the initial delay
+    handle(
+        Event::Browse("_test._tcp.local".into(), BrowseFilter::default(), 100),
+        &mut query,
+    );
+    assert_eq!(query.as_ref().unwrap().state, BrowseState::Prelude);
+
+    //Step 2: Send the first query, the next one follows after 1s
+    handle(
+        Event::TimeElapsed((ServiceState::BrowseDelay, 100)),
+        &mut query,
+    );
+    let q = query.as_ref().unwrap();
+    assert_eq!(q.state, BrowseState::Sent);
+    assert_eq!((q.timeout, q.interval), (1000, 1000));
+
+    //Step 3: Later queries are left to the ContinuousQueryHandler
+    handle(Event::Ttl(), &mut query);
+    handle(
+        Event::TimeElapsed((ServiceState::BrowseDelay, 100)),
+        &mut query,
+    );
+
+    assert_eq!(timeouts, vec![(ServiceState::BrowseDelay, 100)]);
+    assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn test_browse_initial_delay() {
+    use crate::service::BrowseFilter;
+
+    let handler = BrowseHandler::default();
+
+    let mut query = None;
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    handler
+        .handle(
+            &Event::Browse("_test._tcp.local".into(), BrowseFilter::default(), 250),
+            &mut vec![],
+            &mut None,
+            &mut query,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+    //No query is sent before the delay fires
+    assert!(queue.is_empty());
+    assert_eq!(timeouts, vec![(ServiceState::BrowseDelay, 250)]);
+    assert_eq!(query.as_ref().unwrap().initial_delay, 250);
+
+    //Other events do not send the query early
+    handler
+        .handle(
+            &Event::Ttl(),
+            &mut vec![],
+            &mut None,
+            &mut query,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+    assert!(queue.is_empty());
+
+    let t = timeouts.pop().unwrap();
+    handler
+        .handle(
+            &Event::TimeElapsed(t),
+            &mut vec![],
+            &mut None,
+            &mut query,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+    assert_eq!(queue.len(), 1);
+    assert!(timeouts.is_empty());
+    assert_eq!(query.as_ref().unwrap().state, BrowseState::Sent);
+}
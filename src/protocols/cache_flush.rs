@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{
+    message::MdnsMessage, record::ResourceRecord, service::ServiceState, utility::SendTarget,
+    MdnsError, Query, Service,
+};
+
+use super::handler::{Event, Handler, RecordCallback};
+
+/// Cache Flush
+///
+/// Removes outdated records from the cache when a record with the cache flush bit arrives
+///
+/// ## RFC Reference
+/// - [RFC6762 Section 10.2 - Announcements to Flush Outdated Cache Entries](https://www.rfc-editor.org/rfc/rfc6762#section-10.2)
+/// - [RFC6762 Section 11.3 - Cache Flush on Failure Indication](https://www.rfc-editor.org/rfc/rfc6762#section-11.3)
+///
+/// ## Protocol
+/// - Receive a response with a record that has the cache flush bit set
+/// - Remove all cached records with the same name and class received more than 1 second ago
+/// - Records received within the last second are spared, they may be part of the same announcement
+/// - Call the removed callbacks for each removed record
+#[derive(Default, Copy, Clone)]
+pub struct CacheFlushHandler<'a> {
+    removed_callbacks: &'a [RecordCallback],
+}
+
+impl<'a> CacheFlushHandler<'a> {
+    /// Call `callbacks` for each record removed from the cache
+    pub fn with_removed_callbacks(mut self, callbacks: &'a [RecordCallback]) -> Self {
+        self.removed_callbacks = callbacks;
+        self
+    }
+}
+
+impl<'a> Handler for CacheFlushHandler<'a> {
+    fn handle(
+        &self,
+        event: &Event,
+        records: &mut Vec<ResourceRecord>,
+        _registration: &mut Option<&mut Service>,
+        _query: &mut Option<Query>,
+        _timeouts: &mut Vec<(ServiceState, u64)>,
+        _queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        match event {
+            //Only responses carry records with the cache flush bit
+            Event::Message(message) if message.is_response() => {
+                let now = Instant::now();
+
+                for flush in message
+                    .answers
+                    .iter()
+                    .chain(message.additionals.iter())
+                    .filter(|r| r.cache_flush)
+                {
+                    records.retain(|rec| {
+                        let outdated = rec
+                            .received_at
+                            .is_some_and(|t| now.duration_since(t) > Duration::from_secs(1));
+
+                        let flushed = outdated
+                            && rec.record_class == flush.record_class
+                            && rec.name.as_str().eq_ignore_ascii_case(flush.name.as_str());
+
+                        if flushed {
+                            debug!("Flushing record {}", rec.name.as_str());
+                            self.removed_callbacks.iter().for_each(|f| f(rec.clone()));
+                        }
+
+                        !flushed
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_cache_flush_handler() {
+    use crate::name::Name;
+
+    let received = |ip| {
+        let mut record = ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            ip,
+        );
+        record.received_at = Some(Instant::now());
+        record
+    };
+
+    //Old record, received more than 1 second before the flush
+    let mut records = vec![received([192, 168, 1, 2])];
+
+    tokio::time::advance(Duration::from_millis(1500)).await;
+
+    //New record, received within the last second
+    records.push(received([192, 168, 1, 3]));
+
+    let mut flush = received([192, 168, 1, 3]);
+    flush.cache_flush = true;
+
+    let mut message = MdnsMessage::default();
+    message.header.qr = true;
+    message.answers.push(flush);
+
+    CacheFlushHandler::default()
+        .handle(
+            &Event::Message(message),
+            &mut records,
+            &mut None,
+            &mut None,
+            &mut vec![],
+            &mut vec![],
+        )
+        .unwrap();
+
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].to_bytes(), received([192, 168, 1, 3]).to_bytes());
+}
@@ -0,0 +1,122 @@
+use crate::{
+    message::MdnsMessage,
+    record::ResourceRecord,
+    service::{BrowseState, ServiceState},
+    utility::SendTarget,
+    MdnsError, Query, Service,
+};
+
+use super::handler::{Event, Handler};
+
+/// Maximum interval between queries in ms
+pub(crate) const MAX_QUERY_INTERVAL: u64 = 3_600_000;
+
+/// Continuous Querying
+///
+/// Keeps querying for the PTR records of a browsed service type at increasing intervals
+///
+/// Schedules the queries on the 1s [`Event::Ttl`] tick instead of timeouts,
+/// [`super::browse::BrowseHandler`] sends the first query
+///
+/// ## RFC Reference
+/// - [RFC6762 Section 5.2 - Continuous Multicast DNS Querying](https://www.rfc-editor.org/rfc/rfc6762#section-5.2)
+///
+/// ## Protocol
+/// - Count [`Query::timeout`] down by 1s on each tick, once the first query is sent
+/// - When it reaches zero, query again and double [`Query::interval`], up to 60 minutes
+/// - Once services were found the browse is complete, it keeps querying
+/// - A newly discovered service restarts the interval at 1s
+#[derive(Default, Copy, Clone)]
+pub struct ContinuousQueryHandler {}
+
+impl Handler for ContinuousQueryHandler {
+    fn handle(
+        &self,
+        event: &Event,
+        _records: &mut Vec<ResourceRecord>,
+        _registration: &mut Option<&mut Service>,
+        query: &mut Option<Query>,
+        _timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        //The first query is not sent yet
+        let q = match query {
+            Some(q) if q.state != BrowseState::Prelude => q,
+            _ => return Ok(()),
+        };
+
+        match event {
+            Event::Ttl() => {
+                q.timeout = q.timeout.saturating_sub(1000);
+
+                if q.timeout == 0 {
+                    debug!("Querying again for {}", q.name);
+                    queue.push((MdnsMessage::query(&q.name), SendTarget::Multicast));
+
+                    q.interval = (q.interval * 2).clamp(1000, MAX_QUERY_INTERVAL);
+                    q.timeout = q.interval;
+                    q.state = if q.found > 0 {
+                        BrowseState::Complete
+                    } else {
+                        BrowseState::Waiting(q.interval)
+                    };
+                }
+            }
+            Event::Discovered(_) => {
+                debug!("Discovered a service for {}, querying again in 1s", q.name);
+                q.interval = 1000;
+                q.timeout = 1000;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_continuous_query() {
+    let handler = ContinuousQueryHandler::default();
+
+    let mut query = Some(Query {
+        name: "_test._tcp.local".into(),
+        state: BrowseState::Sent,
+        timeout: 1000,
+        interval: 1000,
+        ..Default::default()
+    });
+
+    let handle = |event: Event, query: &mut Option<Query>| {
+        let mut queue = vec![];
+        handler
+            .handle(
+                &event,
+                &mut vec![],
+                &mut None,
+                query,
+                &mut vec![],
+                &mut queue,
+            )
+            .unwrap();
+        queue.len()
+    };
+
+    //Queries after 1s, 2s and 4s
+    let ticks: Vec<usize> = (1..=10)
+        .filter(|_| handle(Event::Ttl(), &mut query) == 1)
+        .collect();
+    assert_eq!(ticks, [1, 3, 7]);
+    assert_eq!(query.as_ref().unwrap().interval, 8000);
+    assert_eq!(query.as_ref().unwrap().state, BrowseState::Waiting(8000));
+
+    //A discovered service restarts the interval and completes the browse
+    query.as_mut().unwrap().add_service(Service::default());
+    handle(Event::Discovered(Service::default()), &mut query);
+    assert_eq!(handle(Event::Ttl(), &mut query), 1);
+    assert_eq!(query.as_ref().unwrap().interval, 2000);
+    assert_eq!(query.as_ref().unwrap().state, BrowseState::Complete);
+
+    //No queries before the first one is sent
+    let mut query = Some(Query::default());
+    assert_eq!(handle(Event::Ttl(), &mut query), 0);
+}
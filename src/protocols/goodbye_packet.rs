@@ -1,5 +1,6 @@
 use crate::{
-    message::MdnsMessage, record::ResourceRecord, service::ServiceState, MdnsError, Query, Service,
+    message::MdnsMessage, record::ResourceRecord, service::ServiceState, utility::SendTarget,
+    MdnsError, Query, Service,
 };
 
 use super::handler::{Event, Handler};
@@ -14,38 +15,128 @@ use super::handler::{Event, Handler};
 ///
 /// [RFC6762 Section 10.1 - Goodbye Packets](https://www.rfc-editor.org/rfc/rfc6762#section-10.1)
 /// - Send unsollicited response with a TTL of 0
-#[derive(Default, Copy, Clone)]
-pub struct GoodbyeHandler<'a> {
-    next: Option<&'a dyn Handler<'a>>,
+/// - Repeat the response every second for reliability, see [`GoodbyeHandler::with_repeat_count()`]
+///
+/// Goodbyes of other hosts remove their services from the active [`Query`] on [`Event::ServiceRemoved`],
+/// so they can be found again when they return
+#[derive(Copy, Clone)]
+pub struct GoodbyeHandler {
+    repeat_count: u8,
+}
+
+impl Default for GoodbyeHandler {
+    fn default() -> Self {
+        Self { repeat_count: 1 }
+    }
 }
 
-impl<'a> Handler<'a> for GoodbyeHandler<'a> {
-    fn set_next(&mut self, next: &'a dyn Handler<'a>) -> &mut dyn Handler<'a> {
-        self.next = Some(next);
+impl GoodbyeHandler {
+    /// Send the goodbye packet `n` times with an interval of 1 second, defaults to 1
+    pub fn with_repeat_count(mut self, n: u8) -> Self {
+        self.repeat_count = n;
         self
     }
+}
+
+impl Handler for GoodbyeHandler {
     fn handle(
         &self,
         event: &Event,
-        records: &mut Vec<ResourceRecord>,
+        _records: &mut Vec<ResourceRecord>,
         registration: &mut Option<&mut Service>,
         query: &mut Option<Query>,
         timeouts: &mut Vec<(ServiceState, u64)>,
-        queue: &mut Vec<MdnsMessage>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
     ) -> Result<(), MdnsError> {
+        if let (Event::ServiceRemoved(instance), Some(q)) = (event, query) {
+            debug!("{} sent goodbye packets", instance);
+
+            q.resolved.retain(|r| !r.eq_ignore_ascii_case(instance));
+            q.services
+                .retain(|s| !s.to_dns_sd_string().eq_ignore_ascii_case(instance));
+        }
+
         if let Some(r) = registration {
             match event {
                 Event::Closing() => {
                     info!("Sending Goodbye Packets!");
-                    queue.push(MdnsMessage::goodbye(&r));
+                    queue.push((MdnsMessage::goodbye(r), SendTarget::Multicast));
+
+                    if self.repeat_count > 1 {
+                        timeouts.push((ServiceState::GoodbyeRetry(self.repeat_count - 1), 1000));
+                    }
+
+                    #[cfg(feature = "metrics")]
+                    if r.state == ServiceState::Registered {
+                        metrics::gauge!("mdns_registrations_active").decrement(1);
+                    }
+                }
+                Event::TimeElapsed((ServiceState::GoodbyeRetry(remaining), _t)) => {
+                    debug!("Repeating Goodbye Packets, {} left", remaining);
+                    queue.push((MdnsMessage::goodbye(r), SendTarget::Multicast));
+
+                    if *remaining > 1 {
+                        timeouts.push((ServiceState::GoodbyeRetry(remaining - 1), 1000));
+                    }
                 }
                 _ => {}
             }
         }
-        if let Some(v) = &self.next {
-            v.handle(event, records, registration, query, timeouts, queue)?;
-        }
 
         Ok(())
     }
 }
+
+#[test]
+fn test_goodbye_repeat() {
+    use crate::service::ServiceBuilder;
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+
+    let handler = GoodbyeHandler::default().with_repeat_count(3);
+
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    handler
+        .handle(
+            &Event::Closing(),
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+    assert_eq!(queue.len(), 1);
+
+    //Each retry queues another goodbye until the repeat count is reached
+    while let Some(t) = timeouts.pop() {
+        assert_eq!(t.1, 1000);
+
+        handler
+            .handle(
+                &Event::TimeElapsed(t),
+                &mut vec![],
+                &mut Some(&mut service),
+                &mut None,
+                &mut timeouts,
+                &mut queue,
+            )
+            .unwrap();
+    }
+
+    assert_eq!(queue.len(), 3);
+    assert!(queue
+        .iter()
+        .all(|(m, _)| m.answers.iter().all(|r| r.is_goodbye())));
+}
+
@@ -1,6 +1,11 @@
 use crate::{
-    message::MdnsMessage, record::ResourceRecord, service::ServiceState, MdnsError, Query, Service,
+    message::MdnsMessage,
+    record::ResourceRecord,
+    service::{BrowseFilter, ServiceState},
+    utility::SendTarget,
+    MdnsError, Query, Service,
 };
+use std::future::Future;
 
 /// Chain of Responsibility Handler
 ///
@@ -16,10 +21,15 @@ use crate::{
 ///
 /// For timing purposes like updating the Time To Live for records or for timeouts, such as in Probing or Announcing, a Event::TimeElepased event is sent into the chain
 ///
-/// Each chain part implements the Handler trait
-
-pub trait Handler<'a> {
-    fn set_next(&mut self, next: &'a dyn Handler<'a>) -> &mut dyn Handler<'a>;
+/// Each chain part implements the Handler trait, the parts are linked with [`chain()`]
+///
+/// `registration` borrows one of the registered services of the client in place, see [`crate::DnsSd2::handle()`],
+/// so changes to its state are kept without moving the service in and out of the client
+///
+/// Handlers are Sync so the event loop can run in a spawned task
+///
+/// Handlers which need to await implement [`AsyncHandler`] instead
+pub trait Handler: Sync {
     fn handle(
         &self,
         event: &Event,
@@ -27,15 +37,155 @@ pub trait Handler<'a> {
         registration: &mut Option<&mut Service>,
         query: &mut Option<Query>,
         timeouts: &mut Vec<(ServiceState, u64)>,
-        queue: &mut Vec<MdnsMessage>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
     ) -> Result<(), MdnsError>;
 }
 
+/// Asynchronous Chain of Responsibility Handler
+///
+/// Like [`Handler`], but the handler can await, e.g. to fetch records from an external source
+///
+/// Every [`Handler`] is an AsyncHandler which completes right away,
+/// the event loop in [`crate::DnsSd2::init()`] awaits the chain for each event
+///
+/// Implement it with `async fn handle(..)`, the returned future must be Send.
+/// AsyncHandlers are linked with [`async_chain()`]
+pub trait AsyncHandler: Sync {
+    fn handle(
+        &self,
+        event: &Event,
+        records: &mut Vec<ResourceRecord>,
+        registration: &mut Option<&mut Service>,
+        query: &mut Option<Query>,
+        timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> impl Future<Output = Result<(), MdnsError>> + Send;
+}
+
+impl<H: Handler> AsyncHandler for H {
+    async fn handle(
+        &self,
+        event: &Event,
+        records: &mut Vec<ResourceRecord>,
+        registration: &mut Option<&mut Service>,
+        query: &mut Option<Query>,
+        timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        Handler::handle(self, event, records, registration, query, timeouts, queue)
+    }
+}
+
+/// Two handlers linked in a chain, see [`chain()`]
+#[derive(Default, Clone)]
+pub struct ChainedHandler<H1, H2> {
+    first: H1,
+    second: H2,
+}
+
+/// Link two handlers, events are handled by `first` and then by `second`
+///
+/// Chains are built front to back by nesting, the second handler of a chain can be a chain itself
+///
+/// ## Example
+///
+/// ```
+/// use dns_sd2::protocols::{
+///     announce::AnnouncementHandler, goodbye_packet::GoodbyeHandler, handler::chain,
+///     probe::ProbeHandler,
+/// };
+///
+/// let handler = chain(
+///     ProbeHandler::default(),
+///     chain(AnnouncementHandler::default(), GoodbyeHandler::default()),
+/// );
+/// ```
+pub fn chain<H1: Handler, H2: Handler>(first: H1, second: H2) -> ChainedHandler<H1, H2> {
+    ChainedHandler { first, second }
+}
+
+impl<H1: Handler, H2: Handler> Handler for ChainedHandler<H1, H2> {
+    fn handle(
+        &self,
+        event: &Event,
+        records: &mut Vec<ResourceRecord>,
+        registration: &mut Option<&mut Service>,
+        query: &mut Option<Query>,
+        timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        self.first
+            .handle(event, records, registration, query, timeouts, queue)?;
+        self.second
+            .handle(event, records, registration, query, timeouts, queue)
+    }
+}
+
+/// Two handlers linked in a chain of which either can await, see [`async_chain()`]
+#[derive(Default, Clone)]
+pub struct AsyncChainedHandler<H1, H2> {
+    first: H1,
+    second: H2,
+}
+
+/// Link two [`AsyncHandler`]s, events are handled by `first` and then by `second`
+///
+/// Like [`chain()`], a [`Handler`] can be linked with an AsyncHandler as every Handler is an AsyncHandler
+///
+/// ## Example
+///
+/// ```
+/// use dns_sd2::protocols::{
+///     announce::AnnouncementHandler,
+///     goodbye_packet::GoodbyeHandler,
+///     handler::{async_chain, chain},
+///     probe::ProbeHandler,
+/// };
+///
+/// let handler = async_chain(
+///     ProbeHandler::default(),
+///     chain(AnnouncementHandler::default(), GoodbyeHandler::default()),
+/// );
+/// ```
+pub fn async_chain<H1: AsyncHandler, H2: AsyncHandler>(
+    first: H1,
+    second: H2,
+) -> AsyncChainedHandler<H1, H2> {
+    AsyncChainedHandler { first, second }
+}
+
+impl<H1: AsyncHandler, H2: AsyncHandler> AsyncHandler for AsyncChainedHandler<H1, H2> {
+    async fn handle(
+        &self,
+        event: &Event,
+        records: &mut Vec<ResourceRecord>,
+        registration: &mut Option<&mut Service>,
+        query: &mut Option<Query>,
+        timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        self.first
+            .handle(event, records, registration, query, timeouts, queue)
+            .await?;
+        self.second
+            .handle(event, records, registration, query, timeouts, queue)
+            .await
+    }
+}
+
+/// Callback called with a [`ResourceRecord`] when the cache changes
+///
+/// See [`crate::DnsSd2::on_record_added()`] and [`crate::DnsSd2::on_record_removed()`]
+pub type RecordCallback = Box<dyn Fn(ResourceRecord) + Send + Sync>;
+
 #[derive(Debug)]
 /// Event Enumerator
 ///
 /// Possible message types that are passed into the chain of handlers
 /// They either pass elapsed times, close signals or messages that have arrived on the socket
+///
+/// Non-exhaustive, new commands and signals are added to the chain as the protocol support grows
+#[non_exhaustive]
 pub enum Event {
     /// Message Enum containing an MdnsMessage
     Message(MdnsMessage),
@@ -45,8 +195,193 @@ pub enum Event {
     Ttl(),
     /// Close Signal
     Closing(),
-    /// Browse Command, contains service string. e.g. '_myservice._udp._local'
-    Browse(String),
+    /// Pause Signal, suspends announcements and responses without sending goodbye packets
+    Pause(),
+    /// Resume Signal, announces paused services again
+    Resume(),
+    /// Network Change Signal, the addresses of the local machine may have changed
+    NetworkChange(),
+    /// Sleep Mode Signal, true when the local machine goes to sleep and false when it wakes
+    SleepModeChanged(bool),
+    /// Browse Command, contains service string. e.g. '_myservice._udp._local', a filter for the results
+    /// and the delay in ms before the first query
+    Browse(String, BrowseFilter, u64),
+    /// Cancel Browse Command, stops the active browse
+    CancelBrowse(),
+    /// Update TXT Command, contains the instance name of the registered service (e.g. 'MyMachine._http._tcp.local') and its new TXT records
+    UpdateTxt(String, Vec<String>),
+    /// Set Host Name Command, contains the new host name of the registered services
+    SetHostName(String),
+    /// Service Removed Signal, a browsed service sent goodbye packets, contains its instance name
+    ServiceRemoved(String),
+    /// Service Discovered Signal, contains a fully resolved service of the [`Query`] before it is yielded
+    Discovered(Service),
     /// Register Command, contains
     Register(String, String, String, u16, Vec<String>),
 }
+
+#[test]
+fn test_chain() {
+    use crate::{
+        protocols::{
+            announce::AnnouncementHandler, goodbye_packet::GoodbyeHandler, probe::ProbeHandler,
+        },
+        service::ServiceBuilder,
+    };
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let handler = chain(
+        ProbeHandler::default(),
+        chain(AnnouncementHandler::default(), GoodbyeHandler::default()),
+    );
+
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+    let mut event = Event::Ttl();
+
+    //Each timeout is handed back to the chain until the service is registered
+    loop {
+        Handler::handle(
+            &handler,
+            &event,
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+        if service.state == ServiceState::Registered {
+            break;
+        }
+
+        event = Event::TimeElapsed(timeouts.pop().expect("Should wait for a timeout"));
+    }
+
+    //Two probes and two announcements
+    assert_eq!(queue.len(), 4);
+    assert!(queue[..2].iter().all(|(m, _)| m.is_query()));
+    assert!(queue[2..].iter().all(|(m, _)| m.is_response()));
+
+    queue.clear();
+
+    Handler::handle(
+        &handler,
+        &Event::Closing(),
+        &mut vec![],
+        &mut Some(&mut service),
+        &mut None,
+        &mut timeouts,
+        &mut queue,
+    )
+    .unwrap();
+
+    assert_eq!(queue.len(), 1);
+    assert_eq!(
+        queue[0].0.to_bytes(),
+        MdnsMessage::goodbye(&service).to_bytes()
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_async_handler() {
+    use crate::{
+        name::Name, protocols::probe::ProbeHandler, question::QType, service::ServiceBuilder,
+        DnsSd2,
+    };
+    use std::time::Duration;
+
+    //Fetches a record from an external source, which takes a while
+    struct LookupHandler {}
+
+    impl AsyncHandler for LookupHandler {
+        async fn handle(
+            &self,
+            event: &Event,
+            records: &mut Vec<ResourceRecord>,
+            _registration: &mut Option<&mut Service>,
+            _query: &mut Option<Query>,
+            _timeouts: &mut Vec<(ServiceState, u64)>,
+            _queue: &mut Vec<(MdnsMessage, SendTarget)>,
+        ) -> Result<(), MdnsError> {
+            if let Event::Ttl() = event {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                records.push(ResourceRecord::create_a_record(
+                    Name::new("printer.local".into()).unwrap(),
+                    [192, 168, 1, 2],
+                ));
+            }
+
+            Ok(())
+        }
+    }
+
+    let mut client = DnsSd2::default();
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    client
+        .handle(&LookupHandler {}, &Event::Ttl(), &mut timeouts, &mut queue)
+        .await
+        .unwrap();
+    assert_eq!(client.records_for_type(QType::A).len(), 1);
+
+    //Synchronous handlers complete right away
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    AsyncHandler::handle(
+        &ProbeHandler::default(),
+        &Event::Ttl(),
+        &mut vec![],
+        &mut Some(&mut service),
+        &mut None,
+        &mut timeouts,
+        &mut queue,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(service.state, ServiceState::WaitForFirstProbe);
+    assert_eq!(timeouts.len(), 1);
+
+    //Asynchronous and synchronous handlers are linked with async_chain
+    let mut client = DnsSd2::default().send_goodbye_on_drop(false);
+    client.registrations.push(
+        ServiceBuilder::default()
+            .host("TestMachine")
+            .service("_test")
+            .protocol("_tcp")
+            .port(53000)
+            .build()
+            .unwrap(),
+    );
+
+    let handler = async_chain(LookupHandler {}, ProbeHandler::default());
+    let mut timeouts = vec![];
+
+    client
+        .handle(&handler, &Event::Ttl(), &mut timeouts, &mut queue)
+        .await
+        .unwrap();
+
+    assert_eq!(client.records_for_type(QType::A).len(), 1);
+    assert_eq!(
+        client.registrations[0].state,
+        ServiceState::WaitForFirstProbe
+    );
+    assert_eq!(timeouts.len(), 1);
+}
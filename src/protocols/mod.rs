@@ -1,12 +1,18 @@
 pub mod announce;
 pub mod browse;
+pub mod cache_flush;
+pub mod continuous_query;
 pub mod duplicate_answer_suppression;
 pub mod duplicate_question_suppression;
 pub mod goodbye_packet;
 pub mod handler;
 pub mod known_answer_suppression;
+pub mod network_change;
 pub mod passive_failure_observance;
 pub mod probe;
 pub mod probe_conflict;
+pub mod query_handler;
+pub mod response_handler;
+pub mod sleep_proxy;
 pub mod truncated;
 pub mod update_ttl;
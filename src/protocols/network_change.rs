@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{
+    message::MdnsMessage,
+    name::Name,
+    question::QType,
+    record::ResourceRecord,
+    service::ServiceState,
+    utility::{local_ipv4_addresses, local_ipv6_addresses, SendTarget},
+    MdnsError, Query, Service,
+};
+
+use super::handler::{Event, Handler};
+
+/// Network Change
+///
+/// Announces the new addresses of a registered service when the network of the local machine changes
+///
+/// ## RFC Reference
+/// - [RFC6762 Section 8.4 - Updating](https://www.rfc-editor.org/rfc/rfc6762#section-8.4)
+/// - [RFC6762 Section 10.1 - Goodbye Packets](https://www.rfc-editor.org/rfc/rfc6762#section-10.1)
+///
+/// ## Protocol
+/// - Remember the addresses of the local machine once a service is `ServiceState::Registered`
+/// - On [`Event::NetworkChange()`] detect the addresses again
+/// - Send goodbye packets for the A/AAAA records of addresses that are gone
+/// - Announce the service with the new address records, the service is not probed again
+/// - Send the second announcement after 1s, see [`super::announce::AnnouncementHandler`]
+pub struct NetworkChangeHandler {
+    /// Detects the addresses of the local machine
+    detect: fn() -> Vec<IpAddr>,
+    /// Last announced addresses for each host
+    addresses: Mutex<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl Default for NetworkChangeHandler {
+    fn default() -> Self {
+        Self {
+            detect: local_addresses,
+            addresses: Mutex::default(),
+        }
+    }
+}
+
+impl NetworkChangeHandler {
+    /// Detect the addresses of the local machine with `detect` instead of the network interfaces
+    pub fn with_address_source(mut self, detect: fn() -> Vec<IpAddr>) -> Self {
+        self.detect = detect;
+        self
+    }
+}
+
+impl Handler for NetworkChangeHandler {
+    fn handle(
+        &self,
+        event: &Event,
+        _records: &mut Vec<ResourceRecord>,
+        registration: &mut Option<&mut Service>,
+        _query: &mut Option<Query>,
+        timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        if let Some(r) = registration {
+            if r.state == ServiceState::Registered {
+                let mut addresses = self
+                    .addresses
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+
+                let known = addresses
+                    .entry(r.host.clone())
+                    .or_insert_with(|| (self.detect)());
+
+                if let Event::NetworkChange() = event {
+                    let detected = (self.detect)();
+
+                    if detected != *known {
+                        debug!("Network changed, addresses {:?} to {:?}", known, detected);
+
+                        let gone: Vec<ResourceRecord> = known
+                            .iter()
+                            .filter(|ip| !detected.contains(ip))
+                            .map(|ip| address_record(r, *ip))
+                            .collect();
+
+                        for goodbye in MdnsMessage::goodbye_for_records(&gone) {
+                            queue.push((goodbye, SendTarget::Multicast));
+                        }
+
+                        let mut announcement = MdnsMessage::announce(r);
+                        announcement
+                            .additionals
+                            .retain(|a| a.record_type != QType::A && a.record_type != QType::Aaaa);
+                        announcement
+                            .additionals
+                            .extend(detected.iter().map(|ip| address_record(r, *ip)));
+                        queue.push((announcement, SendTarget::Multicast));
+
+                        r.state = ServiceState::WaitForSecondAnnouncement;
+                        timeouts.push((r.state, 1000));
+
+                        *known = detected;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// All non-loopback addresses of this machine, Ipv4 first
+fn local_addresses() -> Vec<IpAddr> {
+    local_ipv4_addresses()
+        .into_iter()
+        .map(IpAddr::V4)
+        .chain(local_ipv6_addresses().into_iter().map(IpAddr::V6))
+        .collect()
+}
+
+/// Unique A or AAAA record of the service host for `ip`
+fn address_record(service: &Service, ip: IpAddr) -> ResourceRecord {
+    let name = Name::new(service.host.clone() + ".local").expect("Should be valid");
+
+    let mut record = match ip {
+        IpAddr::V4(ip) => ResourceRecord::create_a_record_from_ipv4(name, ip),
+        IpAddr::V6(ip) => ResourceRecord::create_aaaa_record_from_ipv6(name, ip),
+    };
+    record.cache_flush = true;
+    record
+}
+
+#[test]
+fn test_network_change() {
+    use std::{
+        net::Ipv4Addr,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use crate::service::ServiceBuilder;
+
+    static CHANGED: AtomicBool = AtomicBool::new(false);
+
+    let handler = NetworkChangeHandler::default().with_address_source(|| {
+        match CHANGED.load(Ordering::Relaxed) {
+            false => vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))],
+            true => vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5))],
+        }
+    });
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+
+    let mut run = |event: Event| {
+        let mut timeouts = vec![];
+        let mut queue = vec![];
+
+        handler
+            .handle(
+                &event,
+                &mut vec![],
+                &mut Some(&mut service),
+                &mut None,
+                &mut timeouts,
+                &mut queue,
+            )
+            .unwrap();
+
+        (timeouts, queue)
+    };
+
+    //Addresses are remembered, an unchanged network sends nothing
+    assert!(run(Event::Ttl()).1.is_empty());
+    assert!(run(Event::NetworkChange()).1.is_empty());
+
+    CHANGED.store(true, Ordering::Relaxed);
+
+    let (timeouts, queue) = run(Event::NetworkChange());
+
+    let ip = |record: &ResourceRecord| record.rdata.as_ref().unwrap().to_bytes();
+
+    //Goodbye for the old address
+    assert_eq!(queue.len(), 2);
+    let goodbye = &queue[0].0.answers[0];
+    assert_eq!(goodbye.ttl, 0);
+    assert_eq!(ip(goodbye), [192, 168, 1, 2]);
+
+    //Announcement with the new address
+    let a_records: Vec<&ResourceRecord> = queue[1]
+        .0
+        .additionals
+        .iter()
+        .filter(|r| r.record_type == QType::A)
+        .collect();
+    assert_eq!(a_records.len(), 1);
+    assert_eq!(ip(a_records[0]), [10, 0, 0, 5]);
+    assert!(queue[1].0.header.qr);
+
+    assert_eq!(
+        timeouts,
+        vec![(ServiceState::WaitForSecondAnnouncement, 1000)]
+    );
+    assert_eq!(service.state, ServiceState::WaitForSecondAnnouncement);
+}
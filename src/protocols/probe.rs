@@ -1,8 +1,14 @@
-use super::handler::{Event, Handler};
+use super::{
+    handler::{Event, Handler},
+    probe_conflict::tiebreak_authority_lists,
+};
 use crate::{
-    message::MdnsMessage, record::ResourceRecord, service::ServiceState, MdnsError, Query, Service,
+    cache::same_record, message::MdnsMessage, question::QType, record::ResourceRecord,
+    registration::Registration, service::ServiceState, utility::SendTarget, MdnsError, Query,
+    Service,
 };
 use rand::{thread_rng, Rng};
+use std::cmp::Ordering;
 
 /// Probe MDNS Service
 ///
@@ -22,79 +28,170 @@ use rand::{thread_rng, Rng};
 /// - Wait for 250ms or get a response -> Return Conflict Error
 /// - Return Ok -> Service has not been registrered
 ///
+/// ## Conflicts
+/// - A response with other data for one of our unique records while probing -> Return Conflict Error
+/// - A simultaneous probe for our names which wins the tiebreak -> Wait 1s and probe again
+/// - Both are counted in [`Service::probe_conflicts`]
+/// - [RFC6762 Section 9 - Conflict Resolution](https://www.rfc-editor.org/rfc/rfc6762#section-9)
+///
 #[derive(Default, Copy, Clone)]
-pub struct ProbeHandler<'a> {
-    next: Option<&'a dyn Handler<'a>>,
-}
+pub struct ProbeHandler {}
+
+impl ProbeHandler {
+    /// Whether `service` is probing and incoming messages may conflict with it
+    fn is_probing(service: &Service) -> bool {
+        matches!(
+            service.state,
+            ServiceState::WaitForFirstProbe
+                | ServiceState::FirstProbe
+                | ServiceState::WaitForSecondProbe
+                | ServiceState::SecondProbe
+                | ServiceState::WaitForAnnouncing
+        )
+    }
+
+    /// The unique records we probe for, shared PTR records cannot conflict
+    fn unique_records(service: &Service) -> Vec<ResourceRecord> {
+        MdnsMessage::probe(service)
+            .authorities
+            .into_iter()
+            .filter(|r| r.record_type != QType::Ptr)
+            .collect()
+    }
+
+    /// Whether the response holds a record with the name and type of one of ours but other data
+    ///
+    /// Identical records are not a conflict, e.g. our own records sent by another registration of this host
+    fn is_conflicting_response(message: &MdnsMessage, ours: &[ResourceRecord]) -> bool {
+        message
+            .answers
+            .iter()
+            .chain(&message.additionals)
+            .filter(|r| {
+                r.ttl > 0
+                    && ours
+                        .iter()
+                        .any(|o| o.name == r.name && o.record_type == r.record_type)
+            })
+            .any(|r| !ours.iter().any(|o| same_record(o, r)))
+    }
+
+    /// Whether a simultaneous probe for our names wins the tiebreak
+    ///
+    /// Only the records with the names in both probes are compared, our own probe is equal and does not win
+    fn loses_tiebreak(message: &MdnsMessage, ours: &[ResourceRecord]) -> bool {
+        let theirs: Vec<_> = message
+            .authorities
+            .iter()
+            .filter(|r| ours.iter().any(|o| o.name == r.name))
+            .cloned()
+            .collect();
+
+        if theirs.is_empty() {
+            return false;
+        }
+
+        let ours: Vec<_> = ours
+            .iter()
+            .filter(|o| theirs.iter().any(|r| o.name == r.name))
+            .cloned()
+            .collect();
 
-impl<'a> Handler<'a> for ProbeHandler<'a> {
-    fn set_next(&mut self, next: &'a dyn Handler<'a>) -> &mut dyn Handler<'a> {
-        self.next = Some(next);
-        self
+        tiebreak_authority_lists(&ours, &theirs) == Ordering::Less
     }
+}
+
+impl Handler for ProbeHandler {
     fn handle(
         &self,
         event: &Event,
-        records: &mut Vec<ResourceRecord>,
+        _records: &mut Vec<ResourceRecord>,
         registration: &mut Option<&mut Service>,
-        query: &mut Option<Query>,
+        _query: &mut Option<Query>,
         timeouts: &mut Vec<(ServiceState, u64)>,
-        queue: &mut Vec<MdnsMessage>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
     ) -> Result<(), MdnsError> {
         if let Some(r) = registration {
-            //TIMEOUTS
-            match event {
-                Event::TimeElapsed((s, _t)) => {
-                    //States must match with registered timeouts
-                    if *s == r.state {
-                        match s {
-                            ServiceState::WaitForFirstProbe => r.state = ServiceState::FirstProbe,
-                            ServiceState::WaitForSecondProbe => r.state = ServiceState::SecondProbe,
-                            ServiceState::WaitForAnnouncing => {
-                                r.state = ServiceState::FirstAnnouncement
-                            }
-
-                            _ => {}
-                        }
+            //CONFLICTS
+            let mut lost_tiebreak = false;
+
+            if let Event::Message(message) = event {
+                if Self::is_probing(r) {
+                    let ours = Self::unique_records(r);
+
+                    if message.is_response() && Self::is_conflicting_response(message, &ours) {
+                        warn!("{} is already taken", r.to_dns_sd_string());
+                        r.probe_conflicts += 1;
+                        return Err(MdnsError::NameAlreadyTaken {});
                     }
-                }
-                _ => {}
-            }
 
-            //STATE MANAGEMENT
-            match r.state {
-                ServiceState::Prelude => {
-                    debug!(
-                        "Adding Timeout for Probing {}.{}.{}.local",
-                        r.host, r.service, r.protocol
-                    );
-                    r.state = ServiceState::WaitForFirstProbe;
-                    timeouts.push((r.state, thread_rng().gen_range(0..250)));
-                }
-                ServiceState::FirstProbe => {
-                    debug!(
-                        "Sending Probe Query for {}.{}.{}.local",
-                        r.host, r.service, r.protocol
-                    );
-                    queue.push(MdnsMessage::probe(&r));
-                    r.state = ServiceState::WaitForSecondProbe;
-                    timeouts.push((r.state, 250));
-                }
-                ServiceState::SecondProbe => {
-                    debug!(
-                        "Sending second Probe Query for {}.{}.{}.local",
-                        r.host, r.service, r.protocol
-                    );
-                    queue.push(MdnsMessage::probe(&r));
-                    r.state = ServiceState::WaitForAnnouncing;
-                    timeouts.push((r.state, 250));
+                    if message.is_query() && Self::loses_tiebreak(message, &ours) {
+                        debug!(
+                            "Lost probe tiebreak for {}, probing again in 1s",
+                            r.to_dns_sd_string()
+                        );
+                        r.probe_conflicts += 1;
+                        lost_tiebreak = true;
+                    }
                 }
-                _ => {}
             }
-        }
 
-        if let Some(v) = &self.next {
-            v.handle(event, records, registration, query, timeouts, queue)?;
+            Registration::update(r, |registration| {
+                let registration = if lost_tiebreak {
+                    registration.restart_probing(1000)
+                } else {
+                    registration
+                };
+
+                //TIMEOUTS
+                //States must match with registered timeouts
+                let elapsed = |state| matches!(event, Event::TimeElapsed((s, _t)) if *s == state);
+
+                let registration = match registration {
+                    Registration::WaitForFirstProbe(p) if elapsed(p.state()) => {
+                        Registration::FirstProbe(p.elapsed())
+                    }
+                    Registration::WaitForSecondProbe(p) if elapsed(p.state()) => {
+                        Registration::SecondProbe(p.elapsed())
+                    }
+                    Registration::WaitForAnnouncing(p) if elapsed(p.state()) => {
+                        Registration::FirstAnnouncement(p.elapsed())
+                    }
+                    registration => registration,
+                };
+
+                //STATE MANAGEMENT
+                let registration = match registration {
+                    Registration::Prelude(p) => {
+                        debug!(
+                            "Adding Timeout for Probing {}",
+                            p.service().to_dns_sd_string()
+                        );
+                        Registration::WaitForFirstProbe(
+                            p.start_probing(thread_rng().gen_range(0..250)),
+                        )
+                    }
+                    Registration::FirstProbe(p) => {
+                        debug!("Sending Probe Query for {}", p.service().to_dns_sd_string());
+                        let (p, probe) = p.probe(250);
+                        queue.push((probe, SendTarget::Multicast));
+                        Registration::WaitForSecondProbe(p)
+                    }
+                    Registration::SecondProbe(p) => {
+                        debug!(
+                            "Sending second Probe Query for {}",
+                            p.service().to_dns_sd_string()
+                        );
+                        let (p, probe) = p.probe(250);
+                        queue.push((probe, SendTarget::Multicast));
+                        Registration::WaitForAnnouncing(p)
+                    }
+                    registration => registration,
+                };
+
+                timeouts.extend(registration.pending_timeout());
+                registration
+            });
         }
 
         Ok(())
@@ -103,16 +200,17 @@ impl<'a> Handler<'a> for ProbeHandler<'a> {
 
 #[test]
 fn test_probe_handler() {
+    use crate::service::ServiceBuilder;
+
     //Mock Service
     //Result if Registration Handler worked properly
-    let mut service = Service {
-        host: "TestMachine".into(),
-        service: "_test".into(),
-        protocol: "_tcp".into(),
-        port: 53000,
-        txt_records: vec![],
-        state: ServiceState::Prelude,
-    };
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
 
     let handler = ProbeHandler::default();
 
@@ -193,3 +291,61 @@ fn test_probe_handler() {
     assert_eq!(service.state, ServiceState::FirstAnnouncement);
     timeouts.clear();
 }
+
+#[test]
+fn test_probe_conflicts() {
+    use crate::{name::Name, service::ServiceBuilder};
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::WaitForSecondProbe;
+
+    let handler = ProbeHandler::default();
+    let mut timeouts = vec![];
+
+    let mut handle = |message: MdnsMessage, service: &mut Service| {
+        handler.handle(
+            &Event::Message(message),
+            &mut vec![],
+            &mut Some(service),
+            &mut None,
+            &mut timeouts,
+            &mut vec![],
+        )
+    };
+
+    //Our own probe and records are no conflict
+    handle(MdnsMessage::probe(&service), &mut service).unwrap();
+    handle(MdnsMessage::announce(&service), &mut service).unwrap();
+    assert_eq!(service.probe_conflicts, 0);
+
+    //A simultaneous probe with more records wins the tiebreak, probe again after 1s
+    let mut probe = MdnsMessage::probe(&service);
+    probe.add_authority(ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [255, 255, 255, 255],
+    ));
+    handle(probe, &mut service).unwrap();
+    assert_eq!(service.probe_conflicts, 1);
+    assert_eq!(service.state, ServiceState::WaitForFirstProbe);
+
+    //A response with other data for our instance name is a conflict
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.add_answer(ResourceRecord::create_srv_record(
+        "TestMachine._test._tcp.local".into(),
+        8080,
+        "OtherMachine.local".into(),
+    ));
+    assert!(matches!(
+        handle(response, &mut service),
+        Err(MdnsError::NameAlreadyTaken {})
+    ));
+    assert_eq!(service.probe_conflicts, 2);
+    assert_eq!(timeouts, vec![(ServiceState::WaitForFirstProbe, 1000)]);
+}
@@ -1,3 +1,7 @@
+use std::cmp::Ordering;
+
+use crate::{record::ResourceRecord, utility::lexicographic_later};
+
 /// Probe Tiebreak
 ///
 /// Resolve conflict in case of probe response by others
@@ -12,4 +16,53 @@ pub async fn probe_tiebreak() -> (){
     //TODO
 
   
-}
\ No newline at end of file
+}
+
+/// Compare the authority records of our probe with the authority records of a simultaneous probe
+///
+/// [RFC6762 Section 8.2 - Simultaneous Probe Tiebreak](https://www.rfc-editor.org/rfc/rfc6762#section-8.2)
+/// - The list with more records wins
+/// - With an equal number of records, both lists are sorted and compared record by record, see [`lexicographic_later()`]
+/// - `Greater` means our probe wins, `Less` means we should wait 1s and probe again
+/// - `Equal` means both lists are identical, e.g. our own probe, and probing continues
+pub fn tiebreak_authority_lists(ours: &[ResourceRecord], theirs: &[ResourceRecord]) -> Ordering {
+    let sorted = |records: &[ResourceRecord]| {
+        let mut records = records.to_vec();
+        records.sort_by(lexicographic_later);
+        records
+    };
+
+    ours.len().cmp(&theirs.len()).then_with(|| {
+        sorted(ours)
+            .iter()
+            .zip(sorted(theirs).iter())
+            .map(|(ours, theirs)| lexicographic_later(ours, theirs))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+#[test]
+fn test_tiebreak_authority_lists() {
+    use crate::name::Name;
+
+    let a_record =
+        |ip| ResourceRecord::create_a_record(Name::new("TestMachine.local".into()).unwrap(), ip);
+
+    let ours = [a_record([169, 254, 99, 200])];
+    let theirs = [a_record([169, 254, 200, 50])];
+
+    //Equal count falls back to the byte comparison of the RDATA
+    assert_eq!(tiebreak_authority_lists(&ours, &theirs), Ordering::Less);
+    assert_eq!(tiebreak_authority_lists(&theirs, &ours), Ordering::Greater);
+    assert_eq!(tiebreak_authority_lists(&ours, &ours), Ordering::Equal);
+
+    //More records wins regardless of content
+    let more = [a_record([1, 1, 1, 1]), a_record([1, 1, 1, 2])];
+    assert_eq!(tiebreak_authority_lists(&more, &theirs), Ordering::Greater);
+    assert_eq!(tiebreak_authority_lists(&theirs, &more), Ordering::Less);
+
+    //The records are compared in sorted order
+    let sorted = [a_record([1, 1, 1, 2]), a_record([1, 1, 1, 1])];
+    assert_eq!(tiebreak_authority_lists(&more, &sorted), Ordering::Equal);
+}
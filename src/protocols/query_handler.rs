@@ -0,0 +1,344 @@
+use crate::{
+    message::MdnsMessage,
+    question::QType,
+    record::ResourceRecord,
+    service::ServiceState,
+    utility::SendTarget,
+    MdnsError, Query, Service,
+};
+use rand::{thread_rng, Rng};
+
+use super::handler::{Event, Handler};
+
+/// Respond to Queries
+///
+/// Answers queries from other hosts for the records of our registered [`Service`]
+///
+/// This step is only available once the service is `ServiceState::Registered`
+///
+/// ## RFC Reference
+/// - [RFC6762 Section 6 - Responding](https://www.rfc-editor.org/rfc/rfc6762#section-6)
+/// - [RFC6762 Section 7.1 - Known-Answer Suppression](https://www.rfc-editor.org/rfc/rfc6762#section-7.1)
+///
+/// ## Protocol
+/// - Receive a query with a question for our service type (PTR), instance name (SRV, TXT) or host name (A, AAAA)
+/// - Skip the answer if the querier already included it as a known answer with at least half of our TTL
+/// - PTR records are shared, delay the response for 20-120ms so multiple responders do not collide
+/// - When the delay has passed, only the registrations whose service type was queried respond
+/// - SRV, TXT, A and AAAA records are unique to us, respond immediately
+/// - Static records in the cache are answered immediately as well, see [`ResourceRecord::is_static()`]
+#[derive(Default, Copy, Clone)]
+pub struct QueryHandler {}
+
+impl Handler for QueryHandler {
+    fn handle(
+        &self,
+        event: &Event,
+        records: &mut Vec<ResourceRecord>,
+        registration: &mut Option<&mut Service>,
+        _query: &mut Option<Query>,
+        timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        //Static records are answered with or without registrations
+        if let Event::Message(message) = event {
+            if message.is_query() {
+                if let Some(response) = static_response(message, records) {
+                    debug!("Responding to query with static records");
+                    queue.push((response, SendTarget::Multicast));
+                }
+            }
+        }
+
+        if let Some(r) = registration {
+            match event {
+                //Incoming query, only answer for records we own
+                Event::Message(message) if message.is_query() => {
+                    if r.state == ServiceState::Registered {
+                        let service_type = r.service.clone() + "." + &r.protocol + ".local";
+                        let instance = r.host.clone() + "." + &service_type;
+                        let host = r.host.clone() + ".local";
+
+                        let questions = message
+                            .questions_for_name(&service_type)
+                            .chain(message.questions_for_name(&instance))
+                            .chain(message.questions_for_name(&host));
+
+                        for question in questions {
+                            let name = question.name.as_str();
+                            let response = MdnsMessage::response(r, question.qtype);
+
+                            //Only questions for the records we own, e.g. not a PTR question for the host
+                            if !response.answers_question(question) {
+                                continue;
+                            }
+
+                            if response
+                                .answers
+                                .iter()
+                                .all(|answer| is_known_answer(message, answer))
+                            {
+                                debug!("Suppressing known answer for {}", name);
+                                continue;
+                            }
+
+                            if question.qtype == QType::Ptr {
+                                debug!("Delaying response for {}", name);
+                                r.queried_type = Some(service_type.clone());
+                                timeouts.push((
+                                    ServiceState::WaitForQueryResponse,
+                                    thread_rng().gen_range(20..=120),
+                                ));
+                            } else {
+                                debug!("Responding to query for {}", name);
+                                queue.push((response, SendTarget::Multicast));
+                            }
+                        }
+                    }
+                }
+                //Delay for our shared records has passed
+                //Only the registrations which were queried respond
+                Event::TimeElapsed((ServiceState::WaitForQueryResponse, _t)) => {
+                    if let Some(service_type) = r.queried_type.take() {
+                        debug!("Responding to query for {}", service_type);
+                        queue.push((MdnsMessage::response(r, QType::Ptr), SendTarget::Multicast));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Response with the static records which answer a question of `message`, see [`ResourceRecord::is_static()`]
+///
+/// Returns `None` if there are none or the querier knows all of them
+fn static_response(message: &MdnsMessage, records: &[ResourceRecord]) -> Option<MdnsMessage> {
+    let mut response = MdnsMessage::default();
+    response.header.qr = true;
+    response.header.aa = true;
+
+    for record in records
+        .iter()
+        .filter(|rec| rec.is_static() && !is_known_answer(message, rec))
+    {
+        let answer = MdnsMessage::default().with_answer(record.clone());
+
+        if message.questions.iter().any(|q| answer.answers_question(q)) {
+            response.add_answer(record.clone());
+        }
+    }
+
+    response.has_answers().then_some(response)
+}
+
+/// Determine whether the querier already knows our answer
+///
+/// The querier includes records it already has in the answer section of its query
+/// We should not answer with a record if its TTL in the known answer is at least half our TTL
+pub(crate) fn is_known_answer(message: &MdnsMessage, answer: &ResourceRecord) -> bool {
+    message.answers.iter().any(|known| {
+        known.record_type == answer.record_type
+            && known.name.as_str().eq_ignore_ascii_case(answer.name.as_str())
+            && known.rdata.as_ref().map(|d| d.to_bytes())
+                == answer.rdata.as_ref().map(|d| d.to_bytes())
+            && known.ttl >= answer.ttl / 2
+    })
+}
+
+#[test]
+fn test_query_handler() {
+    use crate::{
+        name::Name,
+        question::{QClass, Question},
+        service::ServiceBuilder,
+    };
+
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.state = ServiceState::Registered;
+
+    let handler = QueryHandler::default();
+
+    let ptr_query = || {
+        let mut message = MdnsMessage::default();
+        message.questions.push(Question {
+            name: Name::new("_test._tcp.local".into()).unwrap(),
+            qtype: QType::Ptr,
+            qclass: QClass::In,
+            unicast_question: false,
+        });
+        message.header.qdcount = 1;
+        message
+    };
+
+    //Step 1: PTR query should delay a response
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    handler
+        .handle(
+            &Event::Message(ptr_query()),
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+    assert_eq!(timeouts.len(), 1);
+    assert_eq!(timeouts[0].0, ServiceState::WaitForQueryResponse);
+    assert!((20..=120).contains(&timeouts[0].1));
+    assert!(queue.is_empty());
+
+    timeouts.clear();
+
+    //Step 2: Delay finished, PTR response should be queued
+    handler
+        .handle(
+            &Event::TimeElapsed((ServiceState::WaitForQueryResponse, 120)),
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+    assert_eq!(queue.len(), 1);
+    assert!(queue[0].0.header.qr);
+    assert_eq!(queue[0].0.answers[0].record_type, QType::Ptr);
+    assert_eq!(queue[0].0.header.ancount, 1);
+
+    queue.clear();
+
+    //Step 3: Querier already knows our PTR record, no response
+    let mut known = ptr_query();
+    known
+        .answers
+        .push(ResourceRecord::create_ptr_record("TestMachine".into(), "_test".into(), "_tcp".into()));
+    known.header.ancount = 1;
+
+    handler
+        .handle(
+            &Event::Message(known),
+            &mut vec![],
+            &mut Some(&mut service),
+            &mut None,
+            &mut timeouts,
+            &mut queue,
+        )
+        .unwrap();
+
+    assert!(timeouts.is_empty());
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_static_records() {
+    use crate::{name::Name, record::STATIC_TTL};
+
+    let handler = QueryHandler::default();
+
+    let mut printer = ResourceRecord::create_a_record(
+        Name::new("printer.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    printer.ttl = STATIC_TTL;
+    let cached =
+        ResourceRecord::create_a_record(Name::new("other.local".into()).unwrap(), [192, 168, 1, 3]);
+    let mut records = vec![printer.clone(), cached];
+
+    let mut run = |message: MdnsMessage| {
+        let mut queue = vec![];
+        handler
+            .handle(
+                &Event::Message(message),
+                &mut records,
+                &mut None,
+                &mut None,
+                &mut vec![],
+                &mut queue,
+            )
+            .unwrap();
+        queue
+    };
+
+    //Static records are answered without a registration
+    let queue = run(MdnsMessage::query_type("Printer.local", QType::A));
+    assert_eq!(queue.len(), 1);
+    assert!(queue[0].0.is_response());
+    assert_eq!(queue[0].0.answers.len(), 1);
+    assert_eq!(queue[0].0.answers[0].name.as_str(), "printer.local");
+
+    //Records cached from other hosts are not
+    assert!(run(MdnsMessage::query_type("other.local", QType::A)).is_empty());
+    assert!(run(MdnsMessage::query_type("printer.local", QType::Aaaa)).is_empty());
+
+    //Known answers are suppressed
+    let known =
+        MdnsMessage::query_type("printer.local", QType::A).with_known_answers(vec![printer]);
+    assert!(run(known).is_empty());
+}
+
+#[tokio::test]
+async fn test_query_multiple_registrations() {
+    use crate::{service::ServiceBuilder, DnsSd2};
+
+    let mut client = DnsSd2::default().send_goodbye_on_drop(false);
+
+    for service in ["_http", "_ssh"] {
+        let mut service = ServiceBuilder::default()
+            .host("TestMachine")
+            .service(service)
+            .protocol("_tcp")
+            .port(53000)
+            .build()
+            .unwrap();
+        service.state = ServiceState::Registered;
+        client.registrations.push(service);
+    }
+
+    let handler = QueryHandler::default();
+    let mut timeouts = vec![];
+    let mut queue = vec![];
+
+    //Both registrations share the host, only the queried service type is delayed
+    client
+        .handle(
+            &handler,
+            &Event::Message(MdnsMessage::query("_http._tcp.local")),
+            &mut timeouts,
+            &mut queue,
+        )
+        .await
+        .unwrap();
+    assert_eq!(timeouts.len(), 1);
+
+    //The timeout passes through the chain of every registration
+    client
+        .handle(
+            &handler,
+            &Event::TimeElapsed(timeouts[0]),
+            &mut vec![],
+            &mut queue,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue[0].0.answers[0].record_type, QType::Ptr);
+    assert_eq!(queue[0].0.answers[0].name.as_str(), "_http._tcp.local");
+    assert!(client
+        .registrations
+        .iter()
+        .all(|r| r.queried_type.is_none()));
+}
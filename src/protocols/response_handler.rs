@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+use tokio::time::Instant;
+
+use crate::{
+    cache::same_record,
+    message::MdnsMessage,
+    name::Name,
+    question::QType,
+    record::{parse_rdata_name, ResourceRecord},
+    records::TXTRecord,
+    service::ServiceState,
+    utility::SendTarget,
+    MdnsError, Query, Service,
+};
+
+use super::handler::{Event, Handler, RecordCallback};
+
+/// Handle Responses
+///
+/// Caches the records of incoming responses and resolves the services of an active [`Query`]
+///
+/// ## RFC Reference
+/// - [RFC6762 Section 10.1 - Goodbye Packets](https://www.rfc-editor.org/rfc/rfc6762#section-10.1)
+/// - [RFC6763 Section 4 - Service Instance Enumeration (Browsing)](https://www.rfc-editor.org/rfc/rfc6763#section-4)
+///
+/// ## Protocol
+/// - Receive a response, cache its answers and additionals, replacing cached records with the same data
+/// - Goodbye records with a TTL of 0 are cached with a TTL of 1, so they expire after 1 second
+/// - For each cached PTR record of the queried service type, look up the SRV and TXT records of the instance
+///   and the A and AAAA records of the SRV target
+/// - The SRV target and its addresses are kept on the service, see [`Service::target`] and [`Service::addresses`]
+/// - With all four records cached, the service is resolved and added to the query, see [`Query::add_service()`]
+/// - Resolved services pass through the chain as [`Event::Discovered`] before they are yielded
+/// - Services are not resolved from goodbye records, see [`removed_instances()`]
+/// - Call the added callbacks for each record which was not cached yet
+#[derive(Default, Copy, Clone)]
+pub struct ResponseHandler<'a> {
+    added_callbacks: &'a [RecordCallback],
+}
+
+impl<'a> ResponseHandler<'a> {
+    /// Call `callbacks` for each record added to the cache
+    pub fn with_added_callbacks(mut self, callbacks: &'a [RecordCallback]) -> Self {
+        self.added_callbacks = callbacks;
+        self
+    }
+}
+
+impl<'a> Handler for ResponseHandler<'a> {
+    fn handle(
+        &self,
+        event: &Event,
+        records: &mut Vec<ResourceRecord>,
+        _registration: &mut Option<&mut Service>,
+        query: &mut Option<Query>,
+        _timeouts: &mut Vec<(ServiceState, u64)>,
+        _queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        match event {
+            Event::Message(message) if message.is_response() => {
+                let now = Instant::now();
+
+                for record in message.answers.iter().chain(message.additionals.iter()) {
+                    let mut record = record.clone();
+                    record.received_at = Some(now);
+
+                    if record.is_goodbye() {
+                        record.ttl = 1;
+                    }
+
+                    match records.iter_mut().find(|r| same_record(r, &record)) {
+                        Some(cached) => *cached = record,
+                        None => {
+                            self.added_callbacks.iter().for_each(|f| f(record.clone()));
+                            records.push(record);
+                        }
+                    }
+                }
+
+                if let Some(q) = query {
+                    for service in resolve(records, &q.name) {
+                        if q.add_service(service.clone()) {
+                            debug!("Resolved {}", service.to_dns_sd_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Instance names of the resolved services of `query` with a goodbye PTR record in `message`
+///
+/// [RFC6762 Section 10.1 - Goodbye Packets](https://www.rfc-editor.org/rfc/rfc6762#section-10.1)
+pub fn removed_instances(message: &MdnsMessage, query: &Query) -> Vec<String> {
+    message
+        .answers
+        .iter()
+        .filter(|r| {
+            r.is_goodbye()
+                && r.record_type == QType::Ptr
+                && r.name.as_str().eq_ignore_ascii_case(&query.name)
+        })
+        .filter_map(|ptr| rdata_name(ptr, 0))
+        .map(|instance| instance.as_str().to_string())
+        .filter(|instance| {
+            query
+                .resolved
+                .iter()
+                .any(|r| r.eq_ignore_ascii_case(instance))
+        })
+        .collect()
+}
+
+/// TXT records in `message` which differ from the last known TXT records of their instance
+///
+/// `known` is updated with every TXT record, the first TXT record of an instance is not a change
+pub fn txt_changes(
+    message: &MdnsMessage,
+    known: &mut HashMap<String, Vec<String>>,
+) -> Vec<(String, Vec<String>)> {
+    message
+        .answers
+        .iter()
+        .filter(|r| r.record_type == QType::Txt && !r.is_goodbye())
+        .filter_map(|txt| {
+            let instance = txt.name.as_str().to_ascii_lowercase();
+            let txt_records = TXTRecord::from_rdata(&rdata_bytes(txt)).ok()?.txt_record;
+
+            match known.insert(instance, txt_records.clone()) {
+                Some(previous) if previous != txt_records => {
+                    Some((txt.name.as_str().to_string(), txt_records))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Services of the given type with their PTR, SRV, TXT and A records cached
+///
+/// Goodbye records are cached with a TTL of 1 until they expire, but keep their original TTL of 0
+pub(crate) fn resolve(records: &[ResourceRecord], service_type: &str) -> Vec<Service> {
+    let cached = |name: &Name, qtype: QType| {
+        let name = name.as_str().to_string();
+
+        records.iter().filter(move |r| {
+            r.ttl > 0
+                && r.original_ttl > 0
+                && r.record_type == qtype
+                && r.name.as_str().eq_ignore_ascii_case(&name)
+        })
+    };
+    let find = |name: &Name, qtype: QType| cached(name, qtype).next();
+
+    records
+        .iter()
+        .filter(|r| {
+            r.ttl > 0
+                && r.original_ttl > 0
+                && r.record_type == QType::Ptr
+                && r.name.as_str().eq_ignore_ascii_case(service_type)
+        })
+        .filter_map(|ptr| {
+            let instance = rdata_name(ptr, 0)?;
+
+            let srv = find(&instance, QType::Srv)?;
+            let txt = find(&instance, QType::Txt)?;
+
+            let srv_bytes = rdata_bytes(srv);
+            let port = u16::from_be_bytes([*srv_bytes.get(4)?, *srv_bytes.get(5)?]);
+            let target = rdata_name(srv, 6)?;
+
+            //The addresses of the SRV target, which can differ from the host of the instance name
+            let ip_v4 = cached(&target, QType::A)
+                .filter_map(|a| <[u8; 4]>::try_from(rdata_bytes(a)).ok())
+                .map(|ip| IpAddr::V4(Ipv4Addr::from(ip)));
+            let ip_v6 = cached(&target, QType::Aaaa)
+                .filter_map(|aaaa| <[u8; 16]>::try_from(rdata_bytes(aaaa)).ok())
+                .map(|ip| IpAddr::V6(Ipv6Addr::from(ip)));
+            let addresses: Vec<IpAddr> = ip_v4.chain(ip_v6).collect();
+
+            if !addresses.iter().any(IpAddr::is_ipv4) {
+                return None;
+            }
+
+            let (host, service, protocol) = Service::parse_dns_sd(instance.as_str()).ok()?;
+
+            Some(Service {
+                host,
+                service,
+                protocol,
+                port,
+                target: Some(target.as_str().to_string()),
+                addresses,
+                txt_records: TXTRecord::from_rdata(&rdata_bytes(txt)).ok()?.txt_record,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Packed RDATA of a record, empty without RDATA
+fn rdata_bytes(record: &ResourceRecord) -> Vec<u8> {
+    record.rdata_bytes().unwrap_or_default()
+}
+
+/// Name in the RDATA of a PTR or SRV record, starting at `offset`
+fn rdata_name(record: &ResourceRecord, offset: usize) -> Option<Name> {
+    let bytes = rdata_bytes(record);
+
+    parse_rdata_name(&bytes, &bytes, offset)
+        .ok()
+        .map(|(name, _)| name)
+}
+
+#[test]
+fn test_response_handler() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .txt("version", "2")
+        .build()
+        .unwrap();
+
+    //The SRV target differs from the host of the instance name
+    let instance = Name::new(service.to_dns_sd_string()).unwrap();
+    let host = Name::new("printer.local".into()).unwrap();
+
+    let responses = [
+        ResourceRecord::create_ptr_record(
+            service.host.clone(),
+            service.service.clone(),
+            service.protocol.clone(),
+        ),
+        ResourceRecord::create_srv_record(instance.as_str().into(), 53000, host.as_str().into()),
+        ResourceRecord::create_a_record(host, [192, 168, 1, 2]),
+        ResourceRecord::create_txt_record(instance, service.txt_records.clone()),
+    ];
+
+    let handler = ResponseHandler::default();
+
+    let mut records = vec![];
+    let mut query = Some(Query {
+        name: service.service_type(),
+        ..Default::default()
+    });
+
+    for (i, record) in responses.into_iter().enumerate() {
+        //Unresolved until all four records arrived
+        assert!(query.as_ref().unwrap().services.is_empty());
+
+        let mut message = MdnsMessage::default();
+        message.header.qr = true;
+        message.answers.push(record);
+        message.header.ancount = 1;
+
+        //Receive each response twice, cached records are replaced
+        for _ in 0..2 {
+            handler
+                .handle(
+                    &Event::Message(message.clone()),
+                    &mut records,
+                    &mut None,
+                    &mut query,
+                    &mut vec![],
+                    &mut vec![],
+                )
+                .unwrap();
+        }
+
+        assert_eq!(records.len(), i + 1);
+    }
+
+    let query = query.unwrap();
+    assert_eq!(query.services.len(), 1);
+    assert_eq!(query.found, 1);
+
+    let resolved = &query.services[0];
+    assert_eq!(resolved.to_dns_sd_string(), service.to_dns_sd_string());
+    assert_eq!(resolved.port, 53000);
+    assert_eq!(resolved.txt_records, vec!["version=2".to_string()]);
+    assert_eq!(resolved.target.as_deref(), Some("printer.local"));
+    assert_eq!(
+        resolved.addresses,
+        [IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))]
+    );
+    assert!(records.iter().all(|r| r.received_at.is_some()));
+}
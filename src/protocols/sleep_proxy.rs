@@ -0,0 +1,190 @@
+#[cfg(feature = "sleep-proxy")]
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use crate::{
+    message::MdnsMessage, record::ResourceRecord, service::ServiceState, utility::SendTarget,
+    MdnsError, Query, Service,
+};
+
+use super::handler::{Event, Handler};
+
+/// TXT record key of services which want their records proxied while sleeping
+pub const SLEEP_PROXY_KEY: &str = "sleep-proxy";
+
+/// Sleep Proxy
+///
+/// Keeps the records of a sleeping host alive on the network
+///
+/// [RFC6762 Section 14 - Behaviour of Sleep Proxies](https://www.rfc-editor.org/rfc/rfc6762#section-14)
+///
+/// Only a stub, the proxy logic is behind the `sleep-proxy` feature.
+/// Without the feature events are ignored
+///
+/// - A [`Service`] with a TXT record key `sleep-proxy` is proxy registered once it is `ServiceState::Registered`
+/// - On [`Event::SleepModeChanged`] the handler starts or stops proxying
+/// - While sleeping, the records are announced again on [`Event::Ttl()`] before their TTL expires
+#[derive(Default)]
+pub struct SleepProxyHandler {
+    #[cfg(feature = "sleep-proxy")]
+    sleeping: AtomicBool,
+    /// Seconds since the records were last announced for each service instance
+    #[cfg(feature = "sleep-proxy")]
+    elapsed: Mutex<HashMap<String, u32>>,
+}
+
+impl SleepProxyHandler {
+    /// Whether the records of `service` are announced by the proxy while sleeping
+    pub fn is_proxy_registered(service: &Service) -> bool {
+        service.state == ServiceState::Registered
+            && service.txt_records.iter().any(|txt| {
+                let key = txt.split_once('=').map_or(txt.as_str(), |(key, _)| key);
+                key.eq_ignore_ascii_case(SLEEP_PROXY_KEY)
+            })
+    }
+
+    /// Whether the handler is currently proxying, always false without the `sleep-proxy` feature
+    pub fn is_sleeping(&self) -> bool {
+        #[cfg(feature = "sleep-proxy")]
+        return self.sleeping.load(Ordering::Relaxed);
+
+        #[cfg(not(feature = "sleep-proxy"))]
+        false
+    }
+
+    /// Announce the records of a proxy registered `service` when 80% of the shortest TTL has passed
+    #[cfg(feature = "sleep-proxy")]
+    fn proxy(&self, service: &Service, queue: &mut Vec<(MdnsMessage, SendTarget)>) {
+        let announcement = MdnsMessage::announce(service);
+
+        let shortest_ttl = announcement
+            .answers
+            .iter()
+            .chain(&announcement.additionals)
+            .map(|r| r.ttl)
+            .min()
+            .unwrap_or_default();
+
+        let instance = service.to_dns_sd_string();
+        let mut elapsed = self.elapsed.lock().unwrap_or_else(PoisonError::into_inner);
+        let seconds = elapsed.entry(instance.clone()).or_default();
+        *seconds += 1;
+
+        if *seconds * 5 >= shortest_ttl * 4 {
+            debug!("Proxying {}", instance);
+            queue.push((announcement, SendTarget::Multicast));
+            *seconds = 0;
+        }
+    }
+}
+
+impl Handler for SleepProxyHandler {
+    #[cfg_attr(not(feature = "sleep-proxy"), allow(unused_variables))]
+    fn handle(
+        &self,
+        event: &Event,
+        _records: &mut Vec<ResourceRecord>,
+        registration: &mut Option<&mut Service>,
+        _query: &mut Option<Query>,
+        _timeouts: &mut Vec<(ServiceState, u64)>,
+        queue: &mut Vec<(MdnsMessage, SendTarget)>,
+    ) -> Result<(), MdnsError> {
+        #[cfg(feature = "sleep-proxy")]
+        match event {
+            Event::SleepModeChanged(sleeping) => {
+                debug!("Sleep mode {}", sleeping);
+                self.sleeping.store(*sleeping, Ordering::Relaxed);
+                self.elapsed
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clear();
+            }
+            Event::Ttl() if self.is_sleeping() => {
+                if let Some(r) = registration {
+                    if Self::is_proxy_registered(r) {
+                        self.proxy(r, queue);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sleep-proxy")]
+#[test]
+fn test_sleep_proxy() {
+    use crate::service::ServiceBuilder;
+
+    let mut services: Vec<Service> = ["TestMachine", "OtherMachine"]
+        .into_iter()
+        .map(|host| {
+            let mut service = ServiceBuilder::default()
+                .host(host)
+                .service("_test")
+                .protocol("_tcp")
+                .port(53000)
+                .txt_flag("sleep-proxy")
+                .build()
+                .unwrap();
+            service.state = ServiceState::Registered;
+            service
+        })
+        .collect();
+
+    let announcements: Vec<_> = services
+        .iter()
+        .map(|s| MdnsMessage::announce(s).to_bytes())
+        .collect();
+
+    let handler = SleepProxyHandler::default();
+    let mut queue = vec![];
+
+    //Every event passes through the chain once for each registration
+    let mut handle = |event: Event, queue: &mut Vec<(MdnsMessage, SendTarget)>| {
+        for service in services.iter_mut() {
+            handler
+                .handle(
+                    &event,
+                    &mut vec![],
+                    &mut Some(service),
+                    &mut None,
+                    &mut vec![],
+                    queue,
+                )
+                .unwrap()
+        }
+    };
+
+    //Awake, nothing is proxied
+    for _ in 0..60 {
+        handle(Event::Ttl(), &mut queue);
+    }
+    assert!(queue.is_empty());
+
+    //Asleep, each service is announced when 80% of the shortest TTL (60s) has passed
+    handle(Event::SleepModeChanged(true), &mut queue);
+    for _ in 0..47 {
+        handle(Event::Ttl(), &mut queue);
+    }
+    assert!(queue.is_empty());
+
+    handle(Event::Ttl(), &mut queue);
+    assert_eq!(queue.len(), 2);
+
+    let announced: Vec<_> = queue.iter().map(|(m, _)| m.to_bytes()).collect();
+    assert_eq!(announced, announcements);
+
+    handle(Event::SleepModeChanged(false), &mut queue);
+    for _ in 0..60 {
+        handle(Event::Ttl(), &mut queue);
+    }
+    assert_eq!(queue.len(), 2);
+}
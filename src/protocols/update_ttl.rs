@@ -1,8 +1,12 @@
 use crate::{
-    message::MdnsMessage, record::ResourceRecord, service::ServiceState, MdnsError, Query, Service,
+    message::MdnsMessage, record::ResourceRecord, service::ServiceState, utility::SendTarget,
+    MdnsError, Query, Service,
 };
 
-use super::handler::{Event, Handler};
+use super::{
+    handler::{Event, Handler, RecordCallback},
+    response_handler::resolve,
+};
 
 /// Update TTL
 ///
@@ -19,44 +23,229 @@ use super::handler::{Event, Handler};
 /// Only records that are of an active interest to a local client are in need of this cache maintenance
 /// [RFC6762 Section 5.2 - Continuous Multicast DNS Querying](https://www.rfc-editor.org/rfc/rfc6762#section-5.2)
 ///
-/// - Decrease TTL for each record by 1s
+/// - Decrease TTL for each record by 1s, static records never expire
 /// - Verify if TTL cache rules are met
 /// - Notify if new query is necessary
+/// - Call the expiry and removed callbacks for each record whose TTL reaches 0
+/// - Remove the expired records from the cache
+/// - Forget resolved services of the [`Query`] which lost one of their records, so they are resolved again when it returns
 #[derive(Default, Copy, Clone)]
 pub struct UpdateTTLHandler<'a> {
-    next: Option<&'a dyn Handler<'a>>,
+    expiry_callbacks: &'a [RecordExpiryCallback],
+    removed_callbacks: &'a [RecordCallback],
 }
 
-impl<'a> Handler<'a> for UpdateTTLHandler<'a> {
-    fn set_next(&mut self, next: &'a dyn Handler<'a>) -> &mut dyn Handler<'a> {
-        self.next = Some(next);
+/// Callback called with a [`ResourceRecord`] when its TTL reaches 0, see [`crate::DnsSd2::on_record_expiry()`]
+pub type RecordExpiryCallback = Box<dyn Fn(&ResourceRecord) + Send + Sync>;
+
+impl<'a> UpdateTTLHandler<'a> {
+    /// Call `callbacks` for each record which expires
+    pub fn with_expiry_callbacks(mut self, callbacks: &'a [RecordExpiryCallback]) -> Self {
+        self.expiry_callbacks = callbacks;
+        self
+    }
+
+    /// Call `callbacks` for each record which expires, as it is removed from the cache
+    pub fn with_removed_callbacks(mut self, callbacks: &'a [RecordCallback]) -> Self {
+        self.removed_callbacks = callbacks;
         self
     }
+}
+
+impl<'a> Handler for UpdateTTLHandler<'a> {
     fn handle(
         &self,
         event: &Event,
         records: &mut Vec<ResourceRecord>,
-        registration: &mut Option<&mut Service>,
+        _registration: &mut Option<&mut Service>,
         query: &mut Option<Query>,
-        timeouts: &mut Vec<(ServiceState, u64)>,
-        queue: &mut Vec<MdnsMessage>,
+        _timeouts: &mut Vec<(ServiceState, u64)>,
+        _queue: &mut Vec<(MdnsMessage, SendTarget)>,
     ) -> Result<(), MdnsError> {
         match event {
-            Event::Ttl {} => {
+            Event::Ttl() => {
                 records.iter_mut().for_each(|rec| {
-                    if rec.ttl > 0 {
+                    if rec.ttl > 0 && !rec.is_static() {
                         rec.ttl -= 1;
+
+                        if rec.ttl == 0 {
+                            debug!("Record {} expired", rec.name.as_str());
+                            self.expiry_callbacks.iter().for_each(|f| f(rec));
+                            self.removed_callbacks.iter().for_each(|f| f(rec.clone()));
+                        }
                     }
 
                     //TODO Add query signal here if rules are met
                 });
+
+                let cached = records.len();
+                records.retain(|rec| rec.ttl > 0);
+
+                if let Some(q) = query.as_mut().filter(|_| records.len() < cached) {
+                    let available: Vec<String> = resolve(records, &q.name)
+                        .iter()
+                        .map(|s| s.to_dns_sd_string())
+                        .collect();
+
+                    q.resolved.retain(|r| {
+                        let complete = available.iter().any(|a| a.eq_ignore_ascii_case(r));
+
+                        if !complete {
+                            debug!("{} lost one of its records", r);
+                        }
+                        complete
+                    });
+                }
+
+                #[cfg(feature = "metrics")]
+                metrics::gauge!("mdns_cache_records_count").set(records.len() as f64);
             }
             _ => {}
         }
-        if let Some(v) = &self.next {
-            v.handle(event, records, registration, query, timeouts, queue)?;
-        }
 
         Ok(())
     }
 }
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_update_ttl_metrics() {
+    use crate::name::Name;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    let mut records = vec![
+        ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        ),
+        ResourceRecord::create_a_record(
+            Name::new("OtherMachine.local".into()).unwrap(),
+            [192, 168, 1, 3],
+        ),
+    ];
+
+    metrics::with_local_recorder(&recorder, || {
+        UpdateTTLHandler::default()
+            .handle(
+                &Event::Ttl(),
+                &mut records,
+                &mut None,
+                &mut None,
+                &mut vec![],
+                &mut vec![],
+            )
+            .unwrap();
+    });
+
+    let cached = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find(|(key, _, _, _)| key.key().name() == "mdns_cache_records_count")
+        .map(|(_, _, _, value)| value);
+
+    assert_eq!(cached, Some(DebugValue::Gauge(2.0.into())));
+}
+
+#[test]
+fn test_record_expiry() {
+    use crate::name::Name;
+    use std::sync::{Arc, Mutex};
+
+    let expired = Arc::new(Mutex::new(Vec::<String>::new()));
+    let e = expired.clone();
+    let callbacks: Vec<RecordExpiryCallback> = vec![Box::new(move |rec: &ResourceRecord| {
+        e.lock().unwrap().push(rec.name.as_str().to_string())
+    })];
+
+    let mut short = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    short.ttl = 2;
+    let mut long = ResourceRecord::create_a_record(
+        Name::new("OtherMachine.local".into()).unwrap(),
+        [192, 168, 1, 3],
+    );
+    long.ttl = 3;
+    let mut records = vec![short, long];
+
+    let handler = UpdateTTLHandler::default().with_expiry_callbacks(&callbacks);
+
+    for _ in 0..4 {
+        handler
+            .handle(
+                &Event::Ttl(),
+                &mut records,
+                &mut None,
+                &mut None,
+                &mut vec![],
+                &mut vec![],
+            )
+            .unwrap();
+
+        if records.len() == 1 {
+            assert_eq!(*expired.lock().unwrap(), ["TestMachine.local"]);
+        }
+    }
+
+    //Expired records are removed from the cache
+    assert!(records.is_empty());
+
+    //Each record is reported once, already expired records are not reported again
+    assert_eq!(
+        *expired.lock().unwrap(),
+        ["TestMachine.local", "OtherMachine.local"]
+    );
+}
+
+#[test]
+fn test_remove_expired_records() {
+    use crate::name::Name;
+
+    let instance = "TestMachine._test._tcp.local".to_string();
+
+    let mut records = vec![
+        ResourceRecord::create_ptr_record("TestMachine".into(), "_test".into(), "_tcp".into()),
+        ResourceRecord::create_srv_record(instance.clone(), 53000, "TestMachine.local".into()),
+        ResourceRecord::create_txt_record(Name::new(instance.clone()).unwrap(), vec![]),
+        ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            [192, 168, 1, 2],
+        )
+        .with_ttl(2),
+    ];
+
+    let mut query = Some(Query {
+        name: "_test._tcp.local".into(),
+        resolved: vec![instance],
+        ..Default::default()
+    });
+
+    let handler = UpdateTTLHandler::default();
+    let mut tick = |records: &mut Vec<ResourceRecord>| {
+        handler
+            .handle(
+                &Event::Ttl(),
+                records,
+                &mut None,
+                &mut query,
+                &mut vec![],
+                &mut vec![],
+            )
+            .unwrap();
+    };
+
+    tick(&mut records);
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[3].ttl, 1);
+
+    //The A record is removed after the second tick, the service can not be resolved anymore
+    tick(&mut records);
+    assert_eq!(records.len(), 3);
+    assert!(records.iter().all(|r| r.ttl > 0));
+    assert!(query.unwrap().resolved.is_empty());
+}
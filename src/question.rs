@@ -1,4 +1,6 @@
-use crate::name::Name;
+use bytes::{BufMut, BytesMut};
+
+use crate::{name::Name, MdnsError};
 
 /// Question
 ///
@@ -61,7 +63,38 @@ pub struct Question {
     pub unicast_question: bool,
 }
 
+/// Defaults to a multicast PTR question of any class, as used for browsing
+impl Default for Question {
+    fn default() -> Self {
+        Self {
+            name: Name::default(),
+            qtype: QType::Ptr,
+            qclass: QClass::Any,
+            unicast_question: false,
+        }
+    }
+}
+
 impl Question {
+    /// Question for the given name (e.g. '_http._tcp.local') with the [`Question::default()`] type and class
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dns_sd2::question::{QType, Question};
+    ///
+    /// let question = Question {
+    ///     qtype: QType::Srv,
+    ///     ..Question::for_service("MyMachine._http._tcp.local")
+    /// };
+    /// ```
+    pub fn for_service(name: impl Into<String>) -> Question {
+        Question {
+            name: Name::new(name.into()).expect("Should be valid"),
+            ..Default::default()
+        }
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = vec![];
 
@@ -69,13 +102,60 @@ impl Question {
         bytes.extend(self.name.to_bytes());
 
         //TYPE
-        bytes.extend((self.qtype as u16).to_be_bytes());
+        bytes.extend(u16::from(self.qtype).to_be_bytes());
 
         //CLASS
-        bytes.extend((self.qclass as u16).to_be_bytes());
+        //The top bit of the class requests a unicast response (QU)
+        bytes.extend(QClass::to_wire(self.qclass, self.unicast_question).to_be_bytes());
 
         bytes
     }
+
+    /// Write the encoded question to `buf`, see [`Question::to_bytes()`]
+    pub fn write_to(&self, buf: &mut BytesMut) {
+        self.name.write_to(buf);
+        buf.put_u16(self.qtype.into());
+        buf.put_u16(QClass::to_wire(self.qclass, self.unicast_question));
+    }
+
+    /// Parse a Question from a message
+    ///
+    /// Starts reading at `offset` and returns the Question with the offset directly after it
+    ///
+    /// Questions with a type or class this crate does not know are skipped and return `None`
+    pub fn parse(bytes: &[u8], offset: usize) -> Result<(Option<Question>, usize), MdnsError> {
+        //NAME
+        let (name, offset) = Name::parse(bytes, offset)?;
+
+        let fields = bytes
+            .get(offset..offset + 4)
+            .ok_or(MdnsError::InvalidMessage {})?;
+
+        //TYPE
+        let raw_type = u16::from_be_bytes([fields[0], fields[1]]);
+
+        //CLASS
+        //The top bit of the class indicates a unicast (QU) question
+        let raw_class = u16::from_be_bytes([fields[2], fields[3]]);
+
+        let question = match (QType::try_from(raw_type), QClass::from_wire(raw_class)) {
+            (Ok(qtype), Ok((qclass, unicast_question))) => Some(Question {
+                name,
+                qtype,
+                qclass,
+                unicast_question,
+            }),
+            _ => {
+                debug!(
+                    "Skipping question with type {} and class {}",
+                    raw_type, raw_class
+                );
+                None
+            }
+        };
+
+        Ok((question, offset + 4))
+    }
 }
 
 /// QClass defines what network class the question is asking for
@@ -90,7 +170,10 @@ impl Question {
 /// - [RFC6762 Section 5.4 - Questions Requesting Unicast Responses](https://www.rfc-editor.org/rfc/rfc6762#section-5.4)
 ///
 /// - [RFC1035 Section 3.2.5 - CLASS Values](https://www.rfc-editor.org/rfc/rfc1035#section-3.2.5)
+///
+/// Non-exhaustive, classes may still be assigned by IANA so matches need a `_` arm
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum QClass {
     /// 1 The Internet
     In = 1,
@@ -104,6 +187,49 @@ pub enum QClass {
     Any = 255,
 }
 
+impl QClass {
+    /// Wire format class with the top bit set if `top_bit` is true
+    ///
+    /// The top bit is the cache flush bit of a record or the unicast response (QU) bit of a question
+    ///
+    /// ## RFC Reference
+    /// - [RFC6762 Section 10.2 - Announcements to Flush Outdated Cache Entries](https://www.rfc-editor.org/rfc/rfc6762#section-10.2)
+    /// - [RFC6762 Section 18.12 - Repurposing of Top Bit of qclass in Question Section](https://www.rfc-editor.org/rfc/rfc6762#section-18.12)
+    pub fn to_wire(class: QClass, top_bit: bool) -> u16 {
+        u16::from(class) | if top_bit { 0x8000 } else { 0 }
+    }
+
+    /// Split a wire format class into its [`QClass`] and whether the top bit is set, see [`QClass::to_wire()`]
+    ///
+    /// Returns [`MdnsError::InvalidMessage`] for a class this crate does not know
+    pub fn from_wire(raw: u16) -> Result<(QClass, bool), MdnsError> {
+        Ok((QClass::try_from(raw & 0x7FFF)?, raw & 0x8000 != 0))
+    }
+}
+
+impl TryFrom<u16> for QClass {
+    type Error = MdnsError;
+
+    /// Convert a wire format class code (without the top QU / cache flush bit) into a [`QClass`]
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(QClass::In),
+            2 => Ok(QClass::Cs),
+            3 => Ok(QClass::Ch),
+            4 => Ok(QClass::Hs),
+            255 => Ok(QClass::Any),
+            _ => Err(MdnsError::InvalidMessage {}),
+        }
+    }
+}
+
+impl From<QClass> for u16 {
+    /// Wire format class code of a [`QClass`], without the top QU / cache flush bit
+    fn from(qclass: QClass) -> Self {
+        qclass as u16
+    }
+}
+
 /// QType defines what the question is asking for
 ///
 /// QTypes are a superset of Types, so all Types are valid QTypes
@@ -113,8 +239,10 @@ pub enum QClass {
 ///## RFC Reference
 /// - [RFC1035 Section 3.2.2 - DNS Types](https://www.rfc-editor.org/rfc/rfc1035#section-3.2.2)
 /// - [RFC1035 Section 4.1 - Format](https://www.rfc-editor.org/rfc/rfc1035#section-4.1)
-
+///
+/// Non-exhaustive, new record types are regularly assigned and support for more of them may be added
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum QType {
     /// 1 a host address (IPV4)
     A = 1,
@@ -159,3 +287,175 @@ pub enum QType {
     /// 255 A request for all records
     Any = 255,
 }
+
+impl TryFrom<u16> for QType {
+    type Error = MdnsError;
+
+    /// Convert a wire format type code into a [`QType`]
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(QType::A),
+            2 => Ok(QType::Ns),
+            3 => Ok(QType::Md),
+            4 => Ok(QType::Mf),
+            5 => Ok(QType::Cname),
+            6 => Ok(QType::Soa),
+            7 => Ok(QType::Mb),
+            8 => Ok(QType::Mg),
+            9 => Ok(QType::Mr),
+            10 => Ok(QType::Null),
+            11 => Ok(QType::Wks),
+            12 => Ok(QType::Ptr),
+            13 => Ok(QType::Hinfo),
+            14 => Ok(QType::Minfo),
+            15 => Ok(QType::Mx),
+            16 => Ok(QType::Txt),
+            28 => Ok(QType::Aaaa),
+            33 => Ok(QType::Srv),
+            47 => Ok(QType::Nsec),
+            252 => Ok(QType::Axfr),
+            255 => Ok(QType::Any),
+            _ => Err(MdnsError::InvalidMessage {}),
+        }
+    }
+}
+
+impl From<QType> for u16 {
+    /// Wire format type code of a [`QType`]
+    fn from(qtype: QType) -> Self {
+        qtype as u16
+    }
+}
+
+#[test]
+fn test_question_to_bytes() {
+    let question = Question {
+        name: Name::new("TestMachine._test._tcp.local".into()).unwrap(),
+        qtype: QType::Srv,
+        qclass: QClass::In,
+        unicast_question: false,
+    };
+
+    //Length prefixed labels, as encoded from a dotted String
+    let mut expected = vec![];
+    for label in "TestMachine._test._tcp.local".split('.') {
+        expected.push(label.len() as u8);
+        expected.extend(label.as_bytes());
+    }
+    expected.push(0);
+    expected.extend((QType::Srv as u16).to_be_bytes());
+    expected.extend((QClass::In as u16).to_be_bytes());
+
+    assert_eq!(question.to_bytes(), expected);
+
+    //Round trip through the parser
+    let (parsed, offset) = Question::parse(&expected, 0).unwrap();
+    assert_eq!(parsed.unwrap().to_bytes(), expected);
+    assert_eq!(offset, expected.len());
+}
+
+#[test]
+fn test_question_default() {
+    //Root name, PTR type and ANY class
+    assert_eq!(Question::default().to_bytes(), [0, 0, 12, 0, 255]);
+
+    let question = Question::for_service("_http._tcp.local");
+
+    let mut expected = vec![5];
+    expected.extend(b"_http");
+    expected.push(4);
+    expected.extend(b"_tcp");
+    expected.push(5);
+    expected.extend(b"local");
+    expected.extend([0, 0, 12, 0, 255]);
+
+    assert_eq!(question.to_bytes(), expected);
+    assert!(!question.unicast_question);
+}
+
+#[test]
+fn test_question_unicast() {
+    let question = Question {
+        qtype: QType::Any,
+        qclass: QClass::In,
+        unicast_question: true,
+        ..Question::for_service("TestMachine.local")
+    };
+
+    //QU bit is bit 15 of the class
+    let bytes = question.to_bytes();
+    let qclass = u16::from_be_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]);
+    assert_eq!(qclass, 0x8000 | QClass::In as u16);
+
+    //Round trip through the parser
+    let parsed = Question::parse(&bytes, 0).unwrap().0.unwrap();
+    assert!(parsed.unicast_question);
+    assert_eq!(parsed.qclass, QClass::In);
+    assert_eq!(parsed.to_bytes(), bytes);
+}
+
+#[test]
+fn test_wire_codes() {
+    let qtypes = [
+        (1, QType::A),
+        (2, QType::Ns),
+        (3, QType::Md),
+        (4, QType::Mf),
+        (5, QType::Cname),
+        (6, QType::Soa),
+        (7, QType::Mb),
+        (8, QType::Mg),
+        (9, QType::Mr),
+        (10, QType::Null),
+        (11, QType::Wks),
+        (12, QType::Ptr),
+        (13, QType::Hinfo),
+        (14, QType::Minfo),
+        (15, QType::Mx),
+        (16, QType::Txt),
+        (28, QType::Aaaa),
+        (33, QType::Srv),
+        (47, QType::Nsec),
+        (252, QType::Axfr),
+        (255, QType::Any),
+    ];
+
+    for (code, qtype) in qtypes {
+        assert_eq!(QType::try_from(code).unwrap(), qtype);
+        assert_eq!(u16::from(qtype), code);
+    }
+
+    let qclasses = [
+        (1, QClass::In),
+        (2, QClass::Cs),
+        (3, QClass::Ch),
+        (4, QClass::Hs),
+        (255, QClass::Any),
+    ];
+
+    for (code, qclass) in qclasses {
+        assert_eq!(QClass::try_from(code).unwrap(), qclass);
+        assert_eq!(u16::from(qclass), code);
+    }
+
+    //Unassigned codes do not convert
+    for code in [0, 17, 27, 254, 256, u16::MAX] {
+        assert!(QType::try_from(code).is_err());
+    }
+
+    //The top QU / cache flush bit is not part of the class code
+    for code in [0, 5, 254, 0x8001] {
+        assert!(QClass::try_from(code).is_err());
+    }
+}
+
+#[test]
+fn test_wire_class() {
+    assert_eq!(QClass::to_wire(QClass::In, true), 0x8001);
+    assert_eq!(QClass::to_wire(QClass::In, false), 0x0001);
+    assert_eq!(QClass::to_wire(QClass::Any, true), 0x80FF);
+
+    assert_eq!(QClass::from_wire(0x8001).unwrap(), (QClass::In, true));
+    assert_eq!(QClass::from_wire(0x0001).unwrap(), (QClass::In, false));
+    assert!(QClass::from_wire(0x8005).is_err());
+}
@@ -1,11 +1,25 @@
+use bytes::{BufMut, BytesMut};
 use packed_struct::PackedStruct;
 
 use crate::{
     name::Name,
     question::{QClass, QType},
-    records::{a::ARecord, aaaa::AAAARecord, ptr::PTRRecord, srv::SRVRecord, txt::TXTRecord},
+    records::{
+        a::ARecord, aaaa::AAAARecord, cname::CNAMERecord, nsec::NSECRecord, ptr::PTRRecord,
+        srv::SRVRecord, txt::TXTRecord, unknown::UnknownRecord,
+    },
+    MdnsError, Service,
 };
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+use tokio::time::Instant;
+
+/// TTL of static records, e.g. imported with [`crate::DnsSd2::import_hosts_file()`]
+///
+/// Static records never expire and are served in response to queries
+pub const STATIC_TTL: u32 = u32::MAX;
 
 /// A Record describing a certain [`QClass`] and [`QType`]
 ///
@@ -33,7 +47,17 @@ use std::fmt::Debug;
 /// /                                               /
 /// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
 /// ```
-#[derive(Debug)]
+///
+/// With the `serde` feature the RDATA is serialized as a base64 string, see [`Base64RData`]
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serde_record::RecordFields",
+        try_from = "serde_record::RecordFields"
+    )
+)]
 pub struct ResourceRecord {
     /// NAME     
     ///
@@ -67,6 +91,10 @@ pub struct ResourceRecord {
     /// interpreted to mean that the RR can only be used for the
     /// transaction in progress, and should not be cached.
     pub ttl: u32,
+    /// ORIGINAL TTL
+    ///
+    /// The TTL this record was received or created with, `ttl` counts down from this value
+    pub original_ttl: u32,
     /// RDLENGTH
     ///
     /// an unsigned 16 bit integer that specifies the length in
@@ -82,9 +110,43 @@ pub struct ResourceRecord {
     /// Implementation is done through the RData trait allowing methods for packing to a byte array
     /// See structs in the ./records folder
     pub rdata: Option<Box<dyn RData + Send>>,
+    /// RECEIVED AT
+    ///
+    /// When this record was received on the socket, `None` for records created by this host
+    ///
+    /// Used to spare records received within the last second when a cache flush record arrives
+    pub received_at: Option<Instant>,
 }
 
 impl ResourceRecord {
+    /// Packed RDATA of this record, `None` without RDATA
+    pub fn rdata_bytes(&self) -> Option<Vec<u8>> {
+        Some(self.rdata.as_ref()?.to_bytes())
+    }
+
+    /// Whether this record is static and never expires, see [`STATIC_TTL`]
+    pub fn is_static(&self) -> bool {
+        self.ttl == STATIC_TTL
+    }
+
+    /// Whether this record is a goodbye, announcing the record is no longer valid (TTL of 0)
+    ///
+    /// [RFC6762 Section 10.1 - Goodbye Packets](https://www.rfc-editor.org/rfc/rfc6762#section-10.1)
+    pub fn is_goodbye(&self) -> bool {
+        self.ttl == 0
+    }
+
+    /// Copy of this record as a goodbye, with a TTL of 0
+    pub fn as_goodbye(&self) -> ResourceRecord {
+        self.clone().with_ttl(0)
+    }
+
+    /// Set the remaining TTL in seconds, the original TTL is unchanged
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
     pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
         //If there is no RDATA set return Error
         if let Some(rdata) = &self.rdata {
@@ -94,16 +156,11 @@ impl ResourceRecord {
             bytes.extend(self.name.to_bytes());
 
             //TYPE
-            bytes.extend((self.record_type as u16).to_be_bytes());
+            bytes.extend(u16::from(self.record_type).to_be_bytes());
 
             //CLASS
-            let mut class_bytes = (self.record_class as u16).to_be_bytes();
-
             //If Caches need to be flushed, set first bit of Class to 1
-            if self.cache_flush {
-                class_bytes[0] |= 0b1000_0000;
-            }
-            bytes.extend(class_bytes);
+            bytes.extend(QClass::to_wire(self.record_class, self.cache_flush).to_be_bytes());
 
             //TTL
             bytes.extend(self.ttl.to_be_bytes());
@@ -120,7 +177,84 @@ impl ResourceRecord {
 
             Ok(bytes)
         } else {
-            return Err("No RDATA set for this record".to_string());
+            Err("No RDATA set for this record".to_string())
+        }
+    }
+
+    /// Write the encoded record to `buf`, records without RDATA are skipped like in [`ResourceRecord::to_bytes()`]
+    ///
+    /// The RDLENGTH is filled in after the RDATA is written
+    pub fn write_to(&self, buf: &mut BytesMut) {
+        if let Some(rdata) = &self.rdata {
+            self.name.write_to(buf);
+            buf.put_u16(self.record_type.into());
+            buf.put_u16(QClass::to_wire(self.record_class, self.cache_flush));
+            buf.put_u32(self.ttl);
+
+            let rdlength = buf.len();
+            buf.put_u16(0);
+
+            rdata.write_to(buf);
+
+            let length = (buf.len() - rdlength - 2) as u16;
+            buf[rdlength..rdlength + 2].copy_from_slice(&length.to_be_bytes());
+        }
+    }
+
+    /// Parse a Resource Record from a message
+    ///
+    /// Starts reading at `offset` and returns the record with the offset directly after it
+    ///
+    /// The RDATA is parsed with [`parse_rdata()`], types without a record struct are kept as received in an [`UnknownRecord`]
+    ///
+    /// Records with a type or class this crate does not know are skipped and return `None`
+    pub fn parse(
+        bytes: &[u8],
+        offset: usize,
+    ) -> Result<(Option<ResourceRecord>, usize), MdnsError> {
+        //NAME
+        let (name, offset) = Name::parse(bytes, offset)?;
+
+        //TYPE, CLASS, TTL and RDLENGTH
+        let fields = bytes
+            .get(offset..offset + 10)
+            .ok_or(MdnsError::InvalidMessage {})?;
+
+        let raw_type = u16::from_be_bytes([fields[0], fields[1]]);
+        let raw_class = u16::from_be_bytes([fields[2], fields[3]]);
+        let ttl = u32::from_be_bytes([fields[4], fields[5], fields[6], fields[7]]);
+        let rdlength = u16::from_be_bytes([fields[8], fields[9]]);
+
+        //RDATA
+        let offset = offset + 10;
+        let data = bytes
+            .get(offset..offset + rdlength as usize)
+            .ok_or(MdnsError::InvalidMessage {})?;
+        let offset = offset + data.len();
+
+        //The top bit of the class indicates the cache flush bit
+        match (QType::try_from(raw_type), QClass::from_wire(raw_class)) {
+            (Ok(record_type), Ok((record_class, cache_flush))) => Ok((
+                Some(ResourceRecord {
+                    name,
+                    record_type,
+                    record_class,
+                    cache_flush,
+                    ttl,
+                    original_ttl: ttl,
+                    rdlength,
+                    rdata: Some(parse_rdata(record_type, data, bytes)?),
+                    received_at: Some(Instant::now()),
+                }),
+                offset,
+            )),
+            _ => {
+                debug!(
+                    "Skipping record with type {} and class {}",
+                    raw_type, raw_class
+                );
+                Ok((None, offset))
+            }
         }
     }
 
@@ -136,16 +270,18 @@ impl ResourceRecord {
             record_class: QClass::In,
             cache_flush: false,
             ttl: 60,
+            original_ttl: 60,
             rdlength: rdata_packed
                 .len()
                 .try_into()
                 .expect("Could not cast usize to u16"),
             rdata: Some(Box::new(rdata)),
+            received_at: None,
         }
     }
 
     /// Create a 'AAAA' type Resource Record
-    pub fn create_aaaa_record(name: Name, ip: [u16; 4]) -> Self {
+    pub fn create_aaaa_record(name: Name, ip: [u16; 8]) -> Self {
         let rdata = AAAARecord { ip };
 
         let rdata_packed = rdata.pack().expect("Packing AAAA record failed");
@@ -156,34 +292,123 @@ impl ResourceRecord {
             record_class: QClass::In,
             cache_flush: false,
             ttl: 120,
+            original_ttl: 120,
             rdlength: rdata_packed
                 .len()
                 .try_into()
                 .expect("Could not cast usize to u16"),
             rdata: Some(Box::new(rdata)),
+            received_at: None,
         }
     }
 
+    /// Create a 'A' type Resource Record from an [`Ipv4Addr`]
+    pub fn create_a_record_from_ipv4(name: Name, ip: Ipv4Addr) -> Self {
+        ResourceRecord::create_a_record(name, ip.octets())
+    }
+
+    /// Create a 'A' type Resource Record for the host of a [`Service`] (e.g. 'MyMachine.local')
+    pub fn create_a_record_from_service(service: &Service, ip: Ipv4Addr) -> Self {
+        ResourceRecord::create_a_record_from_ipv4(Name::local_for(&service.host), ip)
+    }
+
+    /// Create a 'AAAA' type Resource Record from an [`Ipv6Addr`]
+    pub fn create_aaaa_record_from_ipv6(name: Name, ip: Ipv6Addr) -> Self {
+        ResourceRecord::create_aaaa_record(name, ip.segments())
+    }
+
     /// Create a 'PTR' type Resource Record
     pub fn create_ptr_record(host: String, service: String, protocol: String) -> Self {
+        //Invalid labels, e.g. of a default Service, leave the names empty
+        let service_type = Name::local()
+            .prepend(&protocol)
+            .and_then(|n| n.prepend(&service))
+            .unwrap_or_default();
+
         let rdata = PTRRecord {
-            name: Name::new(host.clone() + "." + &service + "." + &protocol + ".local")
-                .expect("Should be valid"),
+            name: service_type.prepend(&host).unwrap_or_default(),
         };
 
         let rdata_packed = rdata.to_bytes();
 
         ResourceRecord {
-            name: Name::new(service + "." + &protocol + ".local").expect("Should be valid"),
+            name: service_type,
             record_type: QType::Ptr,
             record_class: QClass::In,
             cache_flush: false,
             ttl: 60,
+            original_ttl: 60,
             rdlength: rdata_packed
                 .len()
                 .try_into()
                 .expect("Could not cast usize to u16"),
             rdata: Some(Box::new(rdata)),
+            received_at: None,
+        }
+    }
+
+    /// Create a 'PTR' type Resource Record pointing from the service type of a [`Service`] to its instance name
+    pub fn create_ptr_record_from_service(service: &Service) -> Self {
+        ResourceRecord::create_ptr_record(
+            service.host.clone(),
+            service.service.clone(),
+            service.protocol.clone(),
+        )
+    }
+
+    /// Create a 'CNAME' type Resource Record, making `name` an alias of `target`
+    ///
+    /// Mdns names should not be aliases, this is meant for hybrid unicast and multicast DNS networks
+    ///
+    /// [RFC6762 Section 23 - Hybrid Unicast/Multicast DNS Networks](https://www.rfc-editor.org/rfc/rfc6762#section-23)
+    pub fn create_cname_record(name: Name, target: Name) -> Self {
+        let rdata = CNAMERecord { target };
+
+        let rdata_packed = rdata.to_bytes();
+
+        ResourceRecord {
+            name,
+            record_type: QType::Cname,
+            record_class: QClass::In,
+            cache_flush: false,
+            ttl: 120,
+            original_ttl: 120,
+            rdlength: rdata_packed
+                .len()
+                .try_into()
+                .expect("Could not cast usize to u16"),
+            rdata: Some(Box::new(rdata)),
+            received_at: None,
+        }
+    }
+
+    /// Create a 'NSEC' type Resource Record
+    ///
+    /// Asserts that only the given record types exist for the name
+    ///
+    /// ## RFC Reference
+    /// - [RFC6762 Section 6.1 - Negative Responses](https://www.rfc-editor.org/rfc/rfc6762#section-6.1)
+    pub fn create_nsec_record(name: Name, types: Vec<QType>) -> Self {
+        let rdata = NSECRecord {
+            next_domain: name.clone(),
+            types,
+        };
+
+        let rdata_packed = rdata.to_bytes();
+
+        ResourceRecord {
+            name,
+            record_type: QType::Nsec,
+            record_class: QClass::In,
+            cache_flush: true,
+            ttl: 120,
+            original_ttl: 120,
+            rdlength: rdata_packed
+                .len()
+                .try_into()
+                .expect("Could not cast usize to u16"),
+            rdata: Some(Box::new(rdata)),
+            received_at: None,
         }
     }
 
@@ -203,46 +428,592 @@ impl ResourceRecord {
             record_class: QClass::In,
             cache_flush: false,
             ttl: 60,
+            original_ttl: 60,
             rdlength: rdata_packed
                 .len()
                 .try_into()
                 .expect("Could not cast usize to u16"),
             rdata: Some(Box::new(rdata)),
+            received_at: None,
         }
     }
-    
+
+    /// Create a 'SRV' type Resource Record for the instance name of a [`Service`], targeting its host
+    pub fn create_srv_record_from_service(service: &Service) -> Self {
+        ResourceRecord::create_srv_record(
+            service.to_dns_sd_string(),
+            service.port,
+            Name::local_for(&service.host).as_str().to_string(),
+        )
+    }
+
     /// Create a 'TXT' type record
-    pub fn create_txt_record(name: Name,txt: Vec<String>) -> Self{
-        let rdata = TXTRecord{txt_record:txt};
-        
+    ///
+    /// The name should be the service instance name (e.g. 'MyMachine._myservice._udp.local')
+    ///
+    /// TXT records are not tied to the host name, so they use the 75 minute default TTL
+    ///
+    /// ## RFC Reference
+    /// - [RFC6763 Section 6 - Data Syntax for DNS-SD TXT Records](https://www.rfc-editor.org/rfc/rfc6763#section-6)
+    /// - [RFC6762 Section 10 - Resource Record TTL Values and Cache Coherency](https://www.rfc-editor.org/rfc/rfc6762#section-10)
+    pub fn create_txt_record(name: Name, txt_records: Vec<String>) -> Self {
+        let rdata = TXTRecord {
+            txt_record: txt_records,
+        };
+
         let rdata_packed = rdata.to_bytes();
-        
+
         ResourceRecord {
             name,
             record_type: QType::Txt,
             record_class: QClass::In,
             cache_flush: false,
-            ttl: 60,
+            ttl: 4500,
+            original_ttl: 4500,
             rdlength: rdata_packed
                 .len()
                 .try_into()
                 .expect("Could not cast usize to u16"),
             rdata: Some(Box::new(rdata)),
+            received_at: None,
         }
     }
+
+    /// Fraction of `original_ttl` remaining, clamped to `[0.0, 1.0]`
+    ///
+    /// A record with an `original_ttl` of 0 has nothing remaining
+    pub fn age(&self, original_ttl: u32) -> f64 {
+        if original_ttl == 0 {
+            return 0.0;
+        }
+
+        (self.ttl as f64 / original_ttl as f64).clamp(0.0, 1.0)
+    }
+
+    /// Whether less than 20% of `original_ttl` remains
+    pub fn is_stale(&self, original_ttl: u32) -> bool {
+        self.age(original_ttl) < 0.2
+    }
+
+    /// Whether the TTL has just reached 80%, 85%, 90% or 95% of `original_ttl`
+    ///
+    /// TTL is counted down each second, so this is true once for each threshold
+    ///
+    /// [RFC6762 Section 5.2 - Continuous Multicast DNS Querying](https://www.rfc-editor.org/rfc/rfc6762#section-5.2)
+    pub fn needs_refresh_query(&self, original_ttl: u32) -> bool {
+        if self.ttl >= original_ttl {
+            return false;
+        }
+
+        let elapsed = (original_ttl - self.ttl) as u64 * 100;
+        let previous = elapsed - 100;
+
+        [80, 85, 90, 95].iter().any(|p| {
+            let threshold = p * original_ttl as u64;
+            elapsed >= threshold && previous < threshold
+        })
+    }
 }
 
 /// RData Trait
 ///
 /// Trait describing functions for the RData field of a Resource Record
 /// Allows for packing byte arrays from Resource Record Data
-pub trait RData {
+///
+/// Records are shared with the event loop, which may run in a spawned task, so RData is Sync
+pub trait RData: Sync {
     fn to_bytes(&self) -> Vec<u8>;
+    /// Write the packed RDATA to `buf`, see [`MdnsMessage::encode()`](crate::message::MdnsMessage::encode)
+    ///
+    /// Writes [`RData::to_bytes()`] by default, record types may write their fields directly
+    fn write_to(&self, buf: &mut BytesMut) {
+        buf.put_slice(&self.to_bytes());
+    }
+    /// Name of the implementing record type, used for Debug output (e.g. "ARecord")
+    fn debug_name(&self) -> &'static str;
+    /// Clone into a new box, allows cloning a [`ResourceRecord`]
+    fn clone_box(&self) -> Box<dyn RData + Send>;
+    /// Parse the RDATA of a received record
+    ///
+    /// `rdata` is a slice of `full_message`, which is needed to decompress names, see [`parse_rdata_name()`]
+    fn parse(rdata: &[u8], full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError>
+    where
+        Self: Sized;
+}
+
+/// Parse the RDATA of a received record into the record struct for its type
+///
+/// Types without a record struct are kept as received in an [`UnknownRecord`]
+pub fn parse_rdata(
+    qtype: QType,
+    rdata: &[u8],
+    full_message: &[u8],
+) -> Result<Box<dyn RData + Send>, MdnsError> {
+    match qtype {
+        QType::A => ARecord::parse(rdata, full_message),
+        QType::Aaaa => AAAARecord::parse(rdata, full_message),
+        QType::Ptr => PTRRecord::parse(rdata, full_message),
+        QType::Srv => SRVRecord::parse(rdata, full_message),
+        QType::Txt => TXTRecord::parse(rdata, full_message),
+        QType::Nsec => NSECRecord::parse(rdata, full_message),
+        QType::Cname => CNAMERecord::parse(rdata, full_message),
+        _ => UnknownRecord::parse(rdata, full_message),
+    }
 }
 
-///TODO TEST THIS
+/// Parse a name starting at `offset` in `rdata`, returns the name and the offset in `rdata` directly after it
+///
+/// Names in RDATA may be compressed with pointers into the rest of the message.
+/// If `rdata` is not a slice of `full_message`, the name is parsed from `rdata` alone
+///
+/// [RFC1035 Section 4.1.4 - Message Compression](https://www.rfc-editor.org/rfc/rfc1035#section-4.1.4)
+pub fn parse_rdata_name(
+    rdata: &[u8],
+    full_message: &[u8],
+    offset: usize,
+) -> Result<(Name, usize), MdnsError> {
+    let start = (rdata.as_ptr() as usize).wrapping_sub(full_message.as_ptr() as usize);
+
+    let (name, end) = if start + rdata.len() <= full_message.len() {
+        //Names may not continue past the RDATA
+        let (name, end) = Name::parse(&full_message[..start + rdata.len()], start + offset)?;
+        (name, end - start)
+    } else {
+        Name::parse(rdata, offset)?
+    };
+
+    Ok((name, end))
+}
+
+/// Records are equal if their name (case insensitive), type, class, cache flush bit, TTL and RDATA are equal
+///
+/// The original TTL and the time the record was received are not compared
+impl PartialEq for ResourceRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.record_type == other.record_type
+            && self.record_class == other.record_class
+            && self.cache_flush == other.cache_flush
+            && self.ttl == other.ttl
+            && self.rdlength == other.rdlength
+            && self.rdata_bytes() == other.rdata_bytes()
+    }
+}
+
+impl Eq for ResourceRecord {}
+
+impl Clone for Box<dyn RData + Send> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Debug Implementation for RData
+///
+/// Shows the record type name and the packed RDATA as hex bytes
 impl Debug for dyn RData + Send {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "RData : {{{:?}}}", self)
+        write!(
+            f,
+            "RData::{{ type: {}, bytes: {:02x?} }}",
+            self.debug_name(),
+            self.to_bytes()
+        )
+    }
+}
+
+/// Raw RDATA, serialized as a base64 string with the `serde` feature
+///
+/// Keeps the RDATA of any record type, including an [`UnknownRecord`], readable in JSON
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Base64RData(pub Vec<u8>);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Base64RData {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        s.serialize_str(&STANDARD.encode(&self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Base64RData {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let encoded = String::deserialize(d)?;
+
+        STANDARD
+            .decode(encoded)
+            .map(Base64RData)
+            .map_err(serde::de::Error::custom)
     }
 }
+
+/// Serde representation of a [`ResourceRecord`], the RDATA is parsed again for its type on deserializing
+#[cfg(feature = "serde")]
+mod serde_record {
+    use super::{parse_rdata, Base64RData, ResourceRecord};
+    use crate::{name::Name, MdnsError};
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct RecordFields {
+        name: String,
+        record_type: u16,
+        record_class: u16,
+        cache_flush: bool,
+        ttl: u32,
+        original_ttl: u32,
+        rdata: Option<Base64RData>,
+    }
+
+    impl From<ResourceRecord> for RecordFields {
+        fn from(record: ResourceRecord) -> Self {
+            RecordFields {
+                name: record.name.as_str().to_string(),
+                record_type: record.record_type.into(),
+                record_class: record.record_class.into(),
+                cache_flush: record.cache_flush,
+                ttl: record.ttl,
+                original_ttl: record.original_ttl,
+                rdata: record.rdata_bytes().map(Base64RData),
+            }
+        }
+    }
+
+    impl TryFrom<RecordFields> for ResourceRecord {
+        type Error = MdnsError;
+
+        fn try_from(fields: RecordFields) -> Result<Self, Self::Error> {
+            let record_type = fields.record_type.try_into()?;
+
+            let rdata = match &fields.rdata {
+                Some(Base64RData(bytes)) => Some(parse_rdata(record_type, bytes, bytes)?),
+                None => None,
+            };
+
+            Ok(ResourceRecord {
+                name: Name::new(fields.name).map_err(|_| MdnsError::InvalidMessage {})?,
+                record_type,
+                record_class: fields.record_class.try_into()?,
+                cache_flush: fields.cache_flush,
+                ttl: fields.ttl,
+                original_ttl: fields.original_ttl,
+                rdlength: fields
+                    .rdata
+                    .map_or(0, |Base64RData(bytes)| bytes.len() as u16),
+                rdata,
+                received_at: None,
+            })
+        }
+    }
+}
+
+#[test]
+fn test_record_debug() {
+    let name = Name::new("TestMachine.local".into()).unwrap();
+
+    let records = [
+        ResourceRecord::create_a_record(name.clone(), [192, 168, 1, 1]),
+        ResourceRecord::create_aaaa_record(name.clone(), [0xfe80, 0, 0, 0, 0, 0, 0, 1]),
+        ResourceRecord::create_ptr_record("TestMachine".into(), "_test".into(), "_tcp".into()),
+        ResourceRecord::create_srv_record(
+            "TestMachine._test._tcp.local".into(),
+            53000,
+            "TestMachine.local".into(),
+        ),
+        ResourceRecord::create_txt_record(name, vec!["key=value".into()]),
+    ];
+
+    let names = ["ARecord", "AAAARecord", "PTRRecord", "SRVRecord", "TXTRecord"];
+
+    for (record, name) in records.iter().zip(names) {
+        let output = format!("{:?}", record);
+        assert!(output.contains(name));
+    }
+
+    let a = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 1],
+    );
+    assert_eq!(
+        format!("{:?}", a.rdata.unwrap()),
+        "RData::{ type: ARecord, bytes: [c0, a8, 01, 01] }"
+    );
+}
+
+#[test]
+fn test_address_records() {
+    let ipv4: Ipv4Addr = "192.168.1.2".parse().unwrap();
+    let a = ResourceRecord::create_a_record_from_ipv4(
+        Name::new("TestMachine.local".into()).unwrap(),
+        ipv4,
+    );
+
+    assert_eq!(a.rdata.unwrap().to_bytes(), ipv4.octets());
+
+    let ipv6: Ipv6Addr = "fd00::1:2".parse().unwrap();
+    let aaaa = ResourceRecord::create_aaaa_record_from_ipv6(
+        Name::new("TestMachine.local".into()).unwrap(),
+        ipv6,
+    );
+
+    assert_eq!(aaaa.rdlength, 16);
+    assert_eq!(aaaa.rdata.unwrap().to_bytes(), ipv6.octets());
+}
+
+#[test]
+fn test_record_age() {
+    let mut record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+
+    assert_eq!(record.original_ttl, 60);
+    assert_eq!(record.age(60), 1.0);
+    assert_eq!(record.age(30), 1.0);
+    assert_eq!(record.age(0), 0.0);
+
+    let refreshes: Vec<u32> = (0..=100)
+        .rev()
+        .filter(|ttl| {
+            record.ttl = *ttl;
+            record.needs_refresh_query(100)
+        })
+        .collect();
+
+    //80%, 85%, 90% and 95% of the TTL consumed
+    assert_eq!(refreshes, [20, 15, 10, 5]);
+
+    record.ttl = 50;
+    assert_eq!(record.age(100), 0.5);
+    assert!(!record.is_stale(100));
+
+    record.ttl = 20;
+    assert!(!record.is_stale(100));
+
+    record.ttl = 19;
+    assert!(record.is_stale(100));
+
+    record.ttl = 0;
+    assert_eq!(record.age(100), 0.0);
+    assert!(record.is_stale(100));
+}
+
+#[test]
+fn test_records_from_service() {
+    let service = Service {
+        host: "TestMachine".into(),
+        service: "_test".into(),
+        protocol: "_tcp".into(),
+        port: 53000,
+        ..Default::default()
+    };
+
+    //Equal to the records created from the assembled names
+    assert_eq!(
+        ResourceRecord::create_srv_record_from_service(&service),
+        ResourceRecord::create_srv_record(
+            "TestMachine._test._tcp.local".into(),
+            53000,
+            "TestMachine.local".into()
+        )
+    );
+    assert_eq!(
+        ResourceRecord::create_ptr_record_from_service(&service),
+        ResourceRecord::create_ptr_record("TestMachine".into(), "_test".into(), "_tcp".into())
+    );
+    assert_eq!(
+        ResourceRecord::create_a_record_from_service(&service, Ipv4Addr::new(192, 168, 1, 2)),
+        ResourceRecord::create_a_record(
+            Name::new("TestMachine.local".into()).unwrap(),
+            [192, 168, 1, 2]
+        )
+    );
+
+    let ptr = ResourceRecord::create_ptr_record_from_service(&service);
+    assert_eq!(ptr.name.as_str(), "_test._tcp.local");
+    assert_eq!(
+        ptr.rdata.as_ref().unwrap().to_bytes(),
+        Name::new("TestMachine._test._tcp.local".into())
+            .unwrap()
+            .to_bytes()
+    );
+}
+
+#[test]
+fn test_goodbye_record() {
+    let record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    assert!(!record.is_goodbye());
+
+    let goodbye = record.as_goodbye();
+    assert!(goodbye.is_goodbye());
+    assert_eq!(goodbye.name.as_str(), record.name.as_str());
+    assert_eq!(goodbye.record_type, record.record_type);
+    assert_eq!(goodbye.record_class, record.record_class);
+    assert_eq!(goodbye.original_ttl, record.original_ttl);
+    assert_eq!(goodbye.rdlength, record.rdlength);
+    assert_eq!(
+        goodbye.rdata.as_ref().map(|r| r.to_bytes()),
+        record.rdata.as_ref().map(|r| r.to_bytes())
+    );
+
+    let record = record.with_ttl(30);
+    assert_eq!(record.ttl, 30);
+    assert_eq!(record.original_ttl, 60);
+}
+
+#[test]
+fn test_record_eq() {
+    let record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+
+    let mut same = ResourceRecord::create_a_record(
+        Name::new("testmachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    same.received_at = Some(Instant::now());
+    assert_eq!(record, same);
+    assert!([same].contains(&record));
+
+    assert_ne!(record, record.clone().with_ttl(30));
+    assert_ne!(
+        record,
+        ResourceRecord::create_a_record(record.name.clone(), [192, 168, 1, 3])
+    );
+    assert_ne!(
+        record,
+        ResourceRecord::create_a_record_from_ipv4(record.name.clone(), Ipv4Addr::LOCALHOST)
+    );
+}
+
+#[test]
+fn test_parse_rdata() {
+    use crate::{message::MdnsMessage, service::ServiceBuilder};
+
+    let name = Name::new("TestMachine.local".into()).unwrap();
+
+    let records = [
+        ResourceRecord::create_a_record(name.clone(), [192, 168, 1, 1]),
+        ResourceRecord::create_aaaa_record(name.clone(), [0xfe80, 0, 0, 0, 0, 0, 0, 1]),
+        ResourceRecord::create_ptr_record("TestMachine".into(), "_test".into(), "_tcp".into()),
+        ResourceRecord::create_srv_record(
+            "TestMachine._test._tcp.local".into(),
+            53000,
+            "TestMachine.local".into(),
+        ),
+        ResourceRecord::create_txt_record(name.clone(), vec!["key=value".into(), "flag".into()]),
+        ResourceRecord::create_nsec_record(name.clone(), vec![QType::A, QType::Aaaa]),
+        ResourceRecord::create_cname_record(Name::new("Alias.local".into()).unwrap(), name.clone()),
+    ];
+
+    let names = [
+        "ARecord",
+        "AAAARecord",
+        "PTRRecord",
+        "SRVRecord",
+        "TXTRecord",
+        "NSECRecord",
+        "CNAMERecord",
+    ];
+
+    for (record, name) in records.iter().zip(names) {
+        let rdata = record.rdata.as_ref().unwrap().to_bytes();
+        let parsed = parse_rdata(record.record_type, &rdata, &rdata).unwrap();
+
+        assert_eq!(parsed.debug_name(), name);
+        assert_eq!(parsed.to_bytes(), rdata);
+    }
+
+    let unknown = parse_rdata(QType::Hinfo, &[1, 2, 3], &[1, 2, 3]).unwrap();
+    assert_eq!(unknown.debug_name(), "UnknownRecord");
+
+    //Malformed RDATA
+    assert!(parse_rdata(QType::A, &[192, 168], &[192, 168]).is_err());
+    assert!(parse_rdata(QType::Txt, &[9, b'k'], &[9, b'k']).is_err());
+
+    //Compressed names point into the rest of the message
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let mut message = vec![0; 12];
+    message.extend(Name::new("TestMachine.local".into()).unwrap().to_bytes());
+    let rdata_start = message.len();
+    message.extend([0, 0, 0, 0, 0xCF, 0x08, 0xC0, 12]);
+
+    let srv = parse_rdata(QType::Srv, &message[rdata_start..], &message).unwrap();
+    assert_eq!(
+        srv.to_bytes(),
+        ResourceRecord::create_srv_record(
+            "TestMachine._test._tcp.local".into(),
+            53000,
+            "TestMachine.local".into()
+        )
+        .rdata
+        .unwrap()
+        .to_bytes()
+    );
+
+    //Parsed messages hold the record structs
+    let announcement = MdnsMessage::announce(&service);
+    let parsed = MdnsMessage::parse(&announcement.to_bytes()).unwrap();
+
+    assert_eq!(
+        parsed.answers[0].rdata.as_ref().unwrap().debug_name(),
+        "PTRRecord"
+    );
+}
+
+#[test]
+fn test_cname_record() {
+    let alias = Name::new("Alias.local".into()).unwrap();
+    let target = Name::new("TestMachine.local".into()).unwrap();
+
+    let cname = ResourceRecord::create_cname_record(alias, target.clone());
+    assert_eq!(cname.record_type, QType::Cname);
+    assert_eq!(cname.rdlength as usize, target.to_bytes().len());
+
+    //Round trip through the wire format
+    let bytes = cname.to_bytes().unwrap();
+    let (parsed, end) = ResourceRecord::parse(&bytes, 0).unwrap();
+    let parsed = parsed.unwrap();
+
+    assert_eq!(end, bytes.len());
+    assert_eq!(parsed.record_type, QType::Cname);
+    assert_eq!(parsed.name.as_str(), "Alias.local");
+    assert_eq!(parsed.rdata.as_ref().unwrap().debug_name(), "CNAMERecord");
+    assert_eq!(parsed.rdata.unwrap().to_bytes(), target.to_bytes());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_record_serde() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let record = ResourceRecord::create_a_record(
+        Name::new("TestMachine.local".into()).unwrap(),
+        [192, 168, 1, 2],
+    );
+    assert_eq!(record.rdata_bytes(), Some(vec![192, 168, 1, 2]));
+
+    //The RDATA is a base64 string
+    let json = serde_json::to_value(&record).unwrap();
+    let rdata = json["rdata"].as_str().unwrap();
+    assert_eq!(STANDARD.decode(rdata).unwrap(), vec![192, 168, 1, 2]);
+
+    let parsed: ResourceRecord = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, record);
+    assert_eq!(parsed.rdata.unwrap().debug_name(), "ARecord");
+}
@@ -1,6 +1,6 @@
 use packed_struct::prelude::*;
 
-use crate::record::RData;
+use crate::{record::RData, MdnsError};
 
 /// A Resource Record
 ///
@@ -17,6 +17,20 @@ pub struct ARecord {
 }
 
 impl RData for ARecord {
+    fn debug_name(&self) -> &'static str {
+        "ARecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], _full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        let ip = rdata.try_into().map_err(|_| MdnsError::InvalidMessage {})?;
+
+        Ok(Box::new(ARecord { ip }))
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         self.pack().expect("Failed to pack A record").into()
     }
@@ -0,0 +1,44 @@
+use packed_struct::prelude::*;
+
+use crate::{record::RData, MdnsError};
+
+/// AAAA Resource Record
+///
+///
+///
+///[3596 Section 2.1 - AAAA Record Format](https://www.rfc-editor.org/rfc/rfc3596#section-2.1)
+#[derive(PackedStruct, Default, Clone, Debug)]
+#[packed_struct(endian = "msb", bit_numbering = "msb0")]
+pub struct AAAARecord {
+    //IP    Ipv6 Address
+    //      Hosts that have multiple internet addresses have multiple AAAA records
+    #[packed_field(bits = "0..=127")]
+    pub ip: [u16; 8],
+}
+
+impl RData for AAAARecord {
+    fn debug_name(&self) -> &'static str {
+        "AAAARecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], _full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        if rdata.len() != 16 {
+            return Err(MdnsError::InvalidMessage {});
+        }
+
+        let mut ip = [0; 8];
+        for (segment, bytes) in ip.iter_mut().zip(rdata.chunks_exact(2)) {
+            *segment = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+
+        Ok(Box::new(AAAARecord { ip }))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.pack().expect("Failed to pack AAAA record").into()
+    }
+}
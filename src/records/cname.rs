@@ -0,0 +1,39 @@
+use crate::{
+    name::Name,
+    record::{parse_rdata_name, RData},
+    MdnsError,
+};
+
+/// CNAME Resource Record
+///
+/// Mdns names should not be aliases, see [RFC6762 Section 2](https://www.rfc-editor.org/rfc/rfc6762#section-2),
+/// but CNAME records may still appear in responses of hybrid unicast and multicast DNS networks,
+/// see [RFC6762 Section 23](https://www.rfc-editor.org/rfc/rfc6762#section-23)
+///
+///[1035 Section 3.3.1 - CNAME RDATA format](https://www.rfc-editor.org/rfc/rfc1035#section-3.3.1)
+#[derive(Default, Clone, Debug)]
+pub struct CNAMERecord {
+    //Target   A <domain-name> which specifies the canonical or primary name for the owner
+    //         The owner name is an alias
+    pub target: Name,
+}
+
+impl RData for CNAMERecord {
+    fn debug_name(&self) -> &'static str {
+        "CNAMERecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        let (target, _) = parse_rdata_name(rdata, full_message, 0)?;
+
+        Ok(Box::new(CNAMERecord { target }))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.target.to_bytes()
+    }
+}
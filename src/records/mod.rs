@@ -1,6 +1,17 @@
 pub mod a;
 pub mod aaaa;
+pub mod cname;
+pub mod nsec;
 pub mod ptr;
 pub mod srv;
 pub mod txt;
+pub mod unknown;
 
+pub use a::ARecord;
+pub use aaaa::AAAARecord;
+pub use cname::CNAMERecord;
+pub use nsec::NSECRecord;
+pub use ptr::PTRRecord;
+pub use srv::SRVRecord;
+pub use txt::TXTRecord;
+pub use unknown::UnknownRecord;
@@ -0,0 +1,131 @@
+use crate::{
+    name::Name,
+    question::QType,
+    record::{parse_rdata_name, RData},
+    MdnsError,
+};
+
+/// NSEC Resource Record
+///
+/// Lists the record types that exist for a name, so queriers know other types do not exist
+///
+/// In Multicast DNS the next domain name is the name of the record itself
+///
+///[4034 Section 4.1 - NSEC RDATA Wire Format](https://www.rfc-editor.org/rfc/rfc4034#section-4.1)
+///[6762 Section 6.1 - Negative Responses](https://www.rfc-editor.org/rfc/rfc6762#section-6.1)
+#[derive(Default, Clone, Debug)]
+pub struct NSECRecord {
+    //Next Domain Name  The name of the record itself in Multicast DNS
+    pub next_domain: Name,
+    //Type Bit Maps     The record types that exist for this name
+    pub types: Vec<QType>,
+}
+
+impl NSECRecord {
+    /// Encode record types in the window based bitmap format
+    ///
+    /// Each window covers 256 types and is encoded as the window number, the bitmap length
+    /// and up to 32 bitmap bytes, where the most significant bit of the first byte is type 0
+    ///
+    ///[4034 Section 4.1.2 - The Type Bit Maps Field](https://www.rfc-editor.org/rfc/rfc4034#section-4.1.2)
+    pub fn bitmap_for_types(types: &[QType]) -> Vec<u8> {
+        let mut windows = [[0u8; 32]; 256];
+
+        for t in types {
+            let t = u16::from(*t);
+            windows[(t >> 8) as usize][(t & 0xFF) as usize / 8] |= 0x80 >> (t % 8);
+        }
+
+        let mut bytes = vec![];
+
+        for (window, bitmap) in windows.iter().enumerate() {
+            //Trailing zero bytes are left out, empty windows are left out completely
+            if let Some(last) = bitmap.iter().rposition(|b| *b != 0) {
+                bytes.push(window as u8);
+                bytes.push(last as u8 + 1);
+                bytes.extend(&bitmap[..=last]);
+            }
+        }
+
+        bytes
+    }
+}
+
+impl NSECRecord {
+    /// Decode record types from the window based bitmap format, unknown types are skipped
+    ///
+    /// See [`NSECRecord::bitmap_for_types()`]
+    pub fn types_from_bitmap(bytes: &[u8]) -> Result<Vec<QType>, MdnsError> {
+        let mut types = vec![];
+        let mut position = 0;
+
+        while position < bytes.len() {
+            let window = bytes[position] as u16;
+            let length = *bytes
+                .get(position + 1)
+                .ok_or(MdnsError::InvalidMessage {})? as usize;
+            let bitmap = bytes
+                .get(position + 2..position + 2 + length)
+                .ok_or(MdnsError::InvalidMessage {})?;
+
+            for (i, byte) in bitmap.iter().enumerate() {
+                for bit in 0..8 {
+                    if byte & (0x80 >> bit) != 0 {
+                        types.extend(QType::try_from(window << 8 | (i * 8 + bit) as u16));
+                    }
+                }
+            }
+
+            position += 2 + length;
+        }
+
+        Ok(types)
+    }
+}
+
+impl RData for NSECRecord {
+    fn debug_name(&self) -> &'static str {
+        "NSECRecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        let (next_domain, end) = parse_rdata_name(rdata, full_message, 0)?;
+        let types = NSECRecord::types_from_bitmap(rdata.get(end..).unwrap_or_default())?;
+
+        Ok(Box::new(NSECRecord { next_domain, types }))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![];
+
+        //Next Domain Name
+        bytes.extend(self.next_domain.to_bytes());
+
+        //Type Bit Maps
+        bytes.extend(NSECRecord::bitmap_for_types(&self.types));
+
+        bytes
+    }
+}
+
+#[test]
+fn test_nsec_bitmap() {
+    //Window 0, 4 bytes, A is bit 1 of byte 0 and AAAA is bit 4 of byte 3
+    assert_eq!(
+        NSECRecord::bitmap_for_types(&[QType::A, QType::Aaaa]),
+        vec![0x00, 0x04, 0x40, 0x00, 0x00, 0x08]
+    );
+
+    //Trailing zero bytes are left out
+    assert_eq!(
+        NSECRecord::bitmap_for_types(&[QType::Txt, QType::Srv]),
+        vec![0x00, 0x05, 0x00, 0x00, 0x80, 0x00, 0x40]
+    );
+
+    //No types, no windows
+    assert!(NSECRecord::bitmap_for_types(&[]).is_empty());
+}
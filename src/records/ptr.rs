@@ -1,4 +1,8 @@
-use crate::{name::Name, record::RData};
+use crate::{
+    name::Name,
+    record::{parse_rdata_name, RData},
+    MdnsError,
+};
 
 /// PTR Resource Record
 ///
@@ -13,6 +17,20 @@ pub struct PTRRecord {
 }
 
 impl RData for PTRRecord {
+    fn debug_name(&self) -> &'static str {
+        "PTRRecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        let (name, _) = parse_rdata_name(rdata, full_message, 0)?;
+
+        Ok(Box::new(PTRRecord { name }))
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = vec![];
 
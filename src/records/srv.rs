@@ -1,4 +1,8 @@
-use crate::{name::Name, record::RData};
+use crate::{
+    name::Name,
+    record::{parse_rdata_name, RData},
+    MdnsError,
+};
 /// SRV Resource Record
 ///
 ///
@@ -21,6 +25,26 @@ pub struct SRVRecord {
 }
 
 impl RData for SRVRecord {
+    fn debug_name(&self) -> &'static str {
+        "SRVRecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        let fields = rdata.get(..6).ok_or(MdnsError::InvalidMessage {})?;
+        let (target, _) = parse_rdata_name(rdata, full_message, 6)?;
+
+        Ok(Box::new(SRVRecord {
+            priority: u16::from_be_bytes([fields[0], fields[1]]),
+            weight: u16::from_be_bytes([fields[2], fields[3]]),
+            port: u16::from_be_bytes([fields[4], fields[5]]),
+            target,
+        }))
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = vec![];
 
@@ -1,4 +1,4 @@
-use crate::record::RData;
+use crate::{record::RData, MdnsError};
 /// TXT Resource Record
 ///
 ///
@@ -25,9 +25,39 @@ impl TXTRecord{
         Ok(TXTRecord{txt_record})
 
     }
+
+    /// Parse the length prefixed strings of the RDATA of a received TXT record
+    pub fn from_rdata(rdata: &[u8]) -> Result<Self, MdnsError> {
+        let mut txt_record = vec![];
+        let mut position = 0;
+
+        //Each string is prepended with a byte indicating the length
+        while let Some(length) = rdata.get(position) {
+            let txt = rdata
+                .get(position + 1..position + 1 + *length as usize)
+                .ok_or(MdnsError::InvalidMessage {})?;
+
+            txt_record.push(String::from_utf8_lossy(txt).into_owned());
+            position += 1 + txt.len();
+        }
+
+        Ok(TXTRecord { txt_record })
+    }
 }
 
 impl RData for TXTRecord {
+    fn debug_name(&self) -> &'static str {
+        "TXTRecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], _full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        Ok(Box::new(TXTRecord::from_rdata(rdata)?))
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         //Prepend each string byte array with a byte indicating the length
         let mut result = vec![];
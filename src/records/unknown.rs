@@ -0,0 +1,32 @@
+use crate::{record::RData, MdnsError};
+
+/// Unknown Resource Record
+///
+/// Holds the raw RDATA of a received record
+///
+/// Used for record types which are not (yet) parsed into a specific record struct
+#[derive(Default, Clone, Debug)]
+pub struct UnknownRecord {
+    //DATA     Raw RDATA octets as received
+    pub data: Vec<u8>,
+}
+
+impl RData for UnknownRecord {
+    fn debug_name(&self) -> &'static str {
+        "UnknownRecord"
+    }
+
+    fn clone_box(&self) -> Box<dyn RData + Send> {
+        Box::new(self.clone())
+    }
+
+    fn parse(rdata: &[u8], _full_message: &[u8]) -> Result<Box<dyn RData + Send>, MdnsError> {
+        Ok(Box::new(UnknownRecord {
+            data: rdata.to_vec(),
+        }))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
@@ -0,0 +1,475 @@
+use std::marker::PhantomData;
+
+use tokio::time::Instant;
+
+use crate::{
+    message::MdnsMessage,
+    service::{Service, ServiceState},
+};
+
+/// Registration State
+///
+/// Marker for the state a [`ServiceRegistration`] is in, mirrors [`ServiceState`]
+pub trait RegistrationState {
+    /// The matching [`ServiceState`]
+    const STATE: ServiceState;
+}
+
+/// State upon creation
+#[derive(Debug)]
+pub struct Prelude;
+/// First random timeout sent
+#[derive(Debug)]
+pub struct WaitForFirstProbe;
+/// First timeout finished
+#[derive(Debug)]
+pub struct FirstProbe;
+/// Query and second timeout sent
+#[derive(Debug)]
+pub struct WaitForSecondProbe;
+/// Second timeout finished
+#[derive(Debug)]
+pub struct SecondProbe;
+/// Probing finished waiting to be announced
+#[derive(Debug)]
+pub struct WaitForAnnouncing;
+/// Ready to announce
+#[derive(Debug)]
+pub struct FirstAnnouncement;
+/// First announcement and timeout sent
+#[derive(Debug)]
+pub struct WaitForSecondAnnouncement;
+/// Timeout finished, sending second announcement
+#[derive(Debug)]
+pub struct SecondAnnouncement;
+/// Queried while announcing or registered, announcing after a short random delay
+#[derive(Debug)]
+pub struct EarlyAnnounce;
+/// Final state
+#[derive(Debug)]
+pub struct Registered;
+
+impl RegistrationState for Prelude {
+    const STATE: ServiceState = ServiceState::Prelude;
+}
+impl RegistrationState for WaitForFirstProbe {
+    const STATE: ServiceState = ServiceState::WaitForFirstProbe;
+}
+impl RegistrationState for FirstProbe {
+    const STATE: ServiceState = ServiceState::FirstProbe;
+}
+impl RegistrationState for WaitForSecondProbe {
+    const STATE: ServiceState = ServiceState::WaitForSecondProbe;
+}
+impl RegistrationState for SecondProbe {
+    const STATE: ServiceState = ServiceState::SecondProbe;
+}
+impl RegistrationState for WaitForAnnouncing {
+    const STATE: ServiceState = ServiceState::WaitForAnnouncing;
+}
+impl RegistrationState for FirstAnnouncement {
+    const STATE: ServiceState = ServiceState::FirstAnnouncement;
+}
+impl RegistrationState for WaitForSecondAnnouncement {
+    const STATE: ServiceState = ServiceState::WaitForSecondAnnouncement;
+}
+impl RegistrationState for SecondAnnouncement {
+    const STATE: ServiceState = ServiceState::SecondAnnouncement;
+}
+impl RegistrationState for EarlyAnnounce {
+    const STATE: ServiceState = ServiceState::EarlyAnnounce;
+}
+impl RegistrationState for Registered {
+    const STATE: ServiceState = ServiceState::Registered;
+}
+
+/// Marker for the states in which a [`ServiceRegistration`] is probing and can lose a simultaneous probe tiebreak
+pub trait Probing: RegistrationState {}
+
+impl Probing for WaitForFirstProbe {}
+impl Probing for FirstProbe {}
+impl Probing for WaitForSecondProbe {}
+impl Probing for SecondProbe {}
+impl Probing for WaitForAnnouncing {}
+
+/// Service Registration
+///
+/// A [`Service`] moving through probing and announcing, with the state encoded in its type
+///
+/// Only the transitions allowed by the protocol exist, so an invalid transition is a compile error
+/// instead of a state silently ignored by a catch-all match arm.
+/// Each transition keeps [`Service::state`] in sync, so the timeouts of the chain can still be keyed by [`ServiceState`]
+///
+/// ## RFC Reference
+/// - [RFC6762 Section 8.1 - Probing](https://www.rfc-editor.org/rfc/rfc6762#section-8.1)
+/// - [RFC6762 Section 8.3 - Announcing](https://www.rfc-editor.org/rfc/rfc6762#section-8.3)
+///
+/// ## Example
+///
+/// A service must be probed before it can be announced
+///
+/// ```compile_fail
+/// use dns_sd2::{registration::ServiceRegistration, service::Service};
+///
+/// let registration = ServiceRegistration::new(Service::default());
+///
+/// registration.announce(1000);
+/// ```
+#[derive(Debug)]
+pub struct ServiceRegistration<S: RegistrationState> {
+    service: Service,
+    timeout: Option<u64>,
+    state: PhantomData<S>,
+}
+
+impl<S: RegistrationState> ServiceRegistration<S> {
+    /// The [`Service`] being registered
+    pub fn service(&self) -> &Service {
+        &self.service
+    }
+
+    /// The [`ServiceState`] matching this registration
+    pub fn state(&self) -> ServiceState {
+        S::STATE
+    }
+
+    /// The timeout in ms set by the last transition, if any
+    pub fn timeout(&self) -> Option<u64> {
+        self.timeout
+    }
+
+    /// The timeout set by the last transition keyed by its [`ServiceState`], as added to the timeouts of the chain
+    pub fn pending_timeout(&self) -> Option<(ServiceState, u64)> {
+        self.timeout.map(|t| (S::STATE, t))
+    }
+
+    /// Take the [`Service`] out of the registration
+    pub fn into_service(self) -> Service {
+        self.service
+    }
+
+    /// Continue a registration of a [`Service`] which is in state `S`
+    fn resume(service: Service) -> Self {
+        ServiceRegistration {
+            service,
+            timeout: None,
+            state: PhantomData,
+        }
+    }
+
+    fn transition<T: RegistrationState>(mut self, timeout: Option<u64>) -> ServiceRegistration<T> {
+        self.service.state = T::STATE;
+
+        ServiceRegistration {
+            service: self.service,
+            timeout,
+            state: PhantomData,
+        }
+    }
+}
+
+impl ServiceRegistration<Prelude> {
+    /// Start registering a [`Service`]
+    pub fn new(mut service: Service) -> Self {
+        service.state = ServiceState::Prelude;
+
+        ServiceRegistration {
+            service,
+            timeout: None,
+            state: PhantomData,
+        }
+    }
+
+    /// Wait a random 0-250ms before the first probe
+    pub fn start_probing(self, timeout_ms: u64) -> ServiceRegistration<WaitForFirstProbe> {
+        self.transition(Some(timeout_ms))
+    }
+}
+
+impl<S: Probing> ServiceRegistration<S> {
+    /// A simultaneous probe won the tiebreak, wait before probing again
+    ///
+    /// [RFC6762 Section 8.2 - Simultaneous Probe Tiebreaking](https://www.rfc-editor.org/rfc/rfc6762#section-8.2)
+    pub fn restart_probing(self, timeout_ms: u64) -> ServiceRegistration<WaitForFirstProbe> {
+        self.transition(Some(timeout_ms))
+    }
+}
+
+impl ServiceRegistration<WaitForFirstProbe> {
+    /// The timeout before the first probe has finished
+    pub fn elapsed(self) -> ServiceRegistration<FirstProbe> {
+        self.transition(None)
+    }
+}
+
+impl ServiceRegistration<FirstProbe> {
+    /// Create the first probe query and wait before the second
+    pub fn probe(self, timeout_ms: u64) -> (ServiceRegistration<WaitForSecondProbe>, MdnsMessage) {
+        let message = MdnsMessage::probe(&self.service);
+        (self.transition(Some(timeout_ms)), message)
+    }
+}
+
+impl ServiceRegistration<WaitForSecondProbe> {
+    /// The timeout before the second probe has finished
+    pub fn elapsed(self) -> ServiceRegistration<SecondProbe> {
+        self.transition(None)
+    }
+}
+
+impl ServiceRegistration<SecondProbe> {
+    /// Create the second probe query and wait before announcing
+    pub fn probe(self, timeout_ms: u64) -> (ServiceRegistration<WaitForAnnouncing>, MdnsMessage) {
+        let message = MdnsMessage::probe(&self.service);
+        (self.transition(Some(timeout_ms)), message)
+    }
+}
+
+impl ServiceRegistration<WaitForAnnouncing> {
+    /// The timeout before announcing has finished
+    pub fn elapsed(self) -> ServiceRegistration<FirstAnnouncement> {
+        self.transition(None)
+    }
+}
+
+impl ServiceRegistration<FirstAnnouncement> {
+    /// Create the first announcement and wait before the second
+    pub fn announce(
+        mut self,
+        timeout_ms: u64,
+    ) -> (ServiceRegistration<WaitForSecondAnnouncement>, MdnsMessage) {
+        let message = MdnsMessage::announce(&self.service);
+        self.service.announced_at = Some(Instant::now());
+        (self.transition(Some(timeout_ms)), message)
+    }
+}
+
+impl ServiceRegistration<WaitForSecondAnnouncement> {
+    /// The timeout before the second announcement has finished
+    pub fn elapsed(self) -> ServiceRegistration<SecondAnnouncement> {
+        self.transition(None)
+    }
+
+    /// Queried for our name, send the second announcement after `timeout_ms` instead
+    pub fn announce_early(self, timeout_ms: u64) -> ServiceRegistration<EarlyAnnounce> {
+        self.transition(Some(timeout_ms))
+    }
+}
+
+impl ServiceRegistration<EarlyAnnounce> {
+    /// The delay before announcing has finished
+    pub fn elapsed(self) -> ServiceRegistration<SecondAnnouncement> {
+        self.transition(None)
+    }
+}
+
+impl ServiceRegistration<SecondAnnouncement> {
+    /// Create the second announcement, the service is registered
+    ///
+    /// Announcing again after a query keeps the original registration time
+    pub fn announce(mut self) -> (ServiceRegistration<Registered>, MdnsMessage) {
+        let message = MdnsMessage::announce(&self.service);
+        self.service.announced_at = Some(Instant::now());
+        self.service.registered_at.get_or_insert_with(Instant::now);
+        (self.transition(None), message)
+    }
+}
+
+impl ServiceRegistration<Registered> {
+    /// Queried for our service, announce again after `timeout_ms`
+    pub fn announce_again(self, timeout_ms: u64) -> ServiceRegistration<EarlyAnnounce> {
+        self.transition(Some(timeout_ms))
+    }
+
+    /// Create the goodbye packet and end the registration
+    pub fn goodbye(self) -> (Service, MdnsMessage) {
+        let message = MdnsMessage::goodbye(&self.service);
+        (self.service, message)
+    }
+}
+
+/// Registration
+///
+/// A [`ServiceRegistration`] in any state, for storing it as a single field
+///
+/// The [`ProbeHandler`](crate::protocols::probe::ProbeHandler) and [`AnnouncementHandler`](crate::protocols::announce::AnnouncementHandler)
+/// move the registered [`Service`]s of the chain through their transitions with [`Registration::update()`]
+#[derive(Debug)]
+pub enum Registration {
+    Prelude(ServiceRegistration<Prelude>),
+    WaitForFirstProbe(ServiceRegistration<WaitForFirstProbe>),
+    FirstProbe(ServiceRegistration<FirstProbe>),
+    WaitForSecondProbe(ServiceRegistration<WaitForSecondProbe>),
+    SecondProbe(ServiceRegistration<SecondProbe>),
+    WaitForAnnouncing(ServiceRegistration<WaitForAnnouncing>),
+    FirstAnnouncement(ServiceRegistration<FirstAnnouncement>),
+    WaitForSecondAnnouncement(ServiceRegistration<WaitForSecondAnnouncement>),
+    SecondAnnouncement(ServiceRegistration<SecondAnnouncement>),
+    EarlyAnnounce(ServiceRegistration<EarlyAnnounce>),
+    Registered(ServiceRegistration<Registered>),
+}
+
+/// Apply `$f` to the [`ServiceRegistration`] of any variant of a [`Registration`]
+macro_rules! each_registration {
+    ($registration:expr, $r:ident => $f:expr) => {
+        match $registration {
+            Registration::Prelude($r) => $f,
+            Registration::WaitForFirstProbe($r) => $f,
+            Registration::FirstProbe($r) => $f,
+            Registration::WaitForSecondProbe($r) => $f,
+            Registration::SecondProbe($r) => $f,
+            Registration::WaitForAnnouncing($r) => $f,
+            Registration::FirstAnnouncement($r) => $f,
+            Registration::WaitForSecondAnnouncement($r) => $f,
+            Registration::SecondAnnouncement($r) => $f,
+            Registration::EarlyAnnounce($r) => $f,
+            Registration::Registered($r) => $f,
+        }
+    };
+}
+
+impl Registration {
+    /// The [`Service`] being registered
+    pub fn service(&self) -> &Service {
+        each_registration!(self, r => r.service())
+    }
+
+    /// The [`ServiceState`] of the registration
+    pub fn state(&self) -> ServiceState {
+        self.service().state
+    }
+
+    /// The timeout set by the last transition, see [`ServiceRegistration::pending_timeout()`]
+    pub fn pending_timeout(&self) -> Option<(ServiceState, u64)> {
+        each_registration!(self, r => r.pending_timeout())
+    }
+
+    /// Take the [`Service`] out of the registration
+    pub fn into_service(self) -> Service {
+        each_registration!(self, r => r.into_service())
+    }
+
+    /// Wait before probing again if the registration is probing, see [`ServiceRegistration::restart_probing()`]
+    pub fn restart_probing(self, timeout_ms: u64) -> Registration {
+        match self {
+            Registration::WaitForFirstProbe(r) => {
+                Registration::WaitForFirstProbe(r.restart_probing(timeout_ms))
+            }
+            Registration::FirstProbe(r) => {
+                Registration::WaitForFirstProbe(r.restart_probing(timeout_ms))
+            }
+            Registration::WaitForSecondProbe(r) => {
+                Registration::WaitForFirstProbe(r.restart_probing(timeout_ms))
+            }
+            Registration::SecondProbe(r) => {
+                Registration::WaitForFirstProbe(r.restart_probing(timeout_ms))
+            }
+            Registration::WaitForAnnouncing(r) => {
+                Registration::WaitForFirstProbe(r.restart_probing(timeout_ms))
+            }
+            registration => registration,
+        }
+    }
+
+    /// Move `service` through a `transition` in place
+    ///
+    /// Services outside of probing and announcing, e.g. [`ServiceState::Paused`], are left unchanged
+    pub fn update(service: &mut Service, transition: impl FnOnce(Registration) -> Registration) {
+        *service = match Registration::try_from(std::mem::take(service)) {
+            Ok(registration) => transition(registration).into_service(),
+            Err(service) => service,
+        };
+    }
+}
+
+impl TryFrom<Service> for Registration {
+    type Error = Service;
+
+    /// The registration of a [`Service`] in its current [`ServiceState`]
+    ///
+    /// Returns the service if its state is not part of probing and announcing
+    fn try_from(service: Service) -> Result<Self, Self::Error> {
+        Ok(match service.state {
+            ServiceState::Prelude => Registration::Prelude(ServiceRegistration::resume(service)),
+            ServiceState::WaitForFirstProbe => {
+                Registration::WaitForFirstProbe(ServiceRegistration::resume(service))
+            }
+            ServiceState::FirstProbe => {
+                Registration::FirstProbe(ServiceRegistration::resume(service))
+            }
+            ServiceState::WaitForSecondProbe => {
+                Registration::WaitForSecondProbe(ServiceRegistration::resume(service))
+            }
+            ServiceState::SecondProbe => {
+                Registration::SecondProbe(ServiceRegistration::resume(service))
+            }
+            ServiceState::WaitForAnnouncing => {
+                Registration::WaitForAnnouncing(ServiceRegistration::resume(service))
+            }
+            ServiceState::FirstAnnouncement => {
+                Registration::FirstAnnouncement(ServiceRegistration::resume(service))
+            }
+            ServiceState::WaitForSecondAnnouncement => {
+                Registration::WaitForSecondAnnouncement(ServiceRegistration::resume(service))
+            }
+            ServiceState::SecondAnnouncement => {
+                Registration::SecondAnnouncement(ServiceRegistration::resume(service))
+            }
+            ServiceState::EarlyAnnounce => {
+                Registration::EarlyAnnounce(ServiceRegistration::resume(service))
+            }
+            ServiceState::Registered => {
+                Registration::Registered(ServiceRegistration::resume(service))
+            }
+            _ => return Err(service),
+        })
+    }
+}
+
+#[test]
+fn test_service_registration() {
+    use crate::service::ServiceBuilder;
+
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let registration = ServiceRegistration::new(service).start_probing(100);
+    assert_eq!(registration.state(), ServiceState::WaitForFirstProbe);
+    assert_eq!(registration.timeout(), Some(100));
+
+    let (registration, probe) = registration.elapsed().probe(250);
+    assert_eq!(probe.header.nscount as usize, probe.authorities.len());
+
+    let (registration, _) = registration.elapsed().probe(250);
+    let (registration, announcement) = registration.elapsed().announce(1000);
+    assert!(announcement.header.qr);
+    assert_eq!(registration.timeout(), Some(1000));
+
+    let (registration, _) = registration.elapsed().announce();
+    assert_eq!(registration.service().state, ServiceState::Registered);
+
+    let registration = registration.announce_again(100);
+    assert_eq!(
+        registration.pending_timeout(),
+        Some((ServiceState::EarlyAnnounce, 100))
+    );
+
+    let (registration, _) = registration.elapsed().announce();
+    let registration = Registration::Registered(registration);
+    assert_eq!(registration.state(), ServiceState::Registered);
+
+    //Services are updated in place, states outside the lifecycle are left unchanged
+    let mut service = registration.into_service();
+    service.state = ServiceState::SecondProbe;
+    Registration::update(&mut service, |r| r.restart_probing(1000));
+    assert_eq!(service.state, ServiceState::WaitForFirstProbe);
+
+    service.state = ServiceState::Paused;
+    Registration::update(&mut service, |r| r.restart_probing(1000));
+    assert_eq!(service.state, ServiceState::Paused);
+}
@@ -1,14 +1,43 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::{Duration, SystemTime},
+};
+
+use tokio::{net::TcpStream, time::Instant};
+
+use crate::{
+    name::Name,
+    utility::{
+        enumerate_network_interfaces, is_reachable_ipv4, is_reachable_ipv6, local_ipv4_addresses,
+        local_ipv6_addresses, NetworkInterface,
+    },
+    MdnsError,
+};
+
+/// Prefix length assumed for the Ipv6 addresses of the network interfaces, which are listed without their prefix
+const IPV6_PREFIX: Ipv6Addr = Ipv6Addr::new(0xffff, 0xffff, 0xffff, 0xffff, 0, 0, 0, 0);
+
 /// A Service is created by calling [`register()`]
 ///
 /// Upon creation, the probing and announcing process is initiated by the
 ///
 /// chain of handlers
 ///
+/// Use [`ServiceBuilder`] to construct a valid Service
+///
 /// Attribute | Value | Explanation
 /// :-- |:-- |:--
 /// Name | String | Service Name
+/// Target | Option<String> | Host name from the SRV record of a browsed Service
+/// Addresses | Vec<IpAddr> | Addresses of the target of a browsed Service
 /// Txt Records | Vec<String> | Txt Records in the format of `key=value`
+/// Subtypes | Vec<String> | Subtypes the service can also be found by
 /// State | [`ServiceState`] | State of the Service
+/// Registered At | Option<Instant> | When the Service became [`ServiceState::Registered`]
+/// Probe Conflicts | u32 | Conflicts found while probing the Service
+/// Announced At | Option<Instant> | When the Service was last announced
+/// Queried Type | Option<String> | Service type of a query waiting for our delayed PTR response
 
 #[derive(Debug, Default, Clone)]
 pub struct Service {
@@ -20,12 +49,362 @@ pub struct Service {
     pub protocol: String,
     /// Port name (e.g. 53000)
     pub port: u16,
+    /// Host name from the SRV record of a browsed service (e.g. 'MyMachine.local')
+    ///
+    /// Can differ from the host of the instance name, `None` for our registrations
+    pub target: Option<String>,
+    /// Addresses from the A and AAAA records of the target of a browsed service
+    pub addresses: Vec<IpAddr>,
     /// TXT Records (in format of "key=value")
     pub txt_records: Vec<String>,
+    /// Subtypes (e.g. "_printer")
+    ///
+    /// Allows browsing for a subset of services of the same type
+    /// [RFC6763 Section 7.1 - Selective Instance Enumeration (Subtypes)](https://www.rfc-editor.org/rfc/rfc6763#section-7.1)
+    pub subtypes: Vec<String>,
     /// Current State
     ///
     /// See [`ServiceState`]
     pub state: ServiceState,
+    /// When the announcements finished and the state became [`ServiceState::Registered`]
+    pub registered_at: Option<Instant>,
+    /// Conflicts found while probing, see [`crate::protocols::probe::ProbeHandler`]
+    pub probe_conflicts: u32,
+    /// When the last announcement of this service was sent, see [`crate::protocols::announce::AnnouncementHandler`]
+    pub announced_at: Option<Instant>,
+    /// Service type of a query waiting for our delayed PTR response, see [`crate::protocols::query_handler::QueryHandler`]
+    ///
+    /// The delay is a single timeout shared by all registrations, only the queried ones respond when it fires
+    pub queried_type: Option<String>,
+}
+
+impl Service {
+    /// Time since this service was registered, `None` unless it is [`ServiceState::Registered`]
+    pub fn elapsed_since_registered(&self) -> Option<Duration> {
+        match self.state {
+            ServiceState::Registered => self.registered_at.map(|t| t.elapsed()),
+            _ => None,
+        }
+    }
+
+    /// The first non-loopback Ipv4 address of this machine, see [`local_ipv4_addresses()`]
+    pub fn local_ipv4_address(&self) -> Option<Ipv4Addr> {
+        local_ipv4_addresses().first().copied()
+    }
+
+    /// The first non-loopback Ipv6 address of this machine, see [`local_ipv6_addresses()`]
+    pub fn local_ipv6_address(&self) -> Option<Ipv6Addr> {
+        local_ipv6_addresses().first().copied()
+    }
+
+    /// Instance name in DNS-SD format (e.g. 'MyMachine._http._tcp.local')
+    ///
+    /// [RFC6763 Section 4.1 - Structured Service Instance Names](https://www.rfc-editor.org/rfc/rfc6763#section-4.1)
+    pub fn to_dns_sd_string(&self) -> String {
+        format!("{}.{}.{}.local", self.host, self.service, self.protocol)
+    }
+
+    /// Service type browsed for to find this service (e.g. '_http._tcp.local')
+    pub fn service_type(&self) -> String {
+        format!("{}.{}.local", self.service, self.protocol)
+    }
+
+    /// Split an instance name in DNS-SD format into its host, service and protocol
+    ///
+    /// The host may contain dots, e.g. 'printer.office._ipp._tcp.local' has host 'printer.office'
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use dns_sd2::service::Service;
+    ///
+    /// let (host, service, protocol) = Service::parse_dns_sd("MyPrinter._ipp._tcp.local").unwrap();
+    ///
+    /// assert_eq!((host.as_str(), service.as_str(), protocol.as_str()), ("MyPrinter", "_ipp", "_tcp"));
+    /// ```
+    pub fn parse_dns_sd(s: &str) -> Result<(String, String, String), String> {
+        let name = s.strip_suffix('.').unwrap_or(s);
+
+        let instance = name
+            .len()
+            .checked_sub(".local".len())
+            .filter(|&i| name.is_char_boundary(i) && name[i..].eq_ignore_ascii_case(".local"))
+            .map(|i| &name[..i])
+            .ok_or(format!("{} should end with '.local'", s))?;
+
+        let (rest, protocol) = instance
+            .rsplit_once('.')
+            .ok_or(format!("{} has no protocol", s))?;
+        let (host, service) = rest
+            .rsplit_once('.')
+            .ok_or(format!("{} has no service", s))?;
+
+        if !protocol.eq_ignore_ascii_case("_tcp") && !protocol.eq_ignore_ascii_case("_udp") {
+            return Err(format!("Protocol {} should be '_tcp' or '_udp'", protocol));
+        }
+
+        if !service.starts_with('_') {
+            return Err(format!("Service {} should start with '_'", service));
+        }
+
+        if host.is_empty() {
+            return Err(format!("{} has no host", s));
+        }
+
+        Ok((host.into(), service.into(), protocol.into()))
+    }
+
+    /// Instance name without the '.local' suffix for display (e.g. 'MyMachine._http._tcp')
+    pub fn display_name(&self) -> String {
+        let instance = self.to_dns_sd_string();
+
+        Name::new(instance.clone())
+            .ok()
+            .and_then(|name| name.relative_to(".local"))
+            .unwrap_or(instance)
+    }
+}
+
+/// A [`Service`] found on the network along with the addresses of its host
+///
+/// Attribute | Value | Explanation
+/// :-- |:-- |:--
+/// Service | [`Service`] | The found service, contains the port and protocol
+/// Ipv4 | Ipv4Addr | Address from the A record of the host
+/// Ipv6 | Option<Ipv6Addr> | Address from the AAAA record of the host, if any
+/// Registered At | SystemTime | When the service was registered, or resolved if unknown
+#[derive(Debug, Clone)]
+pub struct ResolvedService {
+    pub service: Service,
+    pub ip_v4: Ipv4Addr,
+    pub ip_v6: Option<Ipv6Addr>,
+    pub registered_at: SystemTime,
+}
+
+impl ResolvedService {
+    /// Address of the service, prefers the Ipv6 address if available
+    pub fn socket_addr(&self) -> SocketAddr {
+        let ip = match self.ip_v6 {
+            Some(ip) => IpAddr::V6(ip),
+            None => IpAddr::V4(self.ip_v4),
+        };
+
+        SocketAddr::new(ip, self.service.port)
+    }
+
+    /// Connect to a `_tcp` service
+    ///
+    /// Returns an [`io::ErrorKind::InvalidInput`] error for other protocols
+    pub async fn connect(&self) -> io::Result<TcpStream> {
+        self.expect_protocol("_tcp")?;
+
+        TcpStream::connect(self.socket_addr()).await
+    }
+
+    /// Bind a local UDP socket connected to a `_udp` service
+    ///
+    /// Returns an [`io::ErrorKind::InvalidInput`] error for other protocols
+    pub fn bind_udp(&self) -> io::Result<UdpSocket> {
+        self.expect_protocol("_udp")?;
+
+        let address = self.socket_addr();
+        let local: IpAddr = match address {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+
+        let socket = UdpSocket::bind((local, 0))?;
+        socket.connect(address)?;
+
+        Ok(socket)
+    }
+
+    /// Whether the Ipv4 address of the service is in the subnet of one of the network interfaces of this machine
+    ///
+    /// Returns [`MdnsError::NotReachable`] if no interface can reach it, see [`is_reachable_ipv4()`]
+    pub fn verify_reachability(&self) -> Result<(), MdnsError> {
+        self.reachable_ipv4_from(&enumerate_network_interfaces())
+    }
+
+    /// Whether the Ipv6 address of the service is in the /64 prefix of one of the network interfaces of this machine
+    ///
+    /// Returns [`MdnsError::NotReachable`] without an Ipv6 address or if no interface can reach it, see [`is_reachable_ipv6()`]
+    pub fn verify_reachability_ipv6(&self) -> Result<(), MdnsError> {
+        self.reachable_ipv6_from(&enumerate_network_interfaces())
+    }
+
+    fn reachable_ipv4_from(&self, interfaces: &[NetworkInterface]) -> Result<(), MdnsError> {
+        interfaces
+            .iter()
+            .filter_map(|i| i.ipv4)
+            .any(|(ip, subnet)| is_reachable_ipv4(&ip, &subnet, &self.ip_v4))
+            .then_some(())
+            .ok_or(MdnsError::NotReachable {})
+    }
+
+    fn reachable_ipv6_from(&self, interfaces: &[NetworkInterface]) -> Result<(), MdnsError> {
+        let ip_v6 = self.ip_v6.ok_or(MdnsError::NotReachable {})?;
+
+        interfaces
+            .iter()
+            .filter_map(|i| i.ipv6)
+            .any(|ip| is_reachable_ipv6(&ip, &IPV6_PREFIX, &ip_v6))
+            .then_some(())
+            .ok_or(MdnsError::NotReachable {})
+    }
+
+    fn expect_protocol(&self, protocol: &str) -> io::Result<()> {
+        if self.service.protocol.eq_ignore_ascii_case(protocol) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Service protocol is {}, not {}",
+                    self.service.protocol, protocol
+                ),
+            ))
+        }
+    }
+}
+
+/// Builder for a [`Service`]
+///
+/// Host, service, protocol and port are required, TXT records and subtypes are optional
+///
+/// The built service starts in `ServiceState::Prelude`
+///
+/// ## Example
+///
+/// ```
+/// use dns_sd2::service::ServiceBuilder;
+///
+/// let service = ServiceBuilder::default()
+///     .host("MyMachine")
+///     .service("_http")
+///     .protocol("_tcp")
+///     .port(8080)
+///     .txt("path", "/index.html")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(service.txt_records, vec!["path=/index.html".to_string()]);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ServiceBuilder {
+    host: Option<String>,
+    service: Option<String>,
+    protocol: Option<String>,
+    port: Option<u16>,
+    txt_records: Vec<String>,
+    subtypes: Vec<String>,
+}
+
+impl ServiceBuilder {
+    /// Host name (e.g. 'MyMachine')
+    pub fn host(mut self, h: &str) -> Self {
+        self.host = Some(h.into());
+        self
+    }
+
+    /// Service name (e.g. "_scanner")
+    pub fn service(mut self, s: &str) -> Self {
+        self.service = Some(s.into());
+        self
+    }
+
+    /// Protocol name, either "_tcp" or "_udp"
+    pub fn protocol(mut self, p: &str) -> Self {
+        self.protocol = Some(p.into());
+        self
+    }
+
+    /// Port (e.g. 53000)
+    pub fn port(mut self, p: u16) -> Self {
+        self.port = Some(p);
+        self
+    }
+
+    /// Add a TXT record in the format of `key=value`
+    pub fn txt(mut self, key: &str, value: &str) -> Self {
+        self.txt_records.push(format!("{}={}", key, value));
+        self
+    }
+
+    /// Add a boolean TXT record which only consists of a key
+    ///
+    /// [RFC6763 Section 6.4 - Rules for Keys in DNS-SD Key/Value Pairs](https://www.rfc-editor.org/rfc/rfc6763#section-6.4)
+    pub fn txt_flag(mut self, key: &str) -> Self {
+        self.txt_records.push(key.into());
+        self
+    }
+
+    /// Add a subtype (e.g. "_printer")
+    pub fn subtype(mut self, s: &str) -> Self {
+        self.subtypes.push(s.into());
+        self
+    }
+
+    /// Build the [`Service`]
+    ///
+    /// Returns an error if a required field is missing, the service name is invalid,
+    /// a subtype does not start with `_` or the protocol is not `_tcp` or `_udp`
+    ///
+    /// A valid service name starts with `_` followed by 1-15 letters, digits and hyphens,
+    /// without a hyphen at the start or end
+    ///
+    /// [RFC6763 Section 7 - Service Names](https://www.rfc-editor.org/rfc/rfc6763#section-7)
+    pub fn build(self) -> Result<Service, String> {
+        let host = self.host.ok_or("Host is required")?;
+        let service = self.service.ok_or("Service is required")?;
+        let protocol = self.protocol.ok_or("Protocol is required")?;
+        let port = self.port.ok_or("Port is required")?;
+
+        let label = service
+            .strip_prefix('_')
+            .ok_or(format!("Service {} should start with '_'", service))?;
+
+        if label.is_empty() || label.len() > 15 {
+            return Err(format!(
+                "Service {} should be 1 to 15 characters long, excluding '_'",
+                service
+            ));
+        }
+
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            || label.starts_with('-')
+            || label.ends_with('-')
+        {
+            return Err(format!(
+                "Service {} should only contain letters, digits and hyphens",
+                service
+            ));
+        }
+
+        if protocol != "_tcp" && protocol != "_udp" {
+            return Err(format!("Protocol {} should be '_tcp' or '_udp'", protocol));
+        }
+
+        if let Some(s) = self.subtypes.iter().find(|s| !s.starts_with('_')) {
+            return Err(format!("Subtype {} should start with '_'", s));
+        }
+
+        Ok(Service {
+            host,
+            service,
+            protocol,
+            port,
+            target: None,
+            addresses: vec![],
+            txt_records: self.txt_records,
+            subtypes: self.subtypes,
+            state: ServiceState::Prelude,
+            registered_at: None,
+            probe_conflicts: 0,
+            announced_at: None,
+            queried_type: None,
+        })
+    }
 }
 
 /// A Query is created by calling [`browse()`]
@@ -38,6 +417,10 @@ pub struct Service {
 /// :-- |:-- |:--
 /// Name | String | Service Name
 /// Timeout | u64 | Timeout until the next query
+/// State | [`BrowseState`] | Progress of the query
+/// Resolved | Vec<String> | Instance names of the services found so far
+/// Initial Delay | u64 | Delay in ms before the first query is sent
+/// Interval | u64 | Interval in ms between continuous queries
 #[derive(Debug, Default)]
 pub struct Query {
     /// Name of the servide we are querying for
@@ -48,6 +431,149 @@ pub struct Query {
     /// When a service is completely resolved (IP and TXT records found)
     /// The service is returned as the next Stream item
     pub services: Vec<Service>,
+    /// Filter for the TXT records of resolved services
+    pub filter: BrowseFilter,
+    /// Current State
+    ///
+    /// See [`BrowseState`]
+    pub state: BrowseState,
+    /// Number of services found since the query started
+    pub found: usize,
+    /// Instance names of the services found so far, each service is only returned once
+    pub resolved: Vec<String>,
+    /// Delay in ms before the first query is sent
+    pub initial_delay: u64,
+    /// Interval in ms between continuous queries, see [`crate::protocols::continuous_query::ContinuousQueryHandler`]
+    pub interval: u64,
+}
+
+impl Query {
+    /// Wait `ms` before sending the first query
+    ///
+    /// A random delay prevents hosts which start at the same time from querying simultaneously,
+    /// see [RFC6762 Section 5.2](https://www.rfc-editor.org/rfc/rfc6762#section-5.2)
+    pub fn with_initial_delay(mut self, ms: u64) -> Query {
+        self.initial_delay = ms;
+        self
+    }
+
+    /// Add a resolved service to be returned as the next Stream item
+    ///
+    /// Services which do not match the [`BrowseFilter`] or were found before are skipped,
+    /// returns whether the service was added
+    pub fn add_service(&mut self, service: Service) -> bool {
+        let instance = service.to_dns_sd_string();
+
+        if self
+            .resolved
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(&instance))
+        {
+            return false;
+        }
+
+        if !self.filter.matches(&service) {
+            debug!("Skipping {} filtered by TXT records", service.host);
+            return false;
+        }
+
+        self.resolved.push(instance);
+        self.services.push(service);
+        self.found += 1;
+        true
+    }
+}
+
+/// Browse State
+///
+/// Defines the state a [`Query`] is in during its lifetime
+///
+///  Value | Explanation
+/// :-- |:--
+/// Prelude | State upon creation, waiting for the initial delay
+/// Sent | First query sent, waiting for the first timeout
+/// Waiting | Query repeated without results, waiting the given ms before the next
+/// Complete | Services were found, the query keeps resolving services announced later until it is cancelled
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BrowseState {
+    /// Prelude | State upon creation, waiting for the initial delay
+    #[default]
+    Prelude,
+    /// Sent | First query sent, waiting for the first timeout
+    Sent,
+    /// Waiting | Query repeated without results, waiting the given ms before the next
+    Waiting(u64),
+    /// Complete | Services were found, the query keeps resolving services announced later until it is cancelled
+    Complete,
+}
+
+/// Browse Filter
+///
+/// Filters browse results by their TXT records, keys are compared case insensitive
+///
+/// [RFC6763 Section 6.4 - Rules for Keys in DNS-SD Key/Value Pairs](https://www.rfc-editor.org/rfc/rfc6763#section-6.4)
+///
+/// ## Example
+///
+/// ```
+/// use dns_sd2::service::{BrowseFilter, ServiceBuilder};
+///
+/// let filter = BrowseFilter::default().require_txt("version", "2").exclude_txt("beta");
+///
+/// let service = ServiceBuilder::default()
+///     .host("MyMachine")
+///     .service("_http")
+///     .protocol("_tcp")
+///     .port(8080)
+///     .txt("version", "2")
+///     .build()
+///     .unwrap();
+///
+/// assert!(filter.matches(&service));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct BrowseFilter {
+    required_txt: Vec<(String, String)>,
+    excluded_txt: Vec<String>,
+}
+
+impl BrowseFilter {
+    /// Require a TXT record in the format of `key=value`
+    pub fn require_txt(mut self, key: &str, value: &str) -> Self {
+        self.required_txt.push((key.into(), value.into()));
+        self
+    }
+
+    /// Exclude services which have a TXT record with this key
+    pub fn exclude_txt(mut self, key: &str) -> Self {
+        self.excluded_txt.push(key.into());
+        self
+    }
+
+    /// Whether the TXT records of the service satisfy this filter
+    pub fn matches(&self, service: &Service) -> bool {
+        //Boolean TXT records have no value
+        let txt: Vec<(&str, Option<&str>)> = service
+            .txt_records
+            .iter()
+            .map(|t| match t.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (t.as_str(), None),
+            })
+            .collect();
+
+        let required = self.required_txt.iter().all(|(key, value)| {
+            txt.iter()
+                .any(|(k, v)| k.eq_ignore_ascii_case(key) && *v == Some(value.as_str()))
+        });
+
+        let excluded = self
+            .excluded_txt
+            .iter()
+            .any(|key| txt.iter().any(|(k, _)| k.eq_ignore_ascii_case(key)));
+
+        required && !excluded
+    }
 }
 
 /// Service State
@@ -68,10 +594,21 @@ pub struct Query {
 /// FirstAnnouncement | Ready to announce
 /// WaitForSecondAnnouncement | First announcement and timeout sent
 /// SecondAnnouncement | Timeout finished, sending second announcement
+/// EarlyAnnounce | Our name was queried while waiting for the second announcement or while registered, announcing after a short random delay
 /// Registered | Final state
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// WaitForQueryResponse | Registered and delaying a response to a query for our shared records
+/// Paused | Announcements and responses are suspended until resumed
+/// GoodbyeRetry | Goodbye packet sent, waiting to repeat it the given number of times
+/// BrowseDelay | Browse command received, waiting before the first query
+/// BrowseCancelled | Browse cancelled, final state of a cancelled browse stream
+/// Removed | A browsed service sent goodbye packets and is no longer available
+///
+/// Non-exhaustive, states are added when more of the registration lifecycle is handled
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum ServiceState {
     ///Prelude | State upon creation
+    #[default]
     Prelude,
     /// WaitForFirstProbe | First random timeout sent
     WaitForFirstProbe,
@@ -89,12 +626,259 @@ pub enum ServiceState {
     WaitForSecondAnnouncement,
     /// SecondAnnouncement | Timeout finished, sending second announcement    
     SecondAnnouncement,
+    /// EarlyAnnounce | Our name was queried while waiting for the second announcement or while registered, announcing after a short random delay
+    EarlyAnnounce,
     /// Registered | Final state    
     Registered,
+    /// WaitForQueryResponse | Registered and delaying a response to a query for our shared records
+    WaitForQueryResponse,
+    /// Paused | Announcements and responses are suspended until resumed
+    Paused,
+    /// GoodbyeRetry | Goodbye packet sent, waiting to repeat it the given number of times
+    GoodbyeRetry(u8),
+    /// BrowseDelay | Browse command received, waiting before the first query
+    BrowseDelay,
+    /// BrowseCancelled | Browse cancelled, final state of a cancelled browse stream
+    BrowseCancelled,
+    /// Removed | A browsed service sent goodbye packets and is no longer available
+    Removed,
+}
+
+/// Service Event
+///
+/// Passed to the callbacks of [`DnsSd2::on_service_found()`](crate::DnsSd2::on_service_found) and [`DnsSd2::on_registered()`](crate::DnsSd2::on_registered)
+///
+/// Variant | Explanation
+/// :-- |:--
+/// Found | A service was found while browsing
+/// Registered | Our service was registered
+/// Added | One of our services finished registering, see [`DnsSd2::register_many()`](crate::DnsSd2::register_many)
+/// Removed | A service found while browsing sent goodbye packets, contains its instance name
+#[derive(Debug, Clone)]
+pub enum ServiceEvent {
+    /// A service was found while browsing
+    Found(Service),
+    /// Our service was registered
+    Registered(Service),
+    /// One of our services finished registering
+    Added(Service),
+    /// A service found while browsing sent goodbye packets, contains its instance name
+    Removed(String),
+}
+
+/// Registration Options
+///
+/// Arguments of [`DnsSd2::register()`](crate::DnsSd2::register) for registering multiple services at once,
+/// see [`DnsSd2::register_many()`](crate::DnsSd2::register_many)
+#[derive(Debug, Default, Clone)]
+pub struct RegistrationOptions {
+    /// Host name (e.g. 'MyMachine')
+    pub host: String,
+    /// Service name (e.g. "_scanner")
+    pub service: String,
+    /// Protocol name (e.g. "_tcp")
+    pub protocol: String,
+    /// Port name (e.g. 53000)
+    pub port: u16,
+    /// TXT Records (in format of "key=value")
+    pub txt_records: Vec<String>,
+}
+
+#[test]
+fn test_service_builder() {
+    let builder = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .txt("key", "value")
+        .txt_flag("flag");
+
+    //Port is required
+    assert!(builder.clone().build().is_err());
+
+    let service = builder.clone().port(53000).build().unwrap();
+    assert_eq!(service.port, 53000);
+    assert_eq!(service.txt_records, vec!["key=value", "flag"]);
+    assert_eq!(service.state, ServiceState::Prelude);
+    assert_eq!(service.display_name(), "TestMachine._test._tcp");
+
+    //Service should start with '_' and protocol should be '_tcp' or '_udp'
+    assert!(builder.clone().port(1).service("test").build().is_err());
+    assert!(builder.clone().port(1).protocol("_sctp").build().is_err());
+
+    //Service name is at most 15 letters, digits and hyphens
+    let service = |s: &str| builder.clone().port(1).service(s).build();
+    assert!(service("_http").is_ok());
+    assert!(service("_sleep-proxy").is_ok());
+    assert!(service("_fifteen-chars15").is_ok());
+    assert!(service("_sixteen-chars-16").is_err());
+    assert!(service("_").is_err());
+    assert!(service("_my_service").is_err());
+    assert!(service("_-http").is_err());
+}
+
+#[test]
+fn test_browse_filter() {
+    let service = |txt: &str| {
+        ServiceBuilder::default()
+            .host("TestMachine")
+            .service("_http")
+            .protocol("_tcp")
+            .port(8080)
+            .txt_flag(txt)
+            .build()
+            .unwrap()
+    };
+
+    let mut query = Query {
+        name: "_http._tcp.local".into(),
+        filter: BrowseFilter::default()
+            .require_txt("version", "2")
+            .exclude_txt("beta"),
+        ..Default::default()
+    };
+
+    assert!(query.add_service(service("Version=2")));
+    assert!(!query.add_service(service("version=1")));
+    assert!(!query.add_service(service("beta")));
+
+    assert_eq!(query.services.len(), 1);
+    assert_eq!(query.services[0].txt_records, vec!["Version=2".to_string()]);
 }
 
-impl Default for ServiceState {
-    fn default() -> Self {
-        ServiceState::Prelude
+#[tokio::test]
+async fn test_resolved_service() {
+    let service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_udp")
+        .port(53000)
+        .build()
+        .unwrap();
+
+    let mut resolved = ResolvedService {
+        service,
+        ip_v4: Ipv4Addr::LOCALHOST,
+        ip_v6: None,
+        registered_at: SystemTime::now(),
+    };
+
+    assert_eq!(resolved.socket_addr(), "127.0.0.1:53000".parse().unwrap());
+
+    let socket = resolved.bind_udp().unwrap();
+    assert_eq!(socket.peer_addr().unwrap(), resolved.socket_addr());
+
+    //Only _tcp services can be connected to
+    let error = resolved.connect().await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+    //Ipv6 is preferred
+    resolved.ip_v6 = Some(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1));
+    assert_eq!(resolved.socket_addr(), "[fe80::1]:53000".parse().unwrap());
+
+    resolved.service.protocol = "_tcp".into();
+    let error = resolved.bind_udp().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_verify_reachability() {
+    let interfaces = [NetworkInterface {
+        name: "eth0".into(),
+        ipv4: Some((
+            Ipv4Addr::new(192, 168, 1, 10),
+            Ipv4Addr::new(255, 255, 255, 0),
+        )),
+        ipv6: Some(Ipv6Addr::new(0xfd48, 0xa12f, 0x7b0c, 0x3da8, 0, 0, 0, 1)),
+        is_loopback: false,
+        is_multicast: true,
+    }];
+
+    let mut resolved = ResolvedService {
+        service: Service::default(),
+        ip_v4: Ipv4Addr::new(192, 168, 1, 2),
+        ip_v6: None,
+        registered_at: SystemTime::now(),
+    };
+
+    //Same subnet
+    assert!(resolved.reachable_ipv4_from(&interfaces).is_ok());
+    assert!(matches!(
+        resolved.reachable_ipv6_from(&interfaces),
+        Err(MdnsError::NotReachable {})
+    ));
+
+    resolved.ip_v6 = Some(Ipv6Addr::new(
+        0xfd48, 0xa12f, 0x7b0c, 0x3da8, 0, 0, 0, 0xabcd,
+    ));
+    assert!(resolved.reachable_ipv6_from(&interfaces).is_ok());
+
+    //Other subnet
+    resolved.ip_v4 = Ipv4Addr::new(192, 168, 2, 2);
+    resolved.ip_v6 = Some(Ipv6Addr::new(
+        0xfd48, 0xa12f, 0x7b0c, 0x3da9, 0, 0, 0, 0xabcd,
+    ));
+
+    assert!(matches!(
+        resolved.reachable_ipv4_from(&interfaces),
+        Err(MdnsError::NotReachable {})
+    ));
+    assert!(matches!(
+        resolved.reachable_ipv6_from(&interfaces),
+        Err(MdnsError::NotReachable {})
+    ));
+    assert!(resolved.reachable_ipv4_from(&[]).is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_elapsed_since_registered() {
+    let mut service = ServiceBuilder::default()
+        .host("TestMachine")
+        .service("_test")
+        .protocol("_tcp")
+        .port(53000)
+        .build()
+        .unwrap();
+    service.registered_at = Some(Instant::now());
+
+    //Only registered services have been registered for a while
+    assert_eq!(service.elapsed_since_registered(), None);
+
+    service.state = ServiceState::Registered;
+    tokio::time::advance(Duration::from_millis(50)).await;
+
+    let elapsed = service.elapsed_since_registered().unwrap();
+    assert!(elapsed > Duration::ZERO && elapsed < Duration::from_secs(1));
+}
+
+#[test]
+fn test_dns_sd_string() {
+    for host in ["MyPrinter", "printer.office"] {
+        let service = ServiceBuilder::default()
+            .host(host)
+            .service("_ipp")
+            .protocol("_tcp")
+            .port(631)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            service.to_dns_sd_string(),
+            format!("{}._ipp._tcp.local", host)
+        );
+        assert_eq!(service.service_type(), "_ipp._tcp.local");
+
+        let (host, name, protocol) = Service::parse_dns_sd(&service.to_dns_sd_string()).unwrap();
+        assert_eq!(host, service.host);
+        assert_eq!(name, service.service);
+        assert_eq!(protocol, service.protocol);
     }
+
+    //Trailing root label and case insensitive domain
+    assert!(Service::parse_dns_sd("MyPrinter._ipp._udp.LOCAL.").is_ok());
+
+    assert!(Service::parse_dns_sd("MyPrinter._ipp._tcp.example.com").is_err());
+    assert!(Service::parse_dns_sd("_ipp._tcp.local").is_err());
+    assert!(Service::parse_dns_sd("MyPrinter.ipp._tcp.local").is_err());
+    assert!(Service::parse_dns_sd("MyPrinter._ipp._sctp.local").is_err());
 }
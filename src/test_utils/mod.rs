@@ -0,0 +1,92 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{Sink, Stream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::utility::{MdnsSocket, SocketFactory};
+
+/// Bytes and address of a sent datagram
+pub type SentMessage = (Vec<u8>, SocketAddr);
+
+/// Mock UDP Socket
+///
+/// In-memory [`MdnsSocket`] which records sent datagrams instead of sending them on the network
+///
+/// Receives the datagrams passed to [`MockUdpSocket::receive()`]
+#[derive(Debug, Clone)]
+pub struct MockUdpSocket {
+    /// Bytes and address of every sent datagram
+    pub sent_messages: Arc<Mutex<Vec<SentMessage>>>,
+    incoming_tx: UnboundedSender<SentMessage>,
+    incoming_rx: Arc<Mutex<UnboundedReceiver<SentMessage>>>,
+}
+
+impl Default for MockUdpSocket {
+    fn default() -> Self {
+        let (incoming_tx, incoming_rx) = unbounded_channel();
+
+        Self {
+            sent_messages: Default::default(),
+            incoming_tx,
+            incoming_rx: Arc::new(Mutex::new(incoming_rx)),
+        }
+    }
+}
+
+impl MockUdpSocket {
+    /// Queue a datagram to be received from the given address
+    pub fn receive(&self, bytes: Vec<u8>, addr: SocketAddr) {
+        self.incoming_tx
+            .send((bytes, addr))
+            .expect("Should queue datagram");
+    }
+
+    /// A [`SocketFactory`] creating mock sockets which share the buffer of this socket
+    pub fn factory(&self) -> impl SocketFactory {
+        let socket = self.clone();
+        move || Ok(Box::new(socket.clone()) as Box<dyn MdnsSocket>)
+    }
+}
+
+impl Stream for MockUdpSocket {
+    type Item = io::Result<(BytesMut, SocketAddr)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.incoming_rx
+            .lock()
+            .expect("Should lock incoming datagrams")
+            .poll_recv(cx)
+            .map(|d| d.map(|(bytes, addr)| Ok((BytesMut::from(&bytes[..]), addr))))
+    }
+}
+
+impl Sink<(Bytes, SocketAddr)> for MockUdpSocket {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: (Bytes, SocketAddr)) -> Result<(), Self::Error> {
+        self.sent_messages
+            .lock()
+            .expect("Should lock sent messages")
+            .push((item.0.to_vec(), item.1));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{Future, Stream};
+use tokio::time::{sleep, Instant, Sleep};
+
+use crate::service::ServiceState;
+
+/// Timeout Map
+///
+/// Pending timeouts of the [`Handler`](crate::protocols::handler::Handler) chain, keyed by the [`ServiceState`] they belong to
+///
+/// Unlike a plain collection of sleep futures, a timeout can be inspected, cancelled or rescheduled before it fires.
+/// Cancelling is needed for delayed responses, which are dropped when another responder answers first
+///
+/// [RFC6762 Section 7.3 - Duplicate Question Suppression](https://www.rfc-editor.org/rfc/rfc6762#section-7.3)
+///
+/// Only one timeout exists per [`ServiceState`], inserting an existing state replaces its timeout.
+/// As a [`Stream`] it yields the state and duration of the earliest finished timeout and removes it from the map
+#[derive(Debug, Default)]
+pub struct TimeoutMap {
+    timeouts: HashMap<ServiceState, (u64, Pin<Box<Sleep>>)>,
+}
+
+impl TimeoutMap {
+    /// Start a timeout of `duration_ms` for the given state
+    pub fn insert(&mut self, state: ServiceState, duration_ms: u64) {
+        self.timeouts.insert(
+            state,
+            (duration_ms, Box::pin(sleep(Duration::from_millis(duration_ms)))),
+        );
+    }
+
+    /// Cancel the timeout for the given state
+    ///
+    /// Returns false if no timeout was pending
+    pub fn cancel(&mut self, state: ServiceState) -> bool {
+        self.timeouts.remove(&state).is_some()
+    }
+
+    /// Restart the pending timeout for the given state with a new duration
+    ///
+    /// Returns false if no timeout was pending
+    pub fn reschedule(&mut self, state: ServiceState, new_duration_ms: u64) -> bool {
+        match self.timeouts.get_mut(&state) {
+            Some((duration, sleep)) => {
+                *duration = new_duration_ms;
+                sleep
+                    .as_mut()
+                    .reset(Instant::now() + Duration::from_millis(new_duration_ms));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether a timeout is pending for the given state
+    pub fn contains(&self, state: ServiceState) -> bool {
+        self.timeouts.contains_key(&state)
+    }
+
+    /// Number of pending timeouts
+    pub fn len(&self) -> usize {
+        self.timeouts.len()
+    }
+
+    /// Whether no timeouts are pending
+    pub fn is_empty(&self) -> bool {
+        self.timeouts.is_empty()
+    }
+}
+
+impl Stream for TimeoutMap {
+    type Item = (ServiceState, u64);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        //Poll every timeout so all of them register the waker, then take the earliest finished one
+        let finished = self
+            .timeouts
+            .iter_mut()
+            .filter_map(|(state, (_, sleep))| match sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => Some((*state, sleep.deadline())),
+                Poll::Pending => None,
+            })
+            .min_by_key(|(_, deadline)| *deadline);
+
+        match finished {
+            Some((state, _)) => {
+                let (duration, _) = self.timeouts.remove(&state).expect("Should contain state");
+                Poll::Ready(Some((state, duration)))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_timeout_order() {
+    use futures::StreamExt;
+
+    let mut timeouts = TimeoutMap::default();
+
+    timeouts.insert(ServiceState::WaitForSecondAnnouncement, 300);
+    timeouts.insert(ServiceState::WaitForFirstProbe, 100);
+    timeouts.insert(ServiceState::WaitForQueryResponse, 200);
+
+    assert_eq!(timeouts.len(), 3);
+    assert_eq!(
+        timeouts.next().await,
+        Some((ServiceState::WaitForFirstProbe, 100))
+    );
+    assert_eq!(
+        timeouts.next().await,
+        Some((ServiceState::WaitForQueryResponse, 200))
+    );
+    assert_eq!(
+        timeouts.next().await,
+        Some((ServiceState::WaitForSecondAnnouncement, 300))
+    );
+    assert!(timeouts.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_timeout_cancel() {
+    use futures::StreamExt;
+
+    let mut timeouts = TimeoutMap::default();
+
+    timeouts.insert(ServiceState::WaitForQueryResponse, 100);
+    timeouts.insert(ServiceState::WaitForSecondAnnouncement, 200);
+
+    assert!(timeouts.cancel(ServiceState::WaitForQueryResponse));
+    assert!(!timeouts.cancel(ServiceState::WaitForQueryResponse));
+    assert!(!timeouts.contains(ServiceState::WaitForQueryResponse));
+
+    assert_eq!(
+        timeouts.next().await,
+        Some((ServiceState::WaitForSecondAnnouncement, 200))
+    );
+    assert!(timeouts.is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_timeout_reschedule() {
+    use futures::StreamExt;
+
+    let mut timeouts = TimeoutMap::default();
+
+    timeouts.insert(ServiceState::WaitForFirstProbe, 100);
+    timeouts.insert(ServiceState::WaitForSecondProbe, 200);
+
+    assert!(timeouts.reschedule(ServiceState::WaitForFirstProbe, 300));
+    assert!(!timeouts.reschedule(ServiceState::WaitForAnnouncing, 300));
+
+    assert_eq!(
+        timeouts.next().await,
+        Some((ServiceState::WaitForSecondProbe, 200))
+    );
+    assert_eq!(
+        timeouts.next().await,
+        Some((ServiceState::WaitForFirstProbe, 300))
+    );
+}
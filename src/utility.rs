@@ -1,17 +1,26 @@
 use std::{
+    cmp::Ordering,
     io::{self},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
     ops::BitAnd,
+    sync::{PoisonError, RwLock},
 };
 
 use bitvec::prelude::*;
-use bytes::Bytes;
-use futures::SinkExt;
+use bytes::{Bytes, BytesMut};
+use futures::{Sink, SinkExt, Stream};
+use if_addrs::IfAddr;
+use once_cell::sync::Lazy;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use tokio::net::UdpSocket;
 use tokio_util::{codec::BytesCodec, udp::UdpFramed};
 
-use crate::{message::MdnsMessage, MdnsError, IP_ANY};
+use crate::{
+    message::{MdnsMessage, MAX_MESSAGE_SIZE},
+    name::Name,
+    record::{ResourceRecord, STATIC_TTL},
+    MdnsError, IP_ANY,
+};
 
 /// When there might be multiple responders on the system,
 /// the port for UDP messages might be occupied without the REUSE_ADDR set
@@ -63,13 +72,37 @@ pub async fn check_unique_responder() -> Result<(), MdnsError> {
     Ok(())
 }
 
-/// UTILITY FUNCTIONS
+// UTILITY FUNCTIONS
 //
 
+/// Ipv4 multicast group of Mdns
+///
+/// [RFC6762 Section 3 - Multicast DNS Names](https://www.rfc-editor.org/rfc/rfc6762#section-3)
+pub const MDNS_IPV4_MULTICAST: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// Socket Configuration
+///
+/// Options for [`create_socket()`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SocketConfig {
+    /// Bind the socket to the network interface with this name, e.g. 'eth0'
+    ///
+    /// Uses `SO_BINDTODEVICE` on Linux and `IP_BOUND_IF` on macOS, it is ignored on other platforms such as Windows
+    pub interface: Option<String>,
+    /// Do not receive the multicast messages sent by this machine, sets `IP_MULTICAST_LOOP` to false
+    ///
+    /// See [`DnsSd2::disable_multicast_loop()`](crate::DnsSd2::disable_multicast_loop)
+    pub disable_multicast_loop: bool,
+}
+
 /// Create Multicast Socket
 ///
 /// Creates a Udp Ipv4 Multicast socket and binds it to the wildcard 0.0.0.0 address
-pub fn create_socket() -> io::Result<UdpSocket> {
+///
+/// Unicast and multicast packets are sent with an IP TTL of 255, see [`mdns_multicast_ttl_check()`]
+///
+/// With [`SocketConfig::interface`] set, the socket only sends and receives on that interface
+pub fn create_socket(config: &SocketConfig) -> io::Result<UdpSocket> {
     //Create a udp ip4 socket
     let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
 
@@ -78,6 +111,10 @@ pub fn create_socket() -> io::Result<UdpSocket> {
     socket.set_reuse_port(true)?;
     socket.set_nonblocking(true)?;
 
+    if let Some(interface) = &config.interface {
+        bind_to_interface(&socket, interface)?;
+    }
+
     //Create IPV4 any adress
     let address = SocketAddrV4::new(IP_ANY.into(), 5353);
 
@@ -89,10 +126,19 @@ pub fn create_socket() -> io::Result<UdpSocket> {
     debug!("Bound Socket");
 
     //Join multicast group
-    socket.join_multicast_v4(&Ipv4Addr::new(224, 0, 0, 51), address.ip())?;
+    socket.join_multicast_v4(&MDNS_IPV4_MULTICAST, address.ip())?;
 
     info!("Joined Multicast");
 
+    //Mdns packets are sent with an IP TTL of 255, receivers discard packets with a lower TTL
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_ttl(255)?;
+
+    if config.disable_multicast_loop {
+        socket.set_multicast_loop_v4(false)?;
+        debug!("Disabled Multicast Loop");
+    }
+
     //Convert to std::net udp socket
     let udp_std_socket: std::net::UdpSocket = socket.into();
 
@@ -101,11 +147,287 @@ pub fn create_socket() -> io::Result<UdpSocket> {
 
     info!(
         "Created a UDP Socket at {}, {}",
-        address.ip().to_string(),
-        address.port().to_string()
+        address.ip(),
+        address.port()
+    );
+
+    Ok(udp_socket)
+}
+
+/// Bind `socket` to the network interface named `interface` with `SO_BINDTODEVICE`
+#[cfg(target_os = "linux")]
+fn bind_to_interface(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))?;
+    debug!("Bound Socket to {}", interface);
+    Ok(())
+}
+
+/// Bind `socket` to the network interface named `interface` with `IP_BOUND_IF`
+#[cfg(target_os = "macos")]
+fn bind_to_interface(socket: &Socket, interface: &str) -> io::Result<()> {
+    let name = std::ffi::CString::new(interface)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    //SAFETY: name is a valid nul terminated string
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+
+    socket.bind_device_by_index(std::num::NonZeroU32::new(index))?;
+    debug!("Bound Socket to {}", interface);
+    Ok(())
+}
+
+/// Interface binding is not supported on this platform, the socket receives on all interfaces
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn bind_to_interface(_socket: &Socket, interface: &str) -> io::Result<()> {
+    warn!(
+        "Binding to interface {} is not supported on this platform",
+        interface
     );
+    Ok(())
+}
 
-    return Ok(udp_socket);
+/// Mdns Socket
+///
+/// A UDP socket receiving and sending datagrams with their address, as implemented by `UdpFramed<BytesCodec>`
+///
+/// Allows [`DnsSd2`](crate::DnsSd2) to run on another socket, e.g. an in-memory socket for testing
+pub trait MdnsSocket:
+    Stream<Item = io::Result<(BytesMut, SocketAddr)>>
+    + Sink<(Bytes, SocketAddr), Error = io::Error>
+    + Send
+    + Unpin
+{
+}
+
+impl<T> MdnsSocket for T where
+    T: Stream<Item = io::Result<(BytesMut, SocketAddr)>>
+        + Sink<(Bytes, SocketAddr), Error = io::Error>
+        + Send
+        + Unpin
+{
+}
+
+/// Socket Factory
+///
+/// Creates the [`MdnsSocket`] for [`DnsSd2`](crate::DnsSd2), which is [`create_framed_socket()`] by default
+///
+/// See [`DnsSd2::with_socket_factory()`](crate::DnsSd2::with_socket_factory)
+pub trait SocketFactory: Fn() -> io::Result<Box<dyn MdnsSocket>> + Send + Sync {}
+
+impl<F> SocketFactory for F where F: Fn() -> io::Result<Box<dyn MdnsSocket>> + Send + Sync {}
+
+/// Create the multicast socket of [`create_socket()`] framed as a [`MdnsSocket`]
+pub fn create_framed_socket() -> io::Result<Box<dyn MdnsSocket>> {
+    Ok(Box::new(UdpFramed::new(
+        create_socket(&SocketConfig::default())?,
+        BytesCodec::new(),
+    )))
+}
+
+/// Whether a received Mdns packet passes the TTL check
+///
+/// Mdns packets are sent with an IP TTL of 255, a lower TTL means the packet was routed from another link
+///
+/// [RFC6762 Section 11 - Source Address Check](https://www.rfc-editor.org/rfc/rfc6762#section-11)
+pub fn mdns_multicast_ttl_check(ttl: u8) -> bool {
+    ttl == 255
+}
+
+/// Create the multicast socket of [`create_socket()`] with `IP_RECVTTL` set
+///
+/// The IP TTL of each received packet is then passed along as ancillary data, see [`TtlCheckedSocket`]
+#[cfg(target_os = "linux")]
+pub fn create_socket_with_ttl_check() -> io::Result<UdpSocket> {
+    let socket = create_socket(&SocketConfig::default())?;
+    set_recv_ttl(&socket)?;
+    Ok(socket)
+}
+
+/// Set `IP_RECVTTL` on `socket`
+#[cfg(target_os = "linux")]
+fn set_recv_ttl(socket: &UdpSocket) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let enabled: libc::c_int = 1;
+
+    //SAFETY: the option value is a c_int that lives for the duration of the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_RECVTTL,
+            &enabled as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Receive a datagram along with its IP TTL, `None` if the TTL was not passed along
+#[cfg(target_os = "linux")]
+fn recv_with_ttl(
+    socket: &UdpSocket,
+    buffer: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Option<u8>)> {
+    use std::os::fd::AsRawFd;
+
+    let mut control = [0u8; 64];
+    let mut iov = libc::iovec {
+        iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buffer.len(),
+    };
+
+    //SAFETY: all-zero is a valid value for these C structs
+    let mut source: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut header: libc::msghdr = unsafe { std::mem::zeroed() };
+
+    header.msg_name = &mut source as *mut libc::sockaddr_storage as *mut libc::c_void;
+    header.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    header.msg_iov = &mut iov;
+    header.msg_iovlen = 1;
+    header.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    header.msg_controllen = control.len() as _;
+
+    //SAFETY: the header points to buffers which outlive the call
+    let length = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut header, 0) };
+
+    if length < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut ttl = None;
+
+    //SAFETY: the control messages were written by recvmsg within the bounds of `control`
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&header);
+
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_TTL {
+                let value = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                ttl = Some(value as u8);
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&header, cmsg);
+        }
+    }
+
+    //SAFETY: recvmsg initialized the source address with msg_namelen bytes
+    let address = unsafe { SockAddr::new(source, header.msg_namelen) }
+        .as_socket()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown source address"))?;
+
+    Ok((length as usize, address, ttl))
+}
+
+/// TTL Checked Socket
+///
+/// A [`MdnsSocket`] which discards received packets failing the [`mdns_multicast_ttl_check()`]
+///
+/// Use it with [`DnsSd2::with_socket_factory(create_ttl_checked_framed_socket)`](crate::DnsSd2::with_socket_factory)
+///
+/// Only available on Linux, where the TTL is retrieved with `IP_RECVTTL`.
+/// The check is opt-in, as it drops every packet of a responder which sends with a lower TTL,
+/// e.g. one that keeps the default multicast TTL of 1.
+/// [`DnsSd2::with_source_address_check()`](crate::DnsSd2::with_source_address_check) checks the source on every platform
+#[cfg(target_os = "linux")]
+pub struct TtlCheckedSocket {
+    socket: std::sync::Arc<UdpSocket>,
+    sink: UdpFramed<BytesCodec, std::sync::Arc<UdpSocket>>,
+    buffer: Vec<u8>,
+}
+
+#[cfg(target_os = "linux")]
+impl TtlCheckedSocket {
+    /// Wrap `socket`, which must have `IP_RECVTTL` set, see [`create_socket_with_ttl_check()`]
+    pub fn new(socket: UdpSocket) -> Self {
+        let socket = std::sync::Arc::new(socket);
+
+        Self {
+            sink: UdpFramed::new(socket.clone(), BytesCodec::new()),
+            socket,
+            buffer: vec![0; 65536],
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Stream for TtlCheckedSocket {
+    type Item = io::Result<(BytesMut, SocketAddr)>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
+
+        let this = self.get_mut();
+
+        loop {
+            if let Err(e) = futures::ready!(this.socket.poll_recv_ready(cx)) {
+                return Poll::Ready(Some(Err(e)));
+            }
+
+            let buffer = &mut this.buffer;
+
+            match this.socket.try_io(tokio::io::Interest::READABLE, || {
+                recv_with_ttl(&this.socket, buffer)
+            }) {
+                Ok((length, addr, ttl)) => match ttl {
+                    Some(ttl) if !mdns_multicast_ttl_check(ttl) => {
+                        warn!("Discarding packet from {} with TTL {}", addr, ttl);
+                    }
+                    _ => return Poll::Ready(Some(Ok((BytesMut::from(&buffer[..length]), addr)))),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Sink<(Bytes, SocketAddr)> for TtlCheckedSocket {
+    type Error = io::Error;
+
+    fn poll_ready(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        Sink::<(Bytes, SocketAddr)>::poll_ready(std::pin::Pin::new(&mut self.get_mut().sink), cx)
+    }
+
+    fn start_send(
+        self: std::pin::Pin<&mut Self>,
+        item: (Bytes, SocketAddr),
+    ) -> Result<(), Self::Error> {
+        Sink::<(Bytes, SocketAddr)>::start_send(std::pin::Pin::new(&mut self.get_mut().sink), item)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        Sink::<(Bytes, SocketAddr)>::poll_flush(std::pin::Pin::new(&mut self.get_mut().sink), cx)
+    }
+
+    fn poll_close(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        Sink::<(Bytes, SocketAddr)>::poll_close(std::pin::Pin::new(&mut self.get_mut().sink), cx)
+    }
+}
+
+/// Create the socket of [`create_socket_with_ttl_check()`] framed as a [`TtlCheckedSocket`]
+#[cfg(target_os = "linux")]
+pub fn create_ttl_checked_framed_socket() -> io::Result<Box<dyn MdnsSocket>> {
+    Ok(Box::new(TtlCheckedSocket::new(
+        create_socket_with_ttl_check()?,
+    )))
 }
 
 /// Determine whether a query host is reachable
@@ -132,7 +454,6 @@ pub fn create_socket() -> io::Result<UdpSocket> {
 /// assert!(is_reachable_ipv4(&Ipv4Addr::new(192,168,1,1), &Ipv4Addr::new(255,255,255,0), &Ipv4Addr::new(192,168,1,30)));
 ///
 /// assert!(!is_reachable_ipv4(&Ipv4Addr::new(192,168,1,1), &Ipv4Addr::new(255,255,255,0), &Ipv4Addr::new(192,168,2,30)));
-
 /// ```
 pub fn is_reachable_ipv4(host_ip: &Ipv4Addr, host_subnet: &Ipv4Addr, source_ip: &Ipv4Addr) -> bool {
     let host_network = host_ip
@@ -187,26 +508,555 @@ pub fn is_reachable_ipv6(host_ip: &Ipv6Addr, host_subnet: &Ipv6Addr, source_ip:
     host_network == source_network
 }
 
-// Lexicographic Comparison
-//
-// Compares two records for which is lexicographically 'later'
-//
-// [RFC6762 Section 8.2 - Simultaneous Probe Tiebreak](https://www.rfc-editor.org/rfc/rfc6762#section-8.2)
-//
-// TODO Clarify protocol procedures
-// Impl Ord for Service{}
+/// Get Hostname
+///
+/// Retrieves the short hostname of this machine (without domain) to use as host for a [`crate::service::Service`]
+///
+/// Falls back to `localhost` with a warning if the hostname can not be retrieved
+/// Returns [`MdnsError::InvalidService`] if the hostname is not valid UTF-8
+///
+/// # Example
+///
+/// ```rust
+/// use dns_sd2::utility::get_hostname;
+///
+/// assert!(!get_hostname().unwrap().is_empty());
+/// ```
+pub fn get_hostname() -> Result<String, MdnsError> {
+    let hostname = match hostname::get() {
+        Ok(h) => h.into_string().map_err(|h| MdnsError::InvalidService {
+            reason: format!("Hostname {:?} is not valid UTF-8", h),
+        })?,
+        Err(e) => {
+            warn!("Could not retrieve hostname {}, using localhost", e);
+            return Ok("localhost".into());
+        }
+    };
+
+    //Strip the domain from the hostname
+    match hostname.split('.').next() {
+        Some(host) if !host.is_empty() => Ok(host.into()),
+        _ => {
+            warn!("Hostname is empty, using localhost");
+            Ok("localhost".into())
+        }
+    }
+}
+
+/// Local Ipv4 Addresses
+///
+/// Returns the Ipv4 addresses of all non-loopback interfaces on this machine
+///
+/// Used for the A records of our host
+pub fn local_ipv4_addresses() -> Vec<Ipv4Addr> {
+    match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces
+            .iter()
+            .filter(|i| !i.is_loopback())
+            .filter_map(|i| match i.ip() {
+                IpAddr::V4(ip) => Some(ip),
+                _ => None,
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Could not retrieve network interfaces {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Local Ipv6 Addresses
+///
+/// Returns the Ipv6 addresses of all non-loopback interfaces on this machine
+///
+/// Used to determine whether AAAA records should be added for our host
+pub fn local_ipv6_addresses() -> Vec<Ipv6Addr> {
+    match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces
+            .iter()
+            .filter(|i| !i.is_loopback())
+            .filter_map(|i| match i.ip() {
+                IpAddr::V6(ip) => Some(ip),
+                _ => None,
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Could not retrieve network interfaces {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Network Interface
+///
+/// Metadata of a network interface on this machine, see [`enumerate_network_interfaces()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkInterface {
+    /// Interface name, e.g. 'eth0'
+    pub name: String,
+    /// First Ipv4 address and its subnet mask
+    pub ipv4: Option<(Ipv4Addr, Ipv4Addr)>,
+    /// First Ipv6 address
+    pub ipv6: Option<Ipv6Addr>,
+    /// Whether this is a loopback interface, e.g. 'lo'
+    pub is_loopback: bool,
+    /// Whether the interface can send multicast, assumed for every non-loopback interface
+    pub is_multicast: bool,
+}
+
+/// Interfaces are enumerated on first use and again on [`refresh_network_interfaces()`]
+static NETWORK_INTERFACES: Lazy<RwLock<Vec<NetworkInterface>>> =
+    Lazy::new(|| RwLock::new(detect_network_interfaces()));
+
+/// Enumerate the network interfaces of this machine
+fn detect_network_interfaces() -> Vec<NetworkInterface> {
+    let mut interfaces: Vec<NetworkInterface> = vec![];
+
+    let addresses = if_addrs::get_if_addrs().unwrap_or_else(|e| {
+        warn!("Could not retrieve network interfaces {}", e);
+        vec![]
+    });
+
+    //Interfaces are listed once for each address
+    for address in addresses {
+        let index = match interfaces.iter().position(|i| i.name == address.name) {
+            Some(index) => index,
+            None => {
+                interfaces.push(NetworkInterface {
+                    name: address.name.clone(),
+                    ipv4: None,
+                    ipv6: None,
+                    is_loopback: address.is_loopback(),
+                    is_multicast: !address.is_loopback(),
+                });
+                interfaces.len() - 1
+            }
+        };
+
+        let interface = &mut interfaces[index];
+
+        match address.addr {
+            IfAddr::V4(v4) => {
+                interface.ipv4.get_or_insert((v4.ip, v4.netmask));
+            }
+            IfAddr::V6(v6) => {
+                interface.ipv6.get_or_insert(v6.ip);
+            }
+        }
+    }
+
+    interfaces
+}
+
+/// Network Interfaces
+///
+/// Returns the name, addresses and flags of all network interfaces on this machine
+///
+/// The interfaces are retrieved on the first call and cached afterwards, see [`refresh_network_interfaces()`]
+pub fn enumerate_network_interfaces() -> Vec<NetworkInterface> {
+    NETWORK_INTERFACES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Enumerate the network interfaces again and replace the cached interfaces, e.g. after a network change
+///
+/// Returns the new interfaces, see [`enumerate_network_interfaces()`]
+pub fn refresh_network_interfaces() -> Vec<NetworkInterface> {
+    let interfaces = detect_network_interfaces();
+
+    *NETWORK_INTERFACES
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = interfaces.clone();
+    interfaces
+}
+
+/// Subnet of a Network Interface
+///
+/// Returns the first Ipv4 address of the interface named `iface` and its subnet mask,
+/// `None` if there is no such interface or it has no Ipv4 address
+pub fn subnet_for_interface(iface: &str) -> Option<(Ipv4Addr, Ipv4Addr)> {
+    NETWORK_INTERFACES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|i| i.name == iface)
+        .and_then(|i| i.ipv4)
+}
+
+/// Whether `ip` is an Ipv4 link-local address in `169.254.0.0/16`
+///
+/// ```rust
+/// use std::net::Ipv4Addr;
+///
+/// use dns_sd2::utility::is_link_local;
+///
+/// assert!(is_link_local(&Ipv4Addr::new(169, 254, 1, 1)));
+/// ```
+pub fn is_link_local(ip: &Ipv4Addr) -> bool {
+    ip.octets()[..2] == [169, 254]
+}
+
+/// Source Address Check
+///
+/// Determine whether a message from `source` originates on the local link
+///
+/// Ipv4 sources are compared with the subnet of each interface, see [`is_reachable_ipv4()`]
+/// Link-local and loopback sources are always on the local link
+///
+/// A source outside the subnets of the cached interfaces is compared again after enumerating the interfaces anew,
+/// so subnets gained while running are accepted, see [`refresh_network_interfaces()`]
+///
+/// The interfaces hold no Ipv6 prefix length, Ipv6 sources are accepted
+///
+/// ## RFC Reference
+/// -[RFC6762 Section 11 - Source Address Check](https://www.rfc-editor.org/rfc/rfc6762#section-11)
+pub fn source_address_check(source: &IpAddr) -> bool {
+    match source {
+        IpAddr::V4(ip) => {
+            let reachable = |interfaces: Vec<NetworkInterface>| {
+                interfaces
+                    .iter()
+                    .filter_map(|i| i.ipv4)
+                    .any(|(host, subnet)| is_reachable_ipv4(&host, &subnet, ip))
+            };
+
+            is_link_local(ip)
+                || ip.is_loopback()
+                || reachable(enumerate_network_interfaces())
+                || reachable(refresh_network_interfaces())
+        }
+        IpAddr::V6(_) => true,
+    }
+}
+
+/// Primary Network Interface
+///
+/// Returns the first non-loopback interface with a multicast capable Ipv4 address
+pub fn primary_interface() -> Option<NetworkInterface> {
+    NETWORK_INTERFACES
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .find(|i| !i.is_loopback && i.is_multicast && i.ipv4.is_some())
+        .cloned()
+}
+
+/// Lexicographic Comparison
+///
+/// Compares two records for which is lexicographically 'later'
+///
+/// The class (without the cache flush bit) is compared first, then the type and then the raw RDATA bytes,
+/// `Greater` means `ours` is later
+///
+/// [RFC6762 Section 8.2 - Simultaneous Probe Tiebreak](https://www.rfc-editor.org/rfc/rfc6762#section-8.2)
+pub fn lexicographic_later(ours: &ResourceRecord, theirs: &ResourceRecord) -> Ordering {
+    let rdata = |r: &ResourceRecord| r.rdata.as_ref().map(|rdata| rdata.to_bytes());
+
+    u16::from(ours.record_class)
+        .cmp(&u16::from(theirs.record_class))
+        .then(u16::from(ours.record_type).cmp(&u16::from(theirs.record_type)))
+        .then_with(|| rdata(ours).cmp(&rdata(theirs)))
+}
+
+/// Parse the contents of a hosts file, e.g. `/etc/hosts`, into static A and AAAA records
+///
+/// Each line holds an address followed by one or more host names, separated by spaces or tabs,
+/// anything after a `#` is a comment.
+/// Only the `.local` names of non-loopback addresses are returned, with a TTL of [`STATIC_TTL`]
+pub fn parse_hosts_file(contents: &str) -> Vec<ResourceRecord> {
+    let mut records = vec![];
+
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+
+        let ip = match fields.next().map(str::parse::<IpAddr>) {
+            Some(Ok(ip)) if !ip.is_loopback() => ip,
+            _ => continue,
+        };
+
+        for host in fields.filter(|h| h.to_ascii_lowercase().ends_with(".local")) {
+            let name = match Name::new(host.to_string()) {
+                Ok(name) => name,
+                Err(_) => {
+                    warn!("Skipping invalid host name {} in hosts file", host);
+                    continue;
+                }
+            };
+
+            let mut record = match ip {
+                IpAddr::V4(ip) => ResourceRecord::create_a_record_from_ipv4(name, ip),
+                IpAddr::V6(ip) => ResourceRecord::create_aaaa_record_from_ipv6(name, ip),
+            };
+            record.ttl = STATIC_TTL;
+            record.original_ttl = STATIC_TTL;
+
+            records.push(record);
+        }
+    }
+
+    records
+}
+
+/// Destination of an outgoing Mdns Message
+///
+/// Messages are multicast unless a querier asked for a unicast (QU) response
+///
+/// ## RFC Reference
+/// - [RFC6762 Section 5.4 - Questions Requesting Unicast Responses](https://www.rfc-editor.org/rfc/rfc6762#section-5.4)
+/// - [RFC6762 Section 3 - Multicast DNS Names](https://www.rfc-editor.org/rfc/rfc6762#section-3)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SendTarget {
+    /// Ipv4 multicast group 224.0.0.251
+    Multicast,
+    /// Unicast to an Ipv4 host
+    UnicastV4(Ipv4Addr),
+    /// Unicast to an Ipv6 host
+    UnicastV6(Ipv6Addr),
+}
+
+impl SendTarget {
+    /// Socket address for this target on the Mdns port 5353
+    pub fn socket_addr(&self) -> SocketAddr {
+        match self {
+            SendTarget::Multicast => SocketAddr::new(IpAddr::V4(MDNS_IPV4_MULTICAST), 5353),
+            SendTarget::UnicastV4(ip) => SocketAddr::new(IpAddr::V4(*ip), 5353),
+            SendTarget::UnicastV6(ip) => SocketAddr::new(IpAddr::V6(*ip), 5353),
+        }
+    }
+}
 
 ///Send an Mdns Message to the multicast group with the given Socket
 pub async fn send_message(
-    socket: &mut UdpFramed<BytesCodec>,
+    socket: &mut (impl Sink<(Bytes, SocketAddr), Error = io::Error> + Unpin),
+    message: &MdnsMessage,
+) -> std::io::Result<()> {
+    send_message_to(socket, message, SendTarget::Multicast.socket_addr()).await
+}
+
+///Send an Mdns Message to the given address with the given Socket
+pub async fn send_message_to(
+    socket: &mut (impl Sink<(Bytes, SocketAddr), Error = io::Error> + Unpin),
     message: &MdnsMessage,
+    addr: SocketAddr,
 ) -> std::io::Result<()> {
-    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+    //Mdns messages always have an ID of zero, RFC6762 Section 18.1
+    if message.header.id != 0 {
+        warn!("Sending message with non-zero ID {}", message.header.id);
+    }
+
+    //The message is encoded once, its size is checked on the encoded bytes
+    let encoded = message.encode();
+
+    //Larger messages may be fragmented or dropped, RFC1035 Section 4.2.1
+    if encoded.len() > MAX_MESSAGE_SIZE {
+        warn!(
+            "Sending message larger than {} bytes to {}",
+            MAX_MESSAGE_SIZE, addr
+        );
+    }
 
     socket
-        .send((Bytes::from(message.to_bytes()), addr))
+        .send((encoded, addr))
         .await
         .expect("Should send message");
 
+    #[cfg(feature = "metrics")]
+    metrics::counter!("mdns_messages_sent_total").increment(1);
+
     Ok(())
 }
+
+#[test]
+fn test_send_target() {
+    assert_eq!(
+        SendTarget::Multicast.socket_addr(),
+        "224.0.0.251:5353".parse().unwrap()
+    );
+    assert_eq!(
+        SendTarget::UnicastV4(Ipv4Addr::new(192, 168, 1, 30)).socket_addr(),
+        "192.168.1.30:5353".parse().unwrap()
+    );
+    assert_eq!(
+        SendTarget::UnicastV6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)).socket_addr(),
+        "[fe80::1]:5353".parse().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_multicast_membership() {
+    let socket = create_socket(&SocketConfig::default()).unwrap();
+
+    //Joining a group twice fails, so the socket is already a member of the Mdns group
+    let error = socket2::SockRef::from(&socket)
+        .join_multicast_v4(&MDNS_IPV4_MULTICAST, &IP_ANY.into())
+        .unwrap_err();
+
+    assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+}
+
+#[tokio::test]
+async fn test_disable_multicast_loop() {
+    let socket = create_socket(&SocketConfig::default()).unwrap();
+    assert!(socket.multicast_loop_v4().unwrap());
+
+    let socket = create_socket(&SocketConfig {
+        disable_multicast_loop: true,
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(!socket.multicast_loop_v4().unwrap());
+}
+
+#[test]
+fn test_enumerate_network_interfaces() {
+    let interfaces = enumerate_network_interfaces();
+
+    let loopback = interfaces
+        .iter()
+        .find(|i| i.is_loopback)
+        .expect("Should contain the loopback interface");
+
+    assert!(!loopback.is_multicast);
+
+    if let Some((ip, _)) = loopback.ipv4 {
+        assert!(ip.is_loopback());
+    }
+
+    if let Some(primary) = primary_interface() {
+        assert!(!primary.is_loopback);
+        assert!(primary.ipv4.is_some());
+    }
+}
+
+#[test]
+fn test_source_address_check() {
+    assert!(is_link_local(&Ipv4Addr::new(169, 254, 1, 1)));
+    assert!(!is_link_local(&Ipv4Addr::new(192, 168, 1, 1)));
+
+    let link_local = IpAddr::V4(Ipv4Addr::new(169, 254, 1, 1));
+    assert!(source_address_check(&link_local));
+    assert!(source_address_check(&IpAddr::V4(Ipv4Addr::LOCALHOST)));
+
+    assert_eq!(subnet_for_interface("no such interface"), None);
+
+    //Refreshing replaces the cached interfaces
+    assert_eq!(refresh_network_interfaces(), enumerate_network_interfaces());
+
+    //Every interface address is on its own subnet
+    for interface in enumerate_network_interfaces() {
+        assert_eq!(subnet_for_interface(&interface.name), interface.ipv4);
+
+        if let Some((ip, _)) = interface.ipv4 {
+            assert!(source_address_check(&IpAddr::V4(ip)));
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_bind_to_interface() {
+    let loopback = enumerate_network_interfaces()
+        .into_iter()
+        .find(|i| i.is_loopback)
+        .expect("Should contain the loopback interface");
+
+    let socket = create_socket(&SocketConfig {
+        interface: Some(loopback.name.clone()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let device = socket2::SockRef::from(&socket).device().unwrap();
+    assert_eq!(device.as_deref(), Some(loopback.name.as_bytes()));
+
+    assert!(create_socket(&SocketConfig {
+        interface: Some("no such interface".into()),
+        ..Default::default()
+    })
+    .is_err());
+}
+
+#[test]
+fn test_mdns_multicast_ttl_check() {
+    assert!(mdns_multicast_ttl_check(255));
+    assert!((0..255).all(|ttl| !mdns_multicast_ttl_check(ttl)));
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_recv_with_ttl() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    set_recv_ttl(&receiver).unwrap();
+
+    let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let target = receiver.local_addr().unwrap();
+
+    for ttl in [255, 64] {
+        sender.set_ttl(ttl).unwrap();
+        sender.send_to(b"mdns", target).await.unwrap();
+
+        receiver.readable().await.unwrap();
+
+        let mut buffer = [0; 16];
+        let (length, addr, received_ttl) = recv_with_ttl(&receiver, &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..length], b"mdns");
+        assert_eq!(addr, sender.local_addr().unwrap());
+        assert_eq!(received_ttl, Some(ttl as u8));
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_ttl_checked_socket() {
+    use futures::StreamExt;
+
+    let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    set_recv_ttl(&receiver).unwrap();
+    let target = receiver.local_addr().unwrap();
+
+    let mut socket = TtlCheckedSocket::new(receiver);
+
+    let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    //Routed packets are discarded
+    sender.set_ttl(64).unwrap();
+    sender.send_to(b"routed", target).await.unwrap();
+    sender.set_ttl(255).unwrap();
+    sender.send_to(b"local", target).await.unwrap();
+
+    let (bytes, _) = socket.next().await.unwrap().unwrap();
+    assert_eq!(&bytes[..], b"local");
+
+    //Packets of our own sockets pass the check
+    let sender = create_socket(&SocketConfig::default()).unwrap();
+    assert_eq!(sender.multicast_ttl_v4().unwrap(), 255);
+
+    sender.send_to(b"mdns", target).await.unwrap();
+
+    let (bytes, _) = socket.next().await.unwrap().unwrap();
+    assert_eq!(&bytes[..], b"mdns");
+}
+
+#[test]
+fn test_lexicographic_later() {
+    use crate::name::Name;
+
+    let name = Name::new("TestMachine.local".into()).unwrap();
+    let a = ResourceRecord::create_a_record(name.clone(), [169, 254, 99, 200]);
+    let aaaa = ResourceRecord::create_aaaa_record(name.clone(), [0xfe80, 0, 0, 0, 0, 0, 0, 1]);
+
+    //The type is compared before the RDATA
+    assert_eq!(lexicographic_later(&aaaa, &a), Ordering::Greater);
+
+    //The cache flush bit is not part of the class
+    let mut flushed = a.clone();
+    flushed.cache_flush = !a.cache_flush;
+    assert_eq!(lexicographic_later(&a, &flushed), Ordering::Equal);
+
+    let later = ResourceRecord::create_a_record(name, [169, 254, 200, 50]);
+    assert_eq!(lexicographic_later(&a, &later), Ordering::Less);
+}
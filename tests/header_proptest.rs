@@ -0,0 +1,106 @@
+//! Property Tests
+//!
+//! Verifies that packing and unpacking round-trips for all combinations of header fields and names
+
+use dns_sd2::{
+    header::{Header, OpCode, RCode},
+    name::Name,
+};
+use packed_struct::prelude::*;
+use proptest::prelude::*;
+
+fn opcode() -> impl Strategy<Value = OpCode> {
+    prop_oneof![
+        Just(OpCode::StandardQuery),
+        Just(OpCode::InverseQuery),
+        Just(OpCode::ServerStatusRequest),
+    ]
+}
+
+fn rcode() -> impl Strategy<Value = RCode> {
+    prop_oneof![
+        Just(RCode::NoError),
+        Just(RCode::FormatError),
+        Just(RCode::ServerFailure),
+        Just(RCode::NameError),
+        Just(RCode::NotImplemented),
+        Just(RCode::Refused),
+    ]
+}
+
+prop_compose! {
+    fn header()(
+        id in any::<u16>(),
+        flags in any::<[bool; 5]>(),
+        opcode in opcode(),
+        z in 0u8..8,
+        rcode in rcode(),
+        counts in any::<[u16; 4]>(),
+    ) -> Header {
+        Header {
+            id,
+            qr: flags[0],
+            opcode,
+            aa: flags[1],
+            tc: flags[2],
+            rd: flags[3],
+            ra: flags[4],
+            z: z.into(),
+            rcode,
+            qdcount: counts[0],
+            ancount: counts[1],
+            nscount: counts[2],
+            arcount: counts[3],
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn header_round_trip(header in header()) {
+        let packed = header.pack().unwrap();
+
+        prop_assert_eq!(&Header::unpack(&packed).unwrap(), &header);
+        prop_assert_eq!(&Header::from_bytes(&packed).unwrap(), &header);
+    }
+
+    #[test]
+    fn name_round_trip(labels in prop::collection::vec("[a-zA-Z0-9_-]{1,60}", 1..4)) {
+        let name = Name::new(labels.join(".")).unwrap();
+        let bytes = name.to_bytes();
+
+        let (parsed, offset) = Name::parse(&bytes, 0).unwrap();
+
+        prop_assert_eq!(parsed.as_str(), name.as_str());
+        prop_assert_eq!(offset, bytes.len());
+    }
+}
+
+#[test]
+fn header_boundaries() {
+    //All counts at their maximum and all flags set
+    let header = Header {
+        id: u16::MAX,
+        qr: true,
+        opcode: OpCode::ServerStatusRequest,
+        aa: true,
+        tc: true,
+        rd: true,
+        ra: true,
+        z: 7.into(),
+        rcode: RCode::Refused,
+        qdcount: u16::MAX,
+        ancount: u16::MAX,
+        nscount: u16::MAX,
+        arcount: u16::MAX,
+    };
+
+    let packed = header.pack().unwrap();
+
+    //QR=1 OPCODE=0010 AA=1 TC=1 RD=1 | RA=1 Z=111 RCODE=0101
+    assert_eq!(
+        packed,
+        [0xFF, 0xFF, 0b1001_0111, 0b1111_0101, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+    );
+    assert_eq!(Header::unpack(&packed).unwrap(), header);
+}
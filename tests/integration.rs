@@ -0,0 +1,130 @@
+//! Integration Tests
+//!
+//! Runs two [`DnsSd2`] clients in the same process, one registering and one browsing,
+//! which talk to each other through the multicast group on the local machine
+//!
+//! These tests serve as the acceptance tests for the protocol features of this crate
+
+use dns_sd2::{
+    service::{Service, ServiceEvent},
+    DnsSd2, MdnsError,
+};
+use futures::{pin_mut, Stream, StreamExt};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    select,
+    time::{sleep, timeout},
+};
+
+/// Drive the registration stream until the browse stream finds a service with the given host
+async fn discover(
+    registration: impl Stream<Item = Result<Service, MdnsError>>,
+    browse: impl Stream<Item = Result<Service, MdnsError>>,
+    host: &str,
+) -> Option<Service> {
+    pin_mut!(registration);
+    pin_mut!(browse);
+
+    loop {
+        select! {
+            _ = registration.next() => {}
+            s = browse.next() => {
+                match s {
+                    Some(Ok(service)) if service.host == host => return Some(service),
+                    Some(_) => {}
+                    None => return None,
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_register_and_browse() {
+    let mut registrar = DnsSd2::default();
+    let mut browser = DnsSd2::default();
+
+    let registration = registrar
+        .register(
+            "TestMachine".into(),
+            "_test".into(),
+            "_tcp".into(),
+            53000,
+            vec!["key=value".into()],
+        )
+        .await;
+
+    let browse = browser.browse("_test._tcp.local".into()).await;
+
+    let service = timeout(
+        Duration::from_secs(5),
+        discover(registration, browse, "TestMachine"),
+    )
+    .await
+    .expect("Should discover the service within 5 seconds")
+    .expect("Browse stream should not end");
+
+    assert_eq!(service.host, "TestMachine");
+    assert_eq!(service.service, "_test");
+    assert_eq!(service.protocol, "_tcp");
+    assert_eq!(service.port, 53000);
+    assert_eq!(service.txt_records, vec!["key=value".to_string()]);
+}
+
+/// Wait until the accumulator of a callback contains at least one event
+async fn first_event(events: &Arc<Mutex<Vec<ServiceEvent>>>) -> ServiceEvent {
+    loop {
+        if let Some(event) = events.lock().unwrap().first() {
+            return event.clone();
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_on_service_found() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let accumulator = events.clone();
+
+    let handle = DnsSd2::default().on_service_found("_test._tcp.local".into(), move |event| {
+        accumulator.lock().unwrap().push(event)
+    });
+
+    let event = timeout(Duration::from_secs(5), first_event(&events))
+        .await
+        .expect("Should deliver an event within 5 seconds");
+
+    //Wait for the task to finish so the client is dropped while the runtime is running
+    handle.abort();
+    let _ = handle.await;
+
+    assert!(matches!(event, ServiceEvent::Found(_)));
+}
+
+#[tokio::test(flavor = "multi_thread", )]
+async fn test_on_registered() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let accumulator = events.clone();
+
+    let handle = DnsSd2::default().on_registered(
+        "TestMachine".into(),
+        "_test".into(),
+        "_tcp".into(),
+        53000,
+        vec![],
+        move |event| accumulator.lock().unwrap().push(event),
+    );
+
+    let event = timeout(Duration::from_secs(5), first_event(&events))
+        .await
+        .expect("Should deliver an event within 5 seconds");
+
+    //Wait for the task to finish so the client is dropped while the runtime is running
+    handle.abort();
+    let _ = handle.await;
+
+    assert!(matches!(event, ServiceEvent::Registered(_)));
+}
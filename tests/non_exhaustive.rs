@@ -0,0 +1,43 @@
+//! Non-exhaustive Enums
+//!
+//! Matches from outside the crate need a `_` arm, so variants can be added without breaking these matches
+
+use dns_sd2::{
+    protocols::handler::Event,
+    question::{QClass, QType},
+    service::ServiceState,
+    MdnsError,
+};
+
+#[test]
+fn test_non_exhaustive_match() {
+    let event = match Event::Ttl() {
+        Event::Ttl() => "ttl",
+        _ => "other",
+    };
+
+    let error = match (MdnsError::Closing {}) {
+        MdnsError::Closing {} => "closing",
+        _ => "other",
+    };
+
+    let state = match ServiceState::default() {
+        ServiceState::Prelude => "prelude",
+        _ => "other",
+    };
+
+    let qtype = match QType::Srv {
+        QType::A | QType::Aaaa => "address",
+        _ => "other",
+    };
+
+    let qclass = match QClass::In {
+        QClass::In => "internet",
+        _ => "other",
+    };
+
+    assert_eq!(
+        [event, error, state, qtype, qclass],
+        ["ttl", "closing", "prelude", "other", "internet"]
+    );
+}